@@ -0,0 +1,49 @@
+-- Schema as it was before migrations were tracked
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS ingredients (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    unit TEXT NOT NULL,
+    amount FLOAT NOT NULL,
+    calories_kcal FLOAT NOT NULL,
+    protein_g FLOAT NOT NULL,
+    carbohydrates_g FLOAT NOT NULL,
+    sugar_g FLOAT NOT NULL,
+    fat_g FLOAT NOT NULL,
+    saturated_fat_g FLOAT NOT NULL,
+    fiber_g FLOAT NOT NULL,
+    potassium_mg FLOAT NOT NULL,
+    sodium_mg FLOAT NOT NULL,
+    cholesterol_mg FLOAT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS recipes (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    notes TEXT,
+    instructions TEXT
+);
+
+CREATE TABLE IF NOT EXISTS recipes_tags (
+    id INTEGER PRIMARY KEY,
+    recipe_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    FOREIGN KEY (recipe_id) REFERENCES recipes(id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id),
+    CONSTRAINT unique_recipes_tags UNIQUE (recipe_id, tag_id)
+);
+
+CREATE TABLE IF NOT EXISTS recipes_ingredients (
+    id INTEGER PRIMARY KEY,
+    recipe_id INTEGER NOT NULL,
+    ingredient_id INTEGER NOT NULL,
+    amount FLOAT NOT NULL,
+    unit TEXT NOT NULL,
+    FOREIGN KEY (recipe_id) REFERENCES recipes(id),
+    FOREIGN KEY (ingredient_id) REFERENCES ingredients(id),
+    CONSTRAINT unique_recipes_ingredients UNIQUE (recipe_id, ingredient_id)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS unit_conversions (
+    id INTEGER PRIMARY KEY,
+    ingredient_id INTEGER NOT NULL,
+    from_unit TEXT NOT NULL,
+    to_unit TEXT NOT NULL,
+    conversion_factor FLOAT NOT NULL,
+    FOREIGN KEY (ingredient_id) REFERENCES ingredients(id),
+    CONSTRAINT unique_unit_conversions UNIQUE (ingredient_id, from_unit, to_unit)
+);
+
+ALTER TABLE ingredients ADD COLUMN grams_per_unit FLOAT;
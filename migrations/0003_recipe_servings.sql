@@ -0,0 +1,3 @@
+ALTER TABLE recipes ADD COLUMN servings FLOAT NOT NULL DEFAULT 1;
+
+ALTER TABLE recipes ADD COLUMN recipe_yield TEXT;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS meal_plans (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS meal_plan_entries (
+    id INTEGER PRIMARY KEY,
+    meal_plan_id INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    meal TEXT NOT NULL,
+    recipe_id INTEGER NOT NULL,
+    servings FLOAT NOT NULL DEFAULT 1,
+    FOREIGN KEY (meal_plan_id) REFERENCES meal_plans(id),
+    FOREIGN KEY (recipe_id) REFERENCES recipes(id)
+);
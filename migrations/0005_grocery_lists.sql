@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS grocery_lists (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    meal_plan_id INTEGER,
+    FOREIGN KEY (meal_plan_id) REFERENCES meal_plans(id)
+);
+
+CREATE TABLE IF NOT EXISTS grocery_list_items (
+    id INTEGER PRIMARY KEY,
+    grocery_list_id INTEGER NOT NULL,
+    ingredient_id INTEGER NOT NULL,
+    amount FLOAT NOT NULL,
+    unit TEXT NOT NULL,
+    checked BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY (grocery_list_id) REFERENCES grocery_lists(id),
+    FOREIGN KEY (ingredient_id) REFERENCES ingredients(id),
+    CONSTRAINT unique_grocery_list_items UNIQUE (grocery_list_id, ingredient_id, unit)
+);
@@ -0,0 +1,12 @@
+-- Content hash of each seed file as last applied, so unchanged files are skipped
+CREATE TABLE IF NOT EXISTS seed_files (
+    path TEXT PRIMARY KEY,
+    content_hash TEXT NOT NULL,
+    row_count INTEGER NOT NULL
+);
+
+-- 'seed' rows follow the data files, 'user' rows were created or edited
+-- through the API and are never overwritten. Existing rows came from seeding
+ALTER TABLE ingredients ADD COLUMN provenance TEXT NOT NULL DEFAULT 'seed';
+
+ALTER TABLE tags ADD COLUMN provenance TEXT NOT NULL DEFAULT 'seed';
@@ -0,0 +1,57 @@
+-- Instructions and notes used to be stored joined with ';', which broke any
+-- step that contained a semicolon. They are now one row each, in order, and a
+-- step can start a new section ("For the sauce")
+CREATE TABLE IF NOT EXISTS recipe_steps (
+    id INTEGER PRIMARY KEY,
+    recipe_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    section TEXT,
+    text TEXT NOT NULL,
+    FOREIGN KEY (recipe_id) REFERENCES recipes(id),
+    UNIQUE (recipe_id, position)
+);
+
+CREATE TABLE IF NOT EXISTS recipe_notes (
+    id INTEGER PRIMARY KEY,
+    recipe_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    FOREIGN KEY (recipe_id) REFERENCES recipes(id),
+    UNIQUE (recipe_id, position)
+);
+
+-- Split the old columns into rows. Empty parts are dropped, so positions may
+-- have gaps
+INSERT INTO recipe_steps (recipe_id, position, text)
+WITH RECURSIVE parts (recipe_id, position, part, rest) AS (
+    SELECT id, 0, NULL, instructions || ';'
+    FROM recipes
+    WHERE instructions IS NOT NULL
+    UNION ALL
+    SELECT recipe_id,
+           position + 1,
+           trim(substr(rest, 1, instr(rest, ';') - 1)),
+           substr(rest, instr(rest, ';') + 1)
+    FROM parts
+    WHERE rest != ''
+)
+SELECT recipe_id, position - 1, part FROM parts WHERE part != '';
+
+INSERT INTO recipe_notes (recipe_id, position, text)
+WITH RECURSIVE parts (recipe_id, position, part, rest) AS (
+    SELECT id, 0, NULL, notes || ';'
+    FROM recipes
+    WHERE notes IS NOT NULL
+    UNION ALL
+    SELECT recipe_id,
+           position + 1,
+           trim(substr(rest, 1, instr(rest, ';') - 1)),
+           substr(rest, instr(rest, ';') + 1)
+    FROM parts
+    WHERE rest != ''
+)
+SELECT recipe_id, position - 1, part FROM parts WHERE part != '';
+
+ALTER TABLE recipes DROP COLUMN notes;
+
+ALTER TABLE recipes DROP COLUMN instructions;
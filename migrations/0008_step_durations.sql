@@ -0,0 +1,5 @@
+-- Hands on and hands off minutes set on a step, when NULL they are parsed
+-- from the step text
+ALTER TABLE recipe_steps ADD COLUMN active_minutes FLOAT;
+
+ALTER TABLE recipe_steps ADD COLUMN passive_minutes FLOAT;
@@ -0,0 +1,34 @@
+-- One row per known nutrient value, a missing row means the value is unknown.
+-- Nutrients are identified by name, e.g. 'calories_kcal' or 'iron_mg', so
+-- new ones need no schema change
+CREATE TABLE IF NOT EXISTS ingredient_nutrients (
+    ingredient_id INTEGER NOT NULL,
+    nutrient TEXT NOT NULL,
+    amount FLOAT NOT NULL,
+    PRIMARY KEY (ingredient_id, nutrient),
+    FOREIGN KEY (ingredient_id) REFERENCES ingredients(id)
+);
+
+-- The old columns were NOT NULL, so every value is known
+INSERT OR IGNORE INTO ingredient_nutrients (ingredient_id, nutrient, amount)
+SELECT id, 'calories_kcal', calories_kcal FROM ingredients
+UNION ALL SELECT id, 'protein_g', protein_g FROM ingredients
+UNION ALL SELECT id, 'carbohydrates_g', carbohydrates_g FROM ingredients
+UNION ALL SELECT id, 'sugar_g', sugar_g FROM ingredients
+UNION ALL SELECT id, 'fat_g', fat_g FROM ingredients
+UNION ALL SELECT id, 'saturated_fat_g', saturated_fat_g FROM ingredients
+UNION ALL SELECT id, 'fiber_g', fiber_g FROM ingredients
+UNION ALL SELECT id, 'potassium_mg', potassium_mg FROM ingredients
+UNION ALL SELECT id, 'sodium_mg', sodium_mg FROM ingredients
+UNION ALL SELECT id, 'cholesterol_mg', cholesterol_mg FROM ingredients;
+
+ALTER TABLE ingredients DROP COLUMN calories_kcal;
+ALTER TABLE ingredients DROP COLUMN protein_g;
+ALTER TABLE ingredients DROP COLUMN carbohydrates_g;
+ALTER TABLE ingredients DROP COLUMN sugar_g;
+ALTER TABLE ingredients DROP COLUMN fat_g;
+ALTER TABLE ingredients DROP COLUMN saturated_fat_g;
+ALTER TABLE ingredients DROP COLUMN fiber_g;
+ALTER TABLE ingredients DROP COLUMN potassium_mg;
+ALTER TABLE ingredients DROP COLUMN sodium_mg;
+ALTER TABLE ingredients DROP COLUMN cholesterol_mg;
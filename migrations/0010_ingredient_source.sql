@@ -0,0 +1,7 @@
+-- Where an ingredient's nutrient data came from, 'usda_fdc' with the
+-- FoodData Central id for imported foods, NULL for hand-typed rows
+ALTER TABLE ingredients ADD COLUMN source TEXT;
+
+ALTER TABLE ingredients ADD COLUMN fdc_id INTEGER;
+
+CREATE UNIQUE INDEX IF NOT EXISTS unique_ingredients_fdc_id ON ingredients (fdc_id);
@@ -0,0 +1,536 @@
+use actix_web::{delete, get, patch, post, put, web, HttpResponse};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::crud::grocery_list::RecipeServings;
+use crate::crud::ingredient::Ingredient;
+use crate::crud::meal_plan::MealPlanEntry;
+use crate::crud::recipe::{RecipeInput, RecipePatch, RecipeSearch};
+use crate::crud::tag::Tag;
+use crate::error::{Error, Result};
+use crate::label::{LabelFormat, ReferenceDiet};
+use crate::planner::PlanRequest;
+use crate::{crud, ingredient_line, jsonld, label, nutrition, planner};
+
+// Register the /api routes, handlers expect a `web::Data<Pool<Sqlite>>` and
+// optionally a `web::Data<ReferenceDiet>` for nutrition labels
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    // malformed requests get the same JSON error body as everything else
+    cfg.app_data(
+        web::JsonConfig::default()
+            .error_handler(|err, _| Error::Validation(err.to_string()).into()),
+    )
+    .app_data(
+        web::QueryConfig::default()
+            .error_handler(|err, _| Error::Validation(err.to_string()).into()),
+    )
+    .app_data(
+        web::PathConfig::default().error_handler(|err, _| Error::NotFound(err.to_string()).into()),
+    )
+    // pasted recipe pages are often larger than the default 256 kB
+    .app_data(web::PayloadConfig::new(4 * 1024 * 1024))
+    .service(get_recipes)
+    // before get_recipe, otherwise "search" is parsed as an id
+    .service(search_recipes)
+    .service(get_recipe)
+    .service(create_recipe)
+    .service(import_recipe)
+    .service(update_recipe)
+    .service(patch_recipe)
+    .service(delete_recipe)
+    .service(get_recipe_nutrition)
+    .service(get_recipe_label)
+    .service(get_recipe_json_ld)
+    .service(get_ingredients)
+    .service(search_ingredients)
+    .service(parse_ingredient)
+    .service(create_ingredient)
+    .service(update_ingredient)
+    .service(delete_ingredient)
+    .service(get_tags)
+    .service(search_tags)
+    .service(create_tag)
+    .service(update_tag)
+    .service(delete_tag)
+    .service(get_meal_plans)
+    .service(create_meal_plan)
+    .service(generate_meal_plan)
+    .service(get_meal_plan)
+    .service(update_meal_plan)
+    .service(delete_meal_plan)
+    .service(create_meal_plan_entry)
+    .service(update_meal_plan_entry)
+    .service(delete_meal_plan_entry)
+    .service(get_grocery_lists)
+    .service(create_grocery_list)
+    .service(get_grocery_list)
+    .service(delete_grocery_list)
+    .service(update_grocery_list_item);
+}
+
+#[get("/api/recipes")]
+async fn get_recipes(pool: web::Data<Pool<Sqlite>>) -> Result<HttpResponse> {
+    let recipes = crud::recipe::read(&pool).await?;
+    Ok(HttpResponse::Ok().json(recipes))
+}
+
+// Ids are passed as comma separated lists, e.g. ?tags=1,2&tag_mode=all
+#[derive(Deserialize)]
+struct RecipeSearchQuery {
+    name: Option<String>,
+    tags: Option<String>,
+    tag_mode: Option<String>,
+    includes: Option<String>,
+    excludes: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+fn parse_ids(ids: &Option<String>) -> Result<Vec<i64>> {
+    match ids {
+        Some(ids) => ids
+            .split(',')
+            .filter(|id| !id.trim().is_empty())
+            .map(|id| {
+                id.trim().parse().map_err(|_| {
+                    Error::Validation("Ids must be comma separated integers".to_string())
+                })
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[get("/api/recipes/search")]
+async fn search_recipes(
+    pool: web::Data<Pool<Sqlite>>,
+    query: web::Query<RecipeSearchQuery>,
+) -> Result<HttpResponse> {
+    let match_all_tags = match query.tag_mode.as_deref() {
+        None | Some("any") => false,
+        Some("all") => true,
+        Some(mode) => {
+            return Err(Error::Validation(format!("Unknown tag_mode '{}'", mode)));
+        }
+    };
+    let search = RecipeSearch {
+        name: query.name.clone(),
+        tag_ids: parse_ids(&query.tags)?,
+        match_all_tags,
+        include_ingredient_ids: parse_ids(&query.includes)?,
+        exclude_ingredient_ids: parse_ids(&query.excludes)?,
+    };
+
+    let recipes = crud::recipe::search(&pool, &search).await?;
+    Ok(HttpResponse::Ok().json(recipes))
+}
+
+#[derive(Deserialize)]
+struct ServingsQuery {
+    servings: Option<f32>,
+}
+
+// ?scale=1.5 or ?servings=6, amounts come back in friendly units
+#[derive(Deserialize)]
+struct ScaleQuery {
+    scale: Option<f32>,
+    servings: Option<f32>,
+}
+
+#[get("/api/recipes/{id}")]
+async fn get_recipe(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    query: web::Query<ScaleQuery>,
+) -> Result<HttpResponse> {
+    let mut recipe = crud::recipe::read_one(&pool, path.into_inner()).await?;
+    match (query.scale, query.servings) {
+        (Some(_), Some(_)) => {
+            return Err(Error::Validation(
+                "Pass either scale or servings, not both".to_string(),
+            ))
+        }
+        (Some(scale), None) => recipe.scale(scale)?,
+        (None, Some(servings)) => recipe.scale_to_servings(servings)?,
+        (None, None) => {}
+    }
+    Ok(HttpResponse::Ok().json(recipe))
+}
+
+#[post("/api/recipes")]
+async fn create_recipe(
+    pool: web::Data<Pool<Sqlite>>,
+    body: web::Json<RecipeInput>,
+) -> Result<HttpResponse> {
+    let recipe_id = crud::recipe::create_full(&pool, &body).await?;
+    let recipe = crud::recipe::read_one(&pool, recipe_id).await?;
+    Ok(HttpResponse::Created().json(recipe))
+}
+
+// The body is a schema.org Recipe JSON-LD document or an HTML page with one.
+// Ingredient lines that match nothing in the catalog are returned in
+// `unmatched` and kept as notes
+#[post("/api/recipes/import")]
+async fn import_recipe(pool: web::Data<Pool<Sqlite>>, body: String) -> Result<HttpResponse> {
+    let imported = jsonld::import(&pool, &body).await?;
+    Ok(HttpResponse::Created().json(imported))
+}
+
+#[put("/api/recipes/{id}")]
+async fn update_recipe(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    body: web::Json<RecipeInput>,
+) -> Result<HttpResponse> {
+    let recipe_id = path.into_inner();
+    crud::recipe::update(&pool, recipe_id, &body).await?;
+    let recipe = crud::recipe::read_one(&pool, recipe_id).await?;
+    Ok(HttpResponse::Ok().json(recipe))
+}
+
+#[patch("/api/recipes/{id}")]
+async fn patch_recipe(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    body: web::Json<RecipePatch>,
+) -> Result<HttpResponse> {
+    let recipe_id = path.into_inner();
+    crud::recipe::patch(&pool, recipe_id, body.into_inner()).await?;
+    let recipe = crud::recipe::read_one(&pool, recipe_id).await?;
+    Ok(HttpResponse::Ok().json(recipe))
+}
+
+#[delete("/api/recipes/{id}")]
+async fn delete_recipe(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    query: web::Query<DeleteQuery>,
+) -> Result<HttpResponse> {
+    crud::recipe::delete(&pool, path.into_inner(), query.cascade).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/api/recipes/{id}/nutrition")]
+async fn get_recipe_nutrition(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    query: web::Query<ServingsQuery>,
+) -> Result<HttpResponse> {
+    let nutrition =
+        nutrition::read_recipe_nutrition(&pool, path.into_inner(), query.servings).await?;
+    Ok(HttpResponse::Ok().json(nutrition))
+}
+
+// ?format=html|text|json, ?calories= overrides the configured reference diet
+#[derive(Deserialize)]
+struct LabelQuery {
+    #[serde(default)]
+    format: LabelFormat,
+    calories: Option<f32>,
+}
+
+#[get("/api/recipes/{id}/label")]
+async fn get_recipe_label(
+    pool: web::Data<Pool<Sqlite>>,
+    diet: Option<web::Data<ReferenceDiet>>,
+    path: web::Path<i64>,
+    query: web::Query<LabelQuery>,
+) -> Result<HttpResponse> {
+    let diet = match (query.calories, diet) {
+        (Some(calories), _) => ReferenceDiet::for_calories(calories)?,
+        (None, Some(diet)) => diet.get_ref().clone(),
+        (None, None) => ReferenceDiet::default(),
+    };
+    let label = label::read_label(&pool, path.into_inner(), &diet).await?;
+    Ok(match query.format {
+        LabelFormat::Html => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(label.render_html()),
+        LabelFormat::Text => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(label.render_text()),
+        LabelFormat::Json => HttpResponse::Ok().json(label),
+    })
+}
+
+#[get("/api/recipes/{id}/jsonld")]
+async fn get_recipe_json_ld(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse> {
+    let recipe = crud::recipe::read_one(&pool, path.into_inner()).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/ld+json")
+        .json(jsonld::export(&recipe)))
+}
+
+#[get("/api/ingredients")]
+async fn get_ingredients(pool: web::Data<Pool<Sqlite>>) -> Result<HttpResponse> {
+    let ingredients = crud::ingredient::read(&pool).await?;
+    Ok(HttpResponse::Ok().json(ingredients))
+}
+
+#[get("/api/ingredients/search")]
+async fn search_ingredients(
+    pool: web::Data<Pool<Sqlite>>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse> {
+    let ingredients = crud::ingredient::read_by_search_string(&pool, &query.q).await?;
+    Ok(HttpResponse::Ok().json(ingredients))
+}
+
+// ?q=2 cups chopped onion, the line split into amount, unit, name and note
+// with the catalog ingredients it may refer to
+#[get("/api/ingredients/parse")]
+async fn parse_ingredient(
+    pool: web::Data<Pool<Sqlite>>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse> {
+    let parsed = ingredient_line::parse_and_match(&pool, &query.q).await?;
+    Ok(HttpResponse::Ok().json(parsed))
+}
+
+#[post("/api/ingredients")]
+async fn create_ingredient(
+    pool: web::Data<Pool<Sqlite>>,
+    body: web::Json<Ingredient>,
+) -> Result<HttpResponse> {
+    let ingredient_id = crud::ingredient::create(&pool, &body).await?;
+    let ingredient = crud::ingredient::read_one(&pool, ingredient_id).await?;
+    Ok(HttpResponse::Created().json(ingredient))
+}
+
+#[put("/api/ingredients/{id}")]
+async fn update_ingredient(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    body: web::Json<Ingredient>,
+) -> Result<HttpResponse> {
+    let ingredient_id = path.into_inner();
+    crud::ingredient::update(&pool, ingredient_id, &body).await?;
+    let ingredient = crud::ingredient::read_one(&pool, ingredient_id).await?;
+    Ok(HttpResponse::Ok().json(ingredient))
+}
+
+#[delete("/api/ingredients/{id}")]
+async fn delete_ingredient(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    query: web::Query<DeleteQuery>,
+) -> Result<HttpResponse> {
+    crud::ingredient::delete(&pool, path.into_inner(), query.cascade).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/api/tags")]
+async fn get_tags(pool: web::Data<Pool<Sqlite>>) -> Result<HttpResponse> {
+    let tags = crud::tag::read(&pool).await?;
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+#[get("/api/tags/search")]
+async fn search_tags(
+    pool: web::Data<Pool<Sqlite>>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse> {
+    let tags = crud::tag::read_by_search_string(&pool, &query.q).await?;
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+#[post("/api/tags")]
+async fn create_tag(pool: web::Data<Pool<Sqlite>>, body: web::Json<Tag>) -> Result<HttpResponse> {
+    let tag_id = crud::tag::create(&pool, &body).await?;
+    let tag = crud::tag::read_one(&pool, tag_id).await?;
+    Ok(HttpResponse::Created().json(tag))
+}
+
+#[put("/api/tags/{id}")]
+async fn update_tag(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    body: web::Json<Tag>,
+) -> Result<HttpResponse> {
+    let tag_id = path.into_inner();
+    crud::tag::update(&pool, tag_id, &body).await?;
+    let tag = crud::tag::read_one(&pool, tag_id).await?;
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+#[delete("/api/tags/{id}")]
+async fn delete_tag(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    query: web::Query<DeleteQuery>,
+) -> Result<HttpResponse> {
+    crud::tag::delete(&pool, path.into_inner(), query.cascade).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    #[serde(default)]
+    cascade: bool,
+}
+
+#[derive(Deserialize)]
+struct MealPlanBody {
+    name: String,
+}
+
+#[get("/api/meal-plans")]
+async fn get_meal_plans(pool: web::Data<Pool<Sqlite>>) -> Result<HttpResponse> {
+    let meal_plans = crud::meal_plan::read(&pool).await?;
+    Ok(HttpResponse::Ok().json(meal_plans))
+}
+
+#[post("/api/meal-plans")]
+async fn create_meal_plan(
+    pool: web::Data<Pool<Sqlite>>,
+    body: web::Json<MealPlanBody>,
+) -> Result<HttpResponse> {
+    let meal_plan_id = crud::meal_plan::create(&pool, &body.name).await?;
+    let meal_plan = crud::meal_plan::read_one(&pool, meal_plan_id).await?;
+    Ok(HttpResponse::Created().json(meal_plan))
+}
+
+// Plan meals from the recipe library to hit daily nutrition targets
+#[post("/api/meal-plans/generate")]
+async fn generate_meal_plan(
+    pool: web::Data<Pool<Sqlite>>,
+    body: web::Json<PlanRequest>,
+) -> Result<HttpResponse> {
+    let generated = planner::create(&pool, &body).await?;
+    Ok(HttpResponse::Created().json(generated))
+}
+
+#[get("/api/meal-plans/{id}")]
+async fn get_meal_plan(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse> {
+    let meal_plan = crud::meal_plan::read_one(&pool, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(meal_plan))
+}
+
+#[put("/api/meal-plans/{id}")]
+async fn update_meal_plan(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    body: web::Json<MealPlanBody>,
+) -> Result<HttpResponse> {
+    let meal_plan_id = path.into_inner();
+    crud::meal_plan::update(&pool, meal_plan_id, &body.name).await?;
+    let meal_plan = crud::meal_plan::read_one(&pool, meal_plan_id).await?;
+    Ok(HttpResponse::Ok().json(meal_plan))
+}
+
+#[delete("/api/meal-plans/{id}")]
+async fn delete_meal_plan(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse> {
+    crud::meal_plan::delete(&pool, path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/api/meal-plans/{id}/entries")]
+async fn create_meal_plan_entry(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+    body: web::Json<MealPlanEntry>,
+) -> Result<HttpResponse> {
+    let meal_plan_id = path.into_inner();
+    crud::meal_plan::add_entry(&pool, meal_plan_id, &body).await?;
+    let meal_plan = crud::meal_plan::read_one(&pool, meal_plan_id).await?;
+    Ok(HttpResponse::Created().json(meal_plan))
+}
+
+#[put("/api/meal-plans/{id}/entries/{entry_id}")]
+async fn update_meal_plan_entry(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<(i64, i64)>,
+    body: web::Json<MealPlanEntry>,
+) -> Result<HttpResponse> {
+    let (meal_plan_id, entry_id) = path.into_inner();
+    crud::meal_plan::update_entry(&pool, meal_plan_id, entry_id, &body).await?;
+    let meal_plan = crud::meal_plan::read_one(&pool, meal_plan_id).await?;
+    Ok(HttpResponse::Ok().json(meal_plan))
+}
+
+#[delete("/api/meal-plans/{id}/entries/{entry_id}")]
+async fn delete_meal_plan_entry(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<(i64, i64)>,
+) -> Result<HttpResponse> {
+    let (meal_plan_id, entry_id) = path.into_inner();
+    crud::meal_plan::delete_entry(&pool, meal_plan_id, entry_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Either a meal plan or an explicit list of recipes to shop for
+#[derive(Deserialize)]
+struct GroceryListBody {
+    name: String,
+    meal_plan_id: Option<i64>,
+    #[serde(default)]
+    recipes: Vec<RecipeServings>,
+}
+
+#[derive(Deserialize)]
+struct GroceryListItemBody {
+    checked: bool,
+}
+
+#[get("/api/grocery-lists")]
+async fn get_grocery_lists(pool: web::Data<Pool<Sqlite>>) -> Result<HttpResponse> {
+    let grocery_lists = crud::grocery_list::read(&pool).await?;
+    Ok(HttpResponse::Ok().json(grocery_lists))
+}
+
+#[post("/api/grocery-lists")]
+async fn create_grocery_list(
+    pool: web::Data<Pool<Sqlite>>,
+    body: web::Json<GroceryListBody>,
+) -> Result<HttpResponse> {
+    let grocery_list_id = match body.meal_plan_id {
+        Some(meal_plan_id) => {
+            crud::grocery_list::create_from_meal_plan(&pool, &body.name, meal_plan_id).await
+        }
+        None => crud::grocery_list::create(&pool, &body.name, None, &body.recipes).await,
+    }?;
+    let grocery_list = crud::grocery_list::read_one(&pool, grocery_list_id).await?;
+    Ok(HttpResponse::Created().json(grocery_list))
+}
+
+#[get("/api/grocery-lists/{id}")]
+async fn get_grocery_list(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse> {
+    let grocery_list = crud::grocery_list::read_one(&pool, path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(grocery_list))
+}
+
+#[delete("/api/grocery-lists/{id}")]
+async fn delete_grocery_list(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<i64>,
+) -> Result<HttpResponse> {
+    crud::grocery_list::delete(&pool, path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[patch("/api/grocery-lists/{id}/items/{item_id}")]
+async fn update_grocery_list_item(
+    pool: web::Data<Pool<Sqlite>>,
+    path: web::Path<(i64, i64)>,
+    body: web::Json<GroceryListItemBody>,
+) -> Result<HttpResponse> {
+    let (grocery_list_id, item_id) = path.into_inner();
+    crud::grocery_list::set_checked(&pool, grocery_list_id, item_id, body.checked).await?;
+    let grocery_list = crud::grocery_list::read_one(&pool, grocery_list_id).await?;
+    Ok(HttpResponse::Ok().json(grocery_list))
+}
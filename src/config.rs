@@ -0,0 +1,278 @@
+use log::LevelFilter;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::crud::PoolConfig;
+use crate::error::{Error, Result};
+use crate::label::DEFAULT_REFERENCE_CALORIES;
+
+pub const DEFAULT_DATABASE: &str = "foodbuddy.db";
+pub const DEFAULT_DATA_DIR: &str = "data";
+pub const DEFAULT_STATIC_DIR: &str = "web-ui/build";
+pub const DEFAULT_BIND: &str = "127.0.0.1:8000";
+pub const DEFAULT_CONFIG_FILE: &str = "meal-planner.toml";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub database: PathBuf,
+    pub data_dir: PathBuf,
+    pub static_dir: PathBuf,
+    pub bind: String,
+    pub log_level: LevelFilter,
+    pub pool: PoolConfig,
+    // calories of the reference diet for % Daily Values on nutrition labels
+    pub reference_calories: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            database: PathBuf::from(DEFAULT_DATABASE),
+            data_dir: PathBuf::from(DEFAULT_DATA_DIR),
+            static_dir: PathBuf::from(DEFAULT_STATIC_DIR),
+            bind: DEFAULT_BIND.to_string(),
+            log_level: LevelFilter::Info,
+            pool: PoolConfig::default(),
+            reference_calories: DEFAULT_REFERENCE_CALORIES,
+        }
+    }
+}
+
+// One source of settings, anything left as None falls through to the
+// layer below. Also the format of the TOML file
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub database: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    pub static_dir: Option<PathBuf>,
+    pub bind: Option<String>,
+    pub log_level: Option<String>,
+    pub pool_size: Option<u32>,
+    pub busy_timeout_ms: Option<u64>,
+    pub wal: Option<bool>,
+    pub foreign_keys: Option<bool>,
+    pub reference_calories: Option<f32>,
+}
+
+impl ConfigLayer {
+    // Relative paths in the file are relative to the file, not to the
+    // working directory
+    pub fn from_file(path: &Path) -> Result<ConfigLayer> {
+        let content = fs::read_to_string(path)?;
+        let mut layer: ConfigLayer = toml::from_str(&content)
+            .map_err(|err| Error::Validation(format!("{}: {}", path.display(), err)))?;
+
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for dir in [
+            &mut layer.database,
+            &mut layer.data_dir,
+            &mut layer.static_dir,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if dir.is_relative() {
+                *dir = base.join(&*dir);
+            }
+        }
+
+        Ok(layer)
+    }
+
+    // MEAL_PLANNER_DATABASE, MEAL_PLANNER_DATA_DIR, MEAL_PLANNER_BIND etc.
+    pub fn from_env() -> Result<ConfigLayer> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<ConfigLayer> {
+        let path = |name: &str| var(name).map(PathBuf::from);
+        Ok(ConfigLayer {
+            database: path("MEAL_PLANNER_DATABASE"),
+            data_dir: path("MEAL_PLANNER_DATA_DIR"),
+            static_dir: path("MEAL_PLANNER_STATIC_DIR"),
+            bind: var("MEAL_PLANNER_BIND"),
+            log_level: var("MEAL_PLANNER_LOG_LEVEL"),
+            pool_size: parse_var(&var, "MEAL_PLANNER_POOL_SIZE")?,
+            busy_timeout_ms: parse_var(&var, "MEAL_PLANNER_BUSY_TIMEOUT_MS")?,
+            wal: parse_var(&var, "MEAL_PLANNER_WAL")?,
+            foreign_keys: parse_var(&var, "MEAL_PLANNER_FOREIGN_KEYS")?,
+            reference_calories: parse_var(&var, "MEAL_PLANNER_REFERENCE_CALORIES")?,
+        })
+    }
+}
+
+fn parse_var<T: FromStr>(var: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>> {
+    match var(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Validation(format!("Invalid value for {}: '{}'", name, value))),
+        None => Ok(None),
+    }
+}
+
+impl Config {
+    // Defaults, then the config file, then MEAL_PLANNER_* environment
+    // variables, then `cli`. The file is `config_file` if given, else
+    // MEAL_PLANNER_CONFIG, else meal-planner.toml if it exists
+    pub fn load(config_file: Option<&Path>, cli: ConfigLayer) -> Result<Config> {
+        let config_file = config_file
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os("MEAL_PLANNER_CONFIG").map(PathBuf::from));
+        let file = match config_file {
+            Some(path) => ConfigLayer::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                ConfigLayer::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => ConfigLayer::default(),
+        };
+
+        Config::default()
+            .apply(file)?
+            .apply(ConfigLayer::from_env()?)?
+            .apply(cli)
+    }
+
+    pub fn apply(mut self, layer: ConfigLayer) -> Result<Config> {
+        if let Some(database) = layer.database {
+            self.database = database;
+        }
+        if let Some(data_dir) = layer.data_dir {
+            self.data_dir = data_dir;
+        }
+        if let Some(static_dir) = layer.static_dir {
+            self.static_dir = static_dir;
+        }
+        if let Some(bind) = layer.bind {
+            self.bind = bind;
+        }
+        if let Some(log_level) = layer.log_level {
+            self.log_level = log_level
+                .parse()
+                .map_err(|_| Error::Validation(format!("Invalid log level '{}'", log_level)))?;
+        }
+        if let Some(pool_size) = layer.pool_size {
+            self.pool.max_connections = pool_size;
+        }
+        if let Some(busy_timeout_ms) = layer.busy_timeout_ms {
+            self.pool.busy_timeout = Duration::from_millis(busy_timeout_ms);
+        }
+        if let Some(wal) = layer.wal {
+            self.pool.wal = wal;
+        }
+        if let Some(foreign_keys) = layer.foreign_keys {
+            self.pool.foreign_keys = foreign_keys;
+        }
+        if let Some(reference_calories) = layer.reference_calories {
+            if reference_calories <= 0.0 || !reference_calories.is_finite() {
+                return Err(Error::Validation(format!(
+                    "Invalid reference calories {}",
+                    reference_calories
+                )));
+            }
+            self.reference_calories = reference_calories;
+        }
+
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::*;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_layers() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("meal-planner.toml");
+        fs::write(
+            &path,
+            r#"
+            database = "db/food.db"
+            data_dir = "/srv/meal-planner/data"
+            bind = "0.0.0.0:8080"
+            log_level = "debug"
+            pool_size = 2
+            reference_calories = 2500
+            "#,
+        )
+        .unwrap();
+        let file = ConfigLayer::from_file(&path).unwrap();
+
+        let vars: HashMap<&str, &str> = [
+            ("MEAL_PLANNER_BIND", "0.0.0.0:9000"),
+            ("MEAL_PLANNER_WAL", "false"),
+        ]
+        .into_iter()
+        .collect();
+        let env = ConfigLayer::from_vars(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+
+        let cli = ConfigLayer {
+            log_level: Some("warn".to_string()),
+            ..Default::default()
+        };
+
+        let config = Config::default()
+            .apply(file)
+            .unwrap()
+            .apply(env)
+            .unwrap()
+            .apply(cli)
+            .unwrap();
+        // relative to the config file
+        assert_eq!(config.database, dir.path().join("db/food.db"));
+        assert_eq!(config.data_dir, PathBuf::from("/srv/meal-planner/data"));
+        assert_eq!(config.static_dir, PathBuf::from(DEFAULT_STATIC_DIR));
+        assert_eq!(config.bind, "0.0.0.0:9000");
+        assert_eq!(config.log_level, LevelFilter::Warn);
+        assert_eq!(config.pool.max_connections, 2);
+        assert!(!config.pool.wal);
+        assert!(config.pool.foreign_keys);
+        assert_eq!(config.reference_calories, 2500.0);
+    }
+
+    #[test]
+    fn test_example_file() {
+        let layer = ConfigLayer::from_file(Path::new("meal-planner.example.toml")).unwrap();
+        let config = Config::default().apply(layer).unwrap();
+        assert_eq!(config.bind, DEFAULT_BIND);
+        assert_eq!(config.database, PathBuf::from(DEFAULT_DATABASE));
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("meal-planner.toml");
+        fs::write(&path, "databse = \"typo.db\"").unwrap();
+        let err = ConfigLayer::from_file(&path).unwrap_err();
+        assert!(err.to_string().contains("databse"));
+
+        let err = ConfigLayer::from_vars(|name| {
+            (name == "MEAL_PLANNER_POOL_SIZE").then(|| "lots".to_string())
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for MEAL_PLANNER_POOL_SIZE: 'lots'"
+        );
+
+        let layer = ConfigLayer {
+            log_level: Some("loud".to_string()),
+            ..Default::default()
+        };
+        assert!(Config::default().apply(layer).is_err());
+
+        let layer = ConfigLayer {
+            reference_calories: Some(0.0),
+            ..Default::default()
+        };
+        assert!(Config::default().apply(layer).is_err());
+    }
+}
@@ -0,0 +1,447 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, FromRow, Pool, Row, Sqlite};
+use std::collections::HashMap;
+
+use super::meal_plan;
+use super::recipe::{self, Recipe};
+use super::unit_conversion::{self, UnitConverter};
+use crate::error::{Error, Result};
+use crate::unit::Unit;
+
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct GroceryListItem {
+    pub id: Option<i64>,
+    pub ingredient_id: i64,
+    pub ingredient_name: String,
+    pub amount: f32,
+    pub unit: Unit,
+    pub checked: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroceryList {
+    pub id: Option<i64>,
+    pub name: String,
+    pub meal_plan_id: Option<i64>,
+    pub items: Vec<GroceryListItem>,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug)]
+pub struct GroceryListSimple {
+    pub id: Option<i64>,
+    pub name: String,
+}
+
+// A recipe to shop for and how many servings of it will be made
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct RecipeServings {
+    pub recipe_id: i64,
+    pub servings: f32,
+}
+
+// Sum the ingredient lines of all recipes into one line per ingredient.
+// Amounts are converted to the ingredient's reference unit when possible,
+// lines that can't be converted keep their own unit as a separate item.
+pub fn aggregate(
+    recipes: &[Recipe],
+    converters: &HashMap<i64, UnitConverter>,
+) -> Vec<GroceryListItem> {
+    let default_converter = UnitConverter::default();
+    let mut items: Vec<GroceryListItem> = Vec::new();
+
+    for recipe in recipes {
+        for line in &recipe.ingredients {
+            let ingredient_id = match line.ingredient.id {
+                Some(id) => id,
+                None => continue,
+            };
+            let converter = converters.get(&ingredient_id).unwrap_or(&default_converter);
+
+            // try the reference unit first, then any unit already on the list
+            let mut targets = vec![line.ingredient.unit];
+            targets.extend(
+                items
+                    .iter()
+                    .filter(|item| item.ingredient_id == ingredient_id)
+                    .map(|item| item.unit),
+            );
+            let converted = targets.into_iter().find_map(|unit| {
+                converter
+                    .convert(line.amount, line.unit, unit)
+                    .map(|amount| (amount, unit))
+            });
+            let (amount, unit) = converted.unwrap_or((line.amount, line.unit));
+
+            match items
+                .iter_mut()
+                .find(|item| item.ingredient_id == ingredient_id && item.unit == unit)
+            {
+                Some(item) => item.amount += amount,
+                None => items.push(GroceryListItem {
+                    id: None,
+                    ingredient_id,
+                    ingredient_name: line.ingredient.name.clone(),
+                    amount,
+                    unit,
+                    checked: false,
+                }),
+            }
+        }
+    }
+
+    items.sort_by(|a, b| a.ingredient_name.cmp(&b.ingredient_name));
+    items
+}
+
+// Read and rescale every recipe, then aggregate their ingredients
+pub async fn build_items(
+    pool: &Pool<Sqlite>,
+    recipes: &[RecipeServings],
+) -> Result<Vec<GroceryListItem>> {
+    let mut scaled_recipes = Vec::new();
+    let mut converters = HashMap::new();
+    for recipe_servings in recipes {
+        let mut recipe = recipe::read_one(pool, recipe_servings.recipe_id).await?;
+        recipe.rescale(recipe_servings.servings);
+        converters.extend(unit_conversion::read_recipe_converters(pool, &recipe).await?);
+        scaled_recipes.push(recipe);
+    }
+
+    Ok(aggregate(&scaled_recipes, &converters))
+}
+
+pub async fn create(
+    pool: &Pool<Sqlite>,
+    grocery_list_name: &str,
+    meal_plan_id: Option<i64>,
+    recipes: &[RecipeServings],
+) -> Result<i64> {
+    let items = build_items(pool, recipes).await?;
+
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+        "INSERT INTO grocery_lists (
+            name,
+            meal_plan_id
+        ) VALUES (?, ?)",
+    )
+    .bind(grocery_list_name)
+    .bind(meal_plan_id)
+    .execute(&mut tx)
+    .await?;
+    let grocery_list_id = result.last_insert_rowid();
+
+    for item in items {
+        sqlx::query(
+            "INSERT INTO grocery_list_items (
+                grocery_list_id,
+                ingredient_id,
+                amount,
+                unit,
+                checked
+            ) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(grocery_list_id)
+        .bind(item.ingredient_id)
+        .bind(item.amount)
+        .bind(item.unit)
+        .bind(item.checked)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(grocery_list_id)
+}
+
+pub async fn create_from_meal_plan(
+    pool: &Pool<Sqlite>,
+    grocery_list_name: &str,
+    meal_plan_id: i64,
+) -> Result<i64> {
+    // read_one so a missing plan is NotFound instead of an empty list
+    let entries = meal_plan::read_one(pool, meal_plan_id).await?.entries;
+    let recipes: Vec<RecipeServings> = entries
+        .iter()
+        .map(|entry| RecipeServings {
+            recipe_id: entry.recipe_id,
+            servings: entry.servings,
+        })
+        .collect();
+
+    create(pool, grocery_list_name, Some(meal_plan_id), &recipes).await
+}
+
+pub async fn read(pool: &Pool<Sqlite>) -> Result<Vec<GroceryListSimple>> {
+    let grocery_lists =
+        sqlx::query_as::<_, GroceryListSimple>(r#"SELECT id, name FROM grocery_lists"#)
+            .fetch_all(pool)
+            .await?;
+
+    Ok(grocery_lists)
+}
+
+// Lists with at least one item for the ingredient
+pub async fn read_by_ingredient_id<'c, E: Executor<'c, Database = Sqlite>>(
+    executor: E,
+    ingredient_id: i64,
+) -> Result<Vec<GroceryListSimple>> {
+    let grocery_lists = sqlx::query_as::<_, GroceryListSimple>(
+        r#"SELECT DISTINCT grocery_lists.id, grocery_lists.name
+           FROM grocery_lists
+           JOIN grocery_list_items ON grocery_lists.id = grocery_list_items.grocery_list_id
+           WHERE grocery_list_items.ingredient_id = ?"#,
+    )
+    .bind(ingredient_id)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(grocery_lists)
+}
+
+pub async fn read_one(pool: &Pool<Sqlite>, grocery_list_id: i64) -> Result<GroceryList> {
+    let row = sqlx::query(
+        r#"SELECT id, name, meal_plan_id
+           FROM grocery_lists
+           WHERE id = ?"#,
+    )
+    .bind(grocery_list_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("Grocery list {} not found", grocery_list_id)))?;
+
+    let items = sqlx::query_as::<_, GroceryListItem>(
+        r#"SELECT grocery_list_items.id,
+                  grocery_list_items.ingredient_id,
+                  ingredients.name AS ingredient_name,
+                  grocery_list_items.amount,
+                  grocery_list_items.unit,
+                  grocery_list_items.checked
+           FROM grocery_list_items
+           JOIN ingredients ON grocery_list_items.ingredient_id = ingredients.id
+           WHERE grocery_list_items.grocery_list_id = ?
+           ORDER BY ingredients.name"#,
+    )
+    .bind(grocery_list_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(GroceryList {
+        id: Some(row.get(0)),
+        name: row.get(1),
+        meal_plan_id: row.get(2),
+        items,
+    })
+}
+
+pub async fn set_checked(
+    pool: &Pool<Sqlite>,
+    grocery_list_id: i64,
+    item_id: i64,
+    checked: bool,
+) -> Result<()> {
+    let result = sqlx::query(
+        "UPDATE grocery_list_items SET checked = ? WHERE id = ? AND grocery_list_id = ?",
+    )
+    .bind(checked)
+    .bind(item_id)
+    .bind(grocery_list_id)
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "Grocery list item {} not found in grocery list {}",
+            item_id, grocery_list_id
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn delete(pool: &Pool<Sqlite>, grocery_list_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM grocery_list_items WHERE grocery_list_id = ?")
+        .bind(grocery_list_id)
+        .execute(&mut tx)
+        .await?;
+    let result = sqlx::query("DELETE FROM grocery_lists WHERE id = ?")
+        .bind(grocery_list_id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "Grocery list {} not found",
+            grocery_list_id
+        )));
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::meal_plan::{MealPlanEntry, MealSlot};
+    use crate::crud::{self, get_connection_pool, grocery_list::*, ingredient};
+    use tempfile::NamedTempFile;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    async fn ingredient_id(pool: &Pool<Sqlite>, name: &str) -> i64 {
+        ingredient::read(pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == name)
+            .unwrap()
+            .id
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_from_meal_plan() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+        let butter = ingredient_id(&pool, "butter").await;
+        let garlic = ingredient_id(&pool, "garlic").await;
+        let bay_leaves = ingredient_id(&pool, "bay leaves").await;
+
+        // serves 2
+        let pasta = recipe::create(&pool, "garlic pasta").await.unwrap();
+        recipe::update_servings(&pool, pasta, 2.0, None)
+            .await
+            .unwrap();
+        recipe::add_recipe_ingredient(&pool, pasta, butter, 2.0, "Tbsp")
+            .await
+            .unwrap();
+        recipe::add_recipe_ingredient(&pool, pasta, garlic, 2.0, "clove")
+            .await
+            .unwrap();
+
+        // serves 1
+        let soup = recipe::create(&pool, "garlic soup").await.unwrap();
+        recipe::add_recipe_ingredient(&pool, soup, butter, 3.0, "tsp")
+            .await
+            .unwrap();
+        recipe::add_recipe_ingredient(&pool, soup, garlic, 6.0, "g")
+            .await
+            .unwrap();
+        recipe::add_recipe_ingredient(&pool, soup, bay_leaves, 1.0, "cup")
+            .await
+            .unwrap();
+
+        let meal_plan_id = meal_plan::create(&pool, "week").await.unwrap();
+        for (date, recipe_id, servings) in [
+            ("2023-03-06", pasta, 4.0),
+            ("2023-03-07", soup, 1.0),
+            ("2023-03-08", soup, 1.0),
+        ] {
+            let entry = MealPlanEntry {
+                id: None,
+                date: date.to_string(),
+                meal: MealSlot::Dinner,
+                recipe_id,
+                recipe_name: None,
+                servings,
+            };
+            meal_plan::add_entry(&pool, meal_plan_id, &entry)
+                .await
+                .unwrap();
+        }
+
+        let grocery_list_id = create_from_meal_plan(&pool, "groceries", meal_plan_id)
+            .await
+            .unwrap();
+        let grocery_list = read_one(&pool, grocery_list_id).await.unwrap();
+        assert_eq!(grocery_list.meal_plan_id, Some(meal_plan_id));
+        assert_eq!(grocery_list.items.len(), 3);
+
+        // 4 Tbsp + 2 * 1 Tbsp, all in the reference unit
+        let butter_item = &grocery_list.items[1];
+        assert_eq!(butter_item.ingredient_name, "butter");
+        assert_eq!(butter_item.unit, Unit::Tablespoon);
+        assert_close(butter_item.amount, 6.0);
+
+        // 4 cloves + 2 * 2 cloves
+        let garlic_item = &grocery_list.items[2];
+        assert_eq!(garlic_item.unit, Unit::Clove);
+        assert_close(garlic_item.amount, 8.0);
+
+        // no way to turn a cup into leaves, so it keeps its own unit
+        let bay_leaves_item = &grocery_list.items[0];
+        assert_eq!(bay_leaves_item.unit, Unit::Cup);
+        assert_close(bay_leaves_item.amount, 2.0);
+
+        set_checked(&pool, grocery_list_id, butter_item.id.unwrap(), true)
+            .await
+            .unwrap();
+        let grocery_list = read_one(&pool, grocery_list_id).await.unwrap();
+        assert!(grocery_list.items[1].checked);
+        assert!(!grocery_list.items[2].checked);
+
+        // items can't be checked off through another list
+        let other_list_id = create(&pool, "other", None, &[]).await.unwrap();
+        assert!(matches!(
+            set_checked(&pool, other_list_id, butter_item.id.unwrap(), false).await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(read_one(&pool, grocery_list_id).await.unwrap().items[1].checked);
+        delete(&pool, other_list_id).await.unwrap();
+
+        assert_eq!(read(&pool).await.unwrap().len(), 1);
+        delete(&pool, grocery_list_id).await.unwrap();
+        assert!(read(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_from_recipes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+        let salt = ingredient_id(&pool, "salt").await;
+
+        let recipe_id = recipe::create(&pool, "salty").await.unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, salt, 1.0, "Tbsp")
+            .await
+            .unwrap();
+
+        let recipes = [RecipeServings {
+            recipe_id,
+            servings: 2.0,
+        }];
+        let grocery_list_id = create(&pool, "salt run", None, &recipes).await.unwrap();
+        let grocery_list = read_one(&pool, grocery_list_id).await.unwrap();
+        assert_eq!(grocery_list.items.len(), 1);
+        assert_eq!(grocery_list.items[0].unit, Unit::Teaspoon);
+        assert_close(grocery_list.items[0].amount, 6.0);
+    }
+
+    #[tokio::test]
+    async fn test_create_from_missing_meal_plan() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        assert!(matches!(
+            create_from_meal_plan(&pool, "groceries", 9999).await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(read(&pool).await.unwrap().is_empty());
+    }
+}
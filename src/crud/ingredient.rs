@@ -1,73 +1,183 @@
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, Pool, Sqlite};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{FromRow, Pool, Row, Sqlite, Transaction};
+use std::collections::HashMap;
 
-#[derive(FromRow, Serialize, Deserialize, Debug)]
+use super::seed::Provenance;
+use super::{grocery_list, recipe};
+use crate::error::{Error, Result};
+use crate::nutrition::NutritionFacts;
+use crate::unit::Unit;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Ingredient {
     pub id: Option<i64>,
     pub name: String,
-    pub unit: String, // g/ml/etc
+    pub unit: Unit, // g/ml/etc
     pub amount: f32,
-    pub calories_kcal: f32,
-    pub protein_g: f32,
-    pub carbohydrates_g: f32,
-    pub sugar_g: f32,
-    pub fat_g: f32,
-    pub saturated_fat_g: f32,
-    pub fiber_g: f32,
-    pub potassium_mg: f32,
-    pub sodium_mg: f32,
-    pub cholesterol_mg: f32,
-}
-
-#[derive(FromRow, Serialize, Deserialize, Debug)]
+    // for `amount` of `unit`, kept in ingredient_nutrients
+    #[serde(flatten)]
+    pub nutrients: NutritionFacts,
+    // weight of one `unit` of this ingredient, e.g. grams per cup or grams per clove
+    #[serde(default)]
+    pub grams_per_unit: Option<f32>,
+    // where the nutrient data came from, e.g. "usda_fdc", None if typed in
+    #[serde(default)]
+    pub source: Option<String>,
+    // FoodData Central id for foods imported from USDA data
+    #[serde(default)]
+    pub fdc_id: Option<i64>,
+}
+
+// Nutrients are not columns of `ingredients`, they are filled in from
+// ingredient_nutrients after the row is read
+impl<'r> FromRow<'r, SqliteRow> for Ingredient {
+    fn from_row(row: &'r SqliteRow) -> sqlx::Result<Self> {
+        Ok(Ingredient {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            unit: row.try_get("unit")?,
+            amount: row.try_get("amount")?,
+            nutrients: NutritionFacts::default(),
+            grams_per_unit: row.try_get("grams_per_unit")?,
+            source: row.try_get("source")?,
+            fdc_id: row.try_get("fdc_id")?,
+        })
+    }
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct IngredientSimple {
     pub id: Option<i64>,
     pub name: String,
 }
 
 pub async fn create(pool: &Pool<Sqlite>, ingredient: &Ingredient) -> Result<i64> {
+    insert(pool, ingredient, Provenance::User).await
+}
+
+// Only for rows coming from data/ingredients, see `seed::run`
+pub async fn create_seeded(pool: &Pool<Sqlite>, ingredient: &Ingredient) -> Result<i64> {
+    insert(pool, ingredient, Provenance::Seed).await
+}
+
+// Only for foods from a FoodData Central bundle, see `usda::import`
+pub async fn create_imported(pool: &Pool<Sqlite>, ingredient: &Ingredient) -> Result<i64> {
+    insert(pool, ingredient, Provenance::Import).await
+}
+
+async fn insert(
+    pool: &Pool<Sqlite>,
+    ingredient: &Ingredient,
+    provenance: Provenance,
+) -> Result<i64> {
+    let mut tx = pool.begin().await?;
     let result = sqlx::query(
-        "INSERT OR IGNORE INTO ingredients (
+        "INSERT INTO ingredients (
             name,
             unit,
             amount,
-            calories_kcal,
-            protein_g,
-            carbohydrates_g,
-            sugar_g,
-            fat_g,
-            saturated_fat_g,
-            fiber_g,
-            potassium_mg,
-            sodium_mg,
-            cholesterol_mg
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            grams_per_unit,
+            source,
+            fdc_id,
+            provenance
+        ) VALUES (?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(&ingredient.name)
-    .bind(&ingredient.unit)
+    .bind(ingredient.unit)
     .bind(ingredient.amount)
-    .bind(ingredient.calories_kcal)
-    .bind(ingredient.protein_g)
-    .bind(ingredient.carbohydrates_g)
-    .bind(ingredient.sugar_g)
-    .bind(ingredient.fat_g)
-    .bind(ingredient.saturated_fat_g)
-    .bind(ingredient.fiber_g)
-    .bind(ingredient.potassium_mg)
-    .bind(ingredient.sodium_mg)
-    .bind(ingredient.cholesterol_mg)
-    .execute(pool)
+    .bind(ingredient.grams_per_unit)
+    .bind(&ingredient.source)
+    .bind(ingredient.fdc_id)
+    .bind(provenance)
+    .execute(&mut tx)
     .await?;
+    let id = result.last_insert_rowid();
+    write_nutrients(&mut tx, id, &ingredient.nutrients).await?;
+    tx.commit().await?;
+
+    Ok(id)
+}
+
+// Replace the ingredient's nutrient rows, unknown values get no row
+async fn write_nutrients(
+    tx: &mut Transaction<'_, Sqlite>,
+    id: i64,
+    nutrients: &NutritionFacts,
+) -> Result<()> {
+    sqlx::query("DELETE FROM ingredient_nutrients WHERE ingredient_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    for (nutrient, amount) in nutrients.values() {
+        if let Some(amount) = amount {
+            sqlx::query(
+                "INSERT INTO ingredient_nutrients (ingredient_id, nutrient, amount) VALUES (?, ?, ?)",
+            )
+            .bind(id)
+            .bind(nutrient)
+            .bind(amount)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn read_nutrients(pool: &Pool<Sqlite>, id: i64) -> Result<NutritionFacts> {
+    let rows: Vec<(String, f32)> =
+        sqlx::query_as("SELECT nutrient, amount FROM ingredient_nutrients WHERE ingredient_id = ?")
+            .bind(id)
+            .fetch_all(pool)
+            .await?;
 
-    Ok(result.last_insert_rowid())
+    let mut nutrients = NutritionFacts::default();
+    for (nutrient, amount) in rows {
+        // rows for nutrients this version doesn't know about are ignored
+        nutrients.set(&nutrient, Some(amount));
+    }
+    Ok(nutrients)
+}
+
+async fn with_nutrients(
+    pool: &Pool<Sqlite>,
+    ingredient: Option<Ingredient>,
+) -> Result<Option<Ingredient>> {
+    match ingredient {
+        Some(mut ingredient) => {
+            if let Some(id) = ingredient.id {
+                ingredient.nutrients = read_nutrients(pool, id).await?;
+            }
+            Ok(Some(ingredient))
+        }
+        None => Ok(None),
+    }
 }
 
 pub async fn read(pool: &Pool<Sqlite>) -> Result<Vec<Ingredient>> {
-    let ingredients = sqlx::query_as::<_, Ingredient>(r#"SELECT * FROM ingredients"#)
+    let mut ingredients = sqlx::query_as::<_, Ingredient>(r#"SELECT * FROM ingredients"#)
         .fetch_all(pool)
         .await?;
 
+    // all nutrients in one query rather than one per ingredient
+    let rows: Vec<(i64, String, f32)> =
+        sqlx::query_as("SELECT ingredient_id, nutrient, amount FROM ingredient_nutrients")
+            .fetch_all(pool)
+            .await?;
+    let mut nutrients: HashMap<i64, NutritionFacts> = HashMap::new();
+    for (id, nutrient, amount) in rows {
+        nutrients
+            .entry(id)
+            .or_default()
+            .set(&nutrient, Some(amount));
+    }
+    for ingredient in &mut ingredients {
+        if let Some(facts) = ingredient.id.and_then(|id| nutrients.remove(&id)) {
+            ingredient.nutrients = facts;
+        }
+    }
+
     Ok(ingredients)
 }
 
@@ -84,6 +194,16 @@ pub async fn read_by_search_string(
 
     Ok(ingredients)
 }
+
+pub async fn read_by_name(pool: &Pool<Sqlite>, name: &str) -> Result<Option<Ingredient>> {
+    let ingredient = sqlx::query_as::<_, Ingredient>(r#"SELECT * FROM ingredients WHERE name = ?"#)
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+
+    with_nutrients(pool, ingredient).await
+}
+
 pub async fn read_one(pool: &Pool<Sqlite>, id: i64) -> Result<Ingredient> {
     let ingredient = sqlx::query_as::<_, Ingredient>(
         r#"
@@ -93,10 +213,103 @@ pub async fn read_one(pool: &Pool<Sqlite>, id: i64) -> Result<Ingredient> {
         "#,
     )
     .bind(id)
-    .fetch_one(pool)
+    .fetch_optional(pool)
+    .await?;
+
+    with_nutrients(pool, ingredient)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Ingredient {} not found", id)))
+}
+
+// Marks the row as edited by the user so seeding leaves it alone
+pub async fn update(pool: &Pool<Sqlite>, id: i64, ingredient: &Ingredient) -> Result<()> {
+    write(pool, id, ingredient, Provenance::User).await
+}
+
+pub async fn update_seeded(pool: &Pool<Sqlite>, id: i64, ingredient: &Ingredient) -> Result<()> {
+    write(pool, id, ingredient, Provenance::Seed).await
+}
+
+pub async fn update_imported(pool: &Pool<Sqlite>, id: i64, ingredient: &Ingredient) -> Result<()> {
+    write(pool, id, ingredient, Provenance::Import).await
+}
+
+async fn write(
+    pool: &Pool<Sqlite>,
+    id: i64,
+    ingredient: &Ingredient,
+    provenance: Provenance,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+        "UPDATE ingredients SET
+            name = ?,
+            unit = ?,
+            amount = ?,
+            grams_per_unit = ?,
+            source = ?,
+            fdc_id = ?,
+            provenance = ?
+        WHERE id = ?",
+    )
+    .bind(&ingredient.name)
+    .bind(ingredient.unit)
+    .bind(ingredient.amount)
+    .bind(ingredient.grams_per_unit)
+    .bind(&ingredient.source)
+    .bind(ingredient.fdc_id)
+    .bind(provenance)
+    .bind(id)
+    .execute(&mut tx)
     .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("Ingredient {} not found", id)));
+    }
+    write_nutrients(&mut tx, id, &ingredient.nutrients).await?;
+    tx.commit().await?;
 
-    Ok(ingredient)
+    Ok(())
+}
+
+// Refuses to delete an ingredient that recipes or grocery lists still use,
+// unless `cascade` is set in which case it is removed from those as well
+pub async fn delete(pool: &Pool<Sqlite>, id: i64, cascade: bool) -> Result<()> {
+    // check in the same transaction so nothing can start using it in between
+    let mut tx = pool.begin().await?;
+    let recipes = recipe::read_by_ingredient_id(&mut tx, id).await?;
+    let grocery_lists = grocery_list::read_by_ingredient_id(&mut tx, id).await?;
+    if (!recipes.is_empty() || !grocery_lists.is_empty()) && !cascade {
+        return Err(Error::Conflict {
+            message: format!(
+                "Ingredient {} is used by {} recipe(s) and {} grocery list(s)",
+                id,
+                recipes.len(),
+                grocery_lists.len()
+            ),
+            recipes,
+            meal_plans: Vec::new(),
+            grocery_lists,
+        });
+    }
+
+    for query in [
+        "DELETE FROM recipes_ingredients WHERE ingredient_id = ?",
+        "DELETE FROM unit_conversions WHERE ingredient_id = ?",
+        "DELETE FROM ingredient_nutrients WHERE ingredient_id = ?",
+        "DELETE FROM grocery_list_items WHERE ingredient_id = ?",
+    ] {
+        sqlx::query(query).bind(id).execute(&mut tx).await?;
+    }
+    let result = sqlx::query("DELETE FROM ingredients WHERE id = ?")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("Ingredient {} not found", id)));
+    }
+    tx.commit().await?;
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -108,49 +321,188 @@ mod tests {
     async fn test_create() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
         let ingredient = Ingredient {
             id: None,
             name: "test_ingredient".to_string(),
-            unit: "ml".to_string(),
+            unit: Unit::Milliliter,
             amount: 500.0,
-            calories_kcal: 320.0,
-            protein_g: 20.0,
-            carbohydrates_g: 10.0,
-            sugar_g: 0.1,
-            fat_g: 11.0,
-            saturated_fat_g: 22.0,
-            fiber_g: 100.0,
-            potassium_mg: 2000.0,
-            sodium_mg: 2000.1,
-            cholesterol_mg: 20000.0,
+            nutrients: NutritionFacts {
+                calories_kcal: Some(320.0),
+                protein_g: Some(20.0),
+                carbohydrates_g: Some(10.0),
+                sugar_g: Some(0.1),
+                fat_g: Some(11.0),
+                saturated_fat_g: Some(22.0),
+                fiber_g: Some(100.0),
+                potassium_mg: Some(2000.0),
+                sodium_mg: Some(2000.1),
+                cholesterol_mg: None,
+                iron_mg: Some(1.5),
+                ..Default::default()
+            },
+            grams_per_unit: None,
+            source: None,
+            fdc_id: None,
         };
         let ingredient_id = create(&pool, &ingredient).await.unwrap();
-        let ingredient = read_one(&pool, ingredient_id).await.unwrap();
-        assert!(ingredient.id.is_some());
-        assert_eq!(ingredient.name, "test_ingredient".to_string());
+        let created = read_one(&pool, ingredient_id).await.unwrap();
+        assert!(created.id.is_some());
+        assert_eq!(created.name, "test_ingredient".to_string());
+        // unknown values stay unknown instead of becoming 0
+        assert_eq!(created.nutrients, ingredient.nutrients);
+        assert_eq!(created.nutrients.cholesterol_mg, None);
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let mut ingredient = read_one(&pool, 1).await.unwrap();
+        // names are unique
+        assert!(create(&pool, &ingredient).await.is_err());
+
+        ingredient.name = "renamed".to_string();
+        ingredient.nutrients.calories_kcal = Some(1.0);
+        ingredient.nutrients.sodium_mg = None;
+        update(&pool, 1, &ingredient).await.unwrap();
+        let ingredient = read_one(&pool, 1).await.unwrap();
+        assert_eq!(ingredient.name, "renamed");
+        assert_eq!(ingredient.nutrients.calories_kcal, Some(1.0));
+        assert_eq!(ingredient.nutrients.sodium_mg, None);
+
+        let recipe_id = recipe::create(&pool, "test_recipe").await.unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, 1, 1.0, "cup")
+            .await
+            .unwrap();
+
+        let err = delete(&pool, 1, false).await.unwrap_err();
+        match err {
+            Error::Conflict { recipes, .. } => {
+                assert_eq!(recipes.len(), 1);
+                assert_eq!(recipes[0].id, Some(recipe_id));
+            }
+            err => panic!("expected a conflict, got {:?}", err),
+        }
+        assert!(read_one(&pool, 1).await.is_ok());
+
+        // saved grocery lists block the delete too, even once no recipe uses it
+        let recipes = [grocery_list::RecipeServings {
+            recipe_id,
+            servings: 1.0,
+        }];
+        let grocery_list_id = grocery_list::create(&pool, "groceries", None, &recipes)
+            .await
+            .unwrap();
+        let no_ingredients = recipe::RecipePatch {
+            ingredients: Some(Vec::new()),
+            ..Default::default()
+        };
+        recipe::patch(&pool, recipe_id, no_ingredients)
+            .await
+            .unwrap();
+        let err = delete(&pool, 1, false).await.unwrap_err();
+        match err {
+            Error::Conflict {
+                recipes,
+                grocery_lists,
+                ..
+            } => {
+                assert!(recipes.is_empty());
+                assert_eq!(grocery_lists.len(), 1);
+                assert_eq!(grocery_lists[0].id, Some(grocery_list_id));
+            }
+            err => panic!("expected a conflict, got {:?}", err),
+        }
+        let grocery_list = grocery_list::read_one(&pool, grocery_list_id)
+            .await
+            .unwrap();
+        assert_eq!(grocery_list.items.len(), 1);
+
+        recipe::add_recipe_ingredient(&pool, recipe_id, 1, 1.0, "cup")
+            .await
+            .unwrap();
+        delete(&pool, 1, true).await.unwrap();
+        assert!(read_one(&pool, 1).await.is_err());
+        assert!(matches!(
+            delete(&pool, 1, false).await,
+            Err(Error::NotFound(_))
+        ));
+        let recipe = recipe::read_one(&pool, recipe_id).await.unwrap();
+        assert!(recipe.ingredients.is_empty());
+        let grocery_list = grocery_list::read_one(&pool, grocery_list_id)
+            .await
+            .unwrap();
+        assert!(grocery_list.items.is_empty());
     }
 
     #[tokio::test]
     async fn test_read() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
         let items = read(&pool).await.unwrap();
-        assert!(items.len() > 0);
+        assert!(!items.is_empty());
+        let butter = items.iter().find(|i| i.name == "butter").unwrap();
+        assert_eq!(
+            butter.nutrients,
+            read_one(&pool, butter.id.unwrap()).await.unwrap().nutrients
+        );
+        assert!(butter.nutrients.calories_kcal.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_units_are_normalized() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        // salt is stored as "teaspoon" in data/ingredients/spices.json
+        let salt = read(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "salt")
+            .unwrap();
+        assert_eq!(salt.unit, Unit::Teaspoon);
+
+        let unit: String = sqlx::query_scalar("SELECT unit FROM ingredients WHERE id = ?")
+            .bind(salt.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(unit, "tsp");
     }
 
     #[tokio::test]
     async fn test_search_string_query() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
@@ -0,0 +1,553 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, FromRow, Pool, Row, Sqlite};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::recipe;
+use crate::error::{Error, Result};
+use crate::timing::{self, RecipeTime};
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MealSlot {
+    Breakfast,
+    Lunch,
+    Dinner,
+    Snack,
+}
+
+impl fmt::Display for MealSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MealSlot::Breakfast => "breakfast",
+            MealSlot::Lunch => "lunch",
+            MealSlot::Dinner => "dinner",
+            MealSlot::Snack => "snack",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct MealPlanEntry {
+    pub id: Option<i64>,
+    pub date: String, // YYYY-MM-DD
+    pub meal: MealSlot,
+    pub recipe_id: i64,
+    // filled in when reading a plan, ignored on create/update
+    #[serde(default)]
+    pub recipe_name: Option<String>,
+    pub servings: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MealPlan {
+    pub id: Option<i64>,
+    pub name: String,
+    pub entries: Vec<MealPlanEntry>,
+    // e.g. weeknight dinners that take too long, filled in when reading
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug)]
+pub struct MealPlanSimple {
+    pub id: Option<i64>,
+    pub name: String,
+}
+
+pub async fn create(pool: &Pool<Sqlite>, meal_plan_name: &str) -> Result<i64> {
+    let result = sqlx::query(
+        "INSERT INTO meal_plans (
+            name
+        ) VALUES (?)",
+    )
+    .bind(meal_plan_name)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+// Create a meal plan with all of its entries in one transaction
+pub async fn create_with_entries(
+    pool: &Pool<Sqlite>,
+    meal_plan_name: &str,
+    entries: &[MealPlanEntry],
+) -> Result<i64> {
+    for entry in entries {
+        validate_entry(entry)?;
+    }
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("INSERT INTO meal_plans (name) VALUES (?)")
+        .bind(meal_plan_name)
+        .execute(&mut tx)
+        .await?;
+    let meal_plan_id = result.last_insert_rowid();
+    for entry in entries {
+        sqlx::query(
+            "INSERT INTO meal_plan_entries (
+                meal_plan_id,
+                date,
+                meal,
+                recipe_id,
+                servings
+            ) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(meal_plan_id)
+        .bind(&entry.date)
+        .bind(entry.meal)
+        .bind(entry.recipe_id)
+        .bind(entry.servings)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(meal_plan_id)
+}
+
+pub async fn read(pool: &Pool<Sqlite>) -> Result<Vec<MealPlanSimple>> {
+    let meal_plans = sqlx::query_as::<_, MealPlanSimple>(r#"SELECT id, name FROM meal_plans"#)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(meal_plans)
+}
+
+pub async fn read_one(pool: &Pool<Sqlite>, meal_plan_id: i64) -> Result<MealPlan> {
+    let row = sqlx::query(
+        r#"SELECT id, name
+           FROM meal_plans
+           WHERE id = ?"#,
+    )
+    .bind(meal_plan_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("Meal plan {} not found", meal_plan_id)))?;
+    let entries = read_entries(pool, meal_plan_id).await?;
+    let warnings = warnings(pool, &entries).await?;
+
+    Ok(MealPlan {
+        id: Some(row.get(0)),
+        name: row.get(1),
+        entries,
+        warnings,
+    })
+}
+
+// Weeknight dinners that take longer than `timing::WEEKNIGHT_DINNER_MINUTES`
+async fn warnings(pool: &Pool<Sqlite>, entries: &[MealPlanEntry]) -> Result<Vec<String>> {
+    let mut times: HashMap<i64, RecipeTime> = HashMap::new();
+    let mut warnings = Vec::new();
+    for entry in entries {
+        if entry.meal != MealSlot::Dinner || !timing::is_weeknight(&entry.date) {
+            continue;
+        }
+        let time = match times.get(&entry.recipe_id) {
+            Some(time) => *time,
+            None => {
+                let steps = recipe::get_recipe_steps(pool, entry.recipe_id).await?;
+                let time = RecipeTime::from_steps(&steps);
+                times.insert(entry.recipe_id, time);
+                time
+            }
+        };
+        if time.total_minutes > timing::WEEKNIGHT_DINNER_MINUTES {
+            warnings.push(format!(
+                "{} dinner: {} takes {} minutes, more than {} on a weeknight",
+                entry.date,
+                entry.recipe_name.as_deref().unwrap_or("recipe"),
+                time.total_minutes.round(),
+                timing::WEEKNIGHT_DINNER_MINUTES
+            ));
+        }
+    }
+
+    Ok(warnings)
+}
+
+pub async fn update(pool: &Pool<Sqlite>, meal_plan_id: i64, meal_plan_name: &str) -> Result<()> {
+    let result = sqlx::query("UPDATE meal_plans SET name = ? WHERE id = ?")
+        .bind(meal_plan_name)
+        .bind(meal_plan_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "Meal plan {} not found",
+            meal_plan_id
+        )));
+    }
+
+    Ok(())
+}
+
+// Grocery lists built from the plan are kept, they just stop pointing at it
+pub async fn delete(pool: &Pool<Sqlite>, meal_plan_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for query in [
+        "DELETE FROM meal_plan_entries WHERE meal_plan_id = ?",
+        "UPDATE grocery_lists SET meal_plan_id = NULL WHERE meal_plan_id = ?",
+    ] {
+        sqlx::query(query)
+            .bind(meal_plan_id)
+            .execute(&mut tx)
+            .await?;
+    }
+    let result = sqlx::query("DELETE FROM meal_plans WHERE id = ?")
+        .bind(meal_plan_id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!(
+            "Meal plan {} not found",
+            meal_plan_id
+        )));
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Plans with at least one entry for the recipe
+pub async fn read_by_recipe_id<'c, E: Executor<'c, Database = Sqlite>>(
+    executor: E,
+    recipe_id: i64,
+) -> Result<Vec<MealPlanSimple>> {
+    let meal_plans = sqlx::query_as::<_, MealPlanSimple>(
+        r#"SELECT DISTINCT meal_plans.id, meal_plans.name
+           FROM meal_plans
+           JOIN meal_plan_entries ON meal_plans.id = meal_plan_entries.meal_plan_id
+           WHERE meal_plan_entries.recipe_id = ?"#,
+    )
+    .bind(recipe_id)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(meal_plans)
+}
+
+// Entries ordered by date, then breakfast/lunch/dinner/snack
+pub async fn read_entries(pool: &Pool<Sqlite>, meal_plan_id: i64) -> Result<Vec<MealPlanEntry>> {
+    let entries = sqlx::query_as::<_, MealPlanEntry>(
+        r#"SELECT meal_plan_entries.id,
+                  meal_plan_entries.date,
+                  meal_plan_entries.meal,
+                  meal_plan_entries.recipe_id,
+                  recipes.name AS recipe_name,
+                  meal_plan_entries.servings
+           FROM meal_plan_entries
+           JOIN recipes ON meal_plan_entries.recipe_id = recipes.id
+           WHERE meal_plan_entries.meal_plan_id = ?
+           ORDER BY meal_plan_entries.date,
+                    CASE meal_plan_entries.meal
+                        WHEN 'breakfast' THEN 0
+                        WHEN 'lunch' THEN 1
+                        WHEN 'dinner' THEN 2
+                        ELSE 3
+                    END"#,
+    )
+    .bind(meal_plan_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+pub async fn add_entry(
+    pool: &Pool<Sqlite>,
+    meal_plan_id: i64,
+    entry: &MealPlanEntry,
+) -> Result<i64> {
+    validate_entry(entry)?;
+    let result = sqlx::query(
+        "INSERT INTO meal_plan_entries (
+            meal_plan_id,
+            date,
+            meal,
+            recipe_id,
+            servings
+        ) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(meal_plan_id)
+    .bind(&entry.date)
+    .bind(entry.meal)
+    .bind(entry.recipe_id)
+    .bind(entry.servings)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+// Entries are only changed through the plan they belong to
+pub async fn update_entry(
+    pool: &Pool<Sqlite>,
+    meal_plan_id: i64,
+    entry_id: i64,
+    entry: &MealPlanEntry,
+) -> Result<()> {
+    validate_entry(entry)?;
+    let result = sqlx::query(
+        "UPDATE meal_plan_entries
+         SET date = ?, meal = ?, recipe_id = ?, servings = ?
+         WHERE id = ? AND meal_plan_id = ?",
+    )
+    .bind(&entry.date)
+    .bind(entry.meal)
+    .bind(entry.recipe_id)
+    .bind(entry.servings)
+    .bind(entry_id)
+    .bind(meal_plan_id)
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(entry_not_found(meal_plan_id, entry_id));
+    }
+
+    Ok(())
+}
+
+pub async fn delete_entry(pool: &Pool<Sqlite>, meal_plan_id: i64, entry_id: i64) -> Result<()> {
+    let result = sqlx::query("DELETE FROM meal_plan_entries WHERE id = ? AND meal_plan_id = ?")
+        .bind(entry_id)
+        .bind(meal_plan_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(entry_not_found(meal_plan_id, entry_id));
+    }
+
+    Ok(())
+}
+
+fn entry_not_found(meal_plan_id: i64, entry_id: i64) -> Error {
+    Error::NotFound(format!(
+        "Meal plan entry {} not found in meal plan {}",
+        entry_id, meal_plan_id
+    ))
+}
+
+fn validate_entry(entry: &MealPlanEntry) -> Result<()> {
+    if entry.servings <= 0.0 {
+        return Err(Error::Validation(format!(
+            "Servings must be greater than 0, got {}",
+            entry.servings
+        )));
+    }
+    validate_date(&entry.date)
+}
+
+// Dates are stored as YYYY-MM-DD text so they sort correctly
+fn validate_date(date: &str) -> Result<()> {
+    if timing::parse_date(date).is_none() {
+        return Err(Error::Validation(format!(
+            "Invalid date '{}', expected YYYY-MM-DD",
+            date
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, get_connection_pool, grocery_list, meal_plan::*, recipe};
+    use tempfile::NamedTempFile;
+
+    fn entry(date: &str, meal: MealSlot, recipe_id: i64) -> MealPlanEntry {
+        MealPlanEntry {
+            id: None,
+            date: date.to_string(),
+            meal,
+            recipe_id,
+            recipe_name: None,
+            servings: 2.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simple_crud() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+        let oats = recipe::create(&pool, "overnight oats").await.unwrap();
+        let chili = recipe::create(&pool, "chili").await.unwrap();
+
+        let meal_plan_id = create(&pool, "meal-plan_2023-03-06").await.unwrap();
+        add_entry(
+            &pool,
+            meal_plan_id,
+            &entry("2023-03-07", MealSlot::Dinner, chili),
+        )
+        .await
+        .unwrap();
+        add_entry(
+            &pool,
+            meal_plan_id,
+            &entry("2023-03-06", MealSlot::Dinner, chili),
+        )
+        .await
+        .unwrap();
+        let breakfast_id = add_entry(
+            &pool,
+            meal_plan_id,
+            &entry("2023-03-06", MealSlot::Breakfast, oats),
+        )
+        .await
+        .unwrap();
+
+        let meal_plan = read_one(&pool, meal_plan_id).await.unwrap();
+        assert_eq!(meal_plan.name, "meal-plan_2023-03-06");
+        assert_eq!(meal_plan.entries.len(), 3);
+        // ordered by date then meal
+        assert_eq!(meal_plan.entries[0].meal, MealSlot::Breakfast);
+        assert_eq!(
+            meal_plan.entries[0].recipe_name.as_deref(),
+            Some("overnight oats")
+        );
+        assert_eq!(meal_plan.entries[2].date, "2023-03-07");
+
+        let mut updated = entry("2023-03-06", MealSlot::Snack, oats);
+        updated.servings = 1.0;
+        update_entry(&pool, meal_plan_id, breakfast_id, &updated)
+            .await
+            .unwrap();
+
+        // an entry can't be changed through another plan
+        let other_plan_id = create(&pool, "other").await.unwrap();
+        assert!(matches!(
+            update_entry(&pool, other_plan_id, breakfast_id, &updated).await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            update_entry(&pool, 9999, breakfast_id, &updated).await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            delete_entry(&pool, other_plan_id, breakfast_id).await,
+            Err(Error::NotFound(_))
+        ));
+        let entries = read_entries(&pool, meal_plan_id).await.unwrap();
+        let snack = entries.iter().find(|e| e.id == Some(breakfast_id)).unwrap();
+        assert_eq!(snack.meal, MealSlot::Snack);
+        assert_eq!(snack.servings, 1.0);
+        delete(&pool, other_plan_id).await.unwrap();
+
+        delete_entry(&pool, meal_plan_id, breakfast_id)
+            .await
+            .unwrap();
+        assert_eq!(read_entries(&pool, meal_plan_id).await.unwrap().len(), 2);
+
+        update(&pool, meal_plan_id, "renamed").await.unwrap();
+        assert_eq!(read(&pool).await.unwrap()[0].name, "renamed");
+
+        delete(&pool, meal_plan_id).await.unwrap();
+        assert!(read(&pool).await.unwrap().is_empty());
+        assert!(read_one(&pool, meal_plan_id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_keeps_grocery_lists() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        let chili = recipe::create(&pool, "chili").await.unwrap();
+        let meal_plan_id = create(&pool, "week").await.unwrap();
+        add_entry(
+            &pool,
+            meal_plan_id,
+            &entry("2023-03-06", MealSlot::Dinner, chili),
+        )
+        .await
+        .unwrap();
+        let grocery_list_id = grocery_list::create_from_meal_plan(&pool, "groceries", meal_plan_id)
+            .await
+            .unwrap();
+
+        delete(&pool, meal_plan_id).await.unwrap();
+        assert!(read_one(&pool, meal_plan_id).await.is_err());
+        let grocery_list = grocery_list::read_one(&pool, grocery_list_id)
+            .await
+            .unwrap();
+        assert_eq!(grocery_list.meal_plan_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_slow_weeknight_dinner_warning() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        let mut input: recipe::RecipeInput =
+            serde_json::from_str(r#"{"name": "stew", "instructions": ["Simmer for 2 hours"]}"#)
+                .unwrap();
+        let stew = recipe::create_full(&pool, &input).await.unwrap();
+        input.name = "omelette".to_string();
+        input.instructions = vec!["Fry for 5 minutes".into()];
+        let omelette = recipe::create_full(&pool, &input).await.unwrap();
+
+        let meal_plan_id = create(&pool, "week").await.unwrap();
+        // Monday, Tuesday and Saturday
+        for (date, meal, recipe_id) in [
+            ("2023-03-06", MealSlot::Dinner, stew),
+            ("2023-03-06", MealSlot::Lunch, stew),
+            ("2023-03-07", MealSlot::Dinner, omelette),
+            ("2023-03-11", MealSlot::Dinner, stew),
+        ] {
+            add_entry(&pool, meal_plan_id, &entry(date, meal, recipe_id))
+                .await
+                .unwrap();
+        }
+
+        let meal_plan = read_one(&pool, meal_plan_id).await.unwrap();
+        assert_eq!(
+            meal_plan.warnings,
+            vec!["2023-03-06 dinner: stew takes 120 minutes, more than 45 on a weeknight"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_entries() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        let recipe_id = recipe::create(&pool, "chili").await.unwrap();
+        let meal_plan_id = create(&pool, "week").await.unwrap();
+
+        let bad_date = entry("03/06/2023", MealSlot::Lunch, recipe_id);
+        assert!(add_entry(&pool, meal_plan_id, &bad_date).await.is_err());
+        let bad_month = entry("2023-13-01", MealSlot::Lunch, recipe_id);
+        assert!(add_entry(&pool, meal_plan_id, &bad_month).await.is_err());
+        for bad_day in ["2023-02-31", "2023-02-29", "2023-04-31", "2023-03-00"] {
+            let bad_day = entry(bad_day, MealSlot::Lunch, recipe_id);
+            assert!(matches!(
+                add_entry(&pool, meal_plan_id, &bad_day).await,
+                Err(Error::Validation(_))
+            ));
+        }
+        let leap_day = entry("2024-02-29", MealSlot::Lunch, recipe_id);
+        assert!(add_entry(&pool, meal_plan_id, &leap_day).await.is_ok());
+        let mut bad_servings = entry("2023-03-06", MealSlot::Lunch, recipe_id);
+        bad_servings.servings = 0.0;
+        assert!(add_entry(&pool, meal_plan_id, &bad_servings).await.is_err());
+    }
+}
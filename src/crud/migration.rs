@@ -0,0 +1,383 @@
+use sqlx::{Executor, Pool, Sqlite, Transaction};
+
+use crate::error::Result;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+// Applied in order, never edit one that has shipped, add a new one instead
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../../migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "unit_conversions",
+        sql: include_str!("../../migrations/0002_unit_conversions.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "recipe_servings",
+        sql: include_str!("../../migrations/0003_recipe_servings.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "meal_plans",
+        sql: include_str!("../../migrations/0004_meal_plans.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "grocery_lists",
+        sql: include_str!("../../migrations/0005_grocery_lists.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "seed_provenance",
+        sql: include_str!("../../migrations/0006_seed_provenance.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "recipe_steps",
+        sql: include_str!("../../migrations/0007_recipe_steps.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "step_durations",
+        sql: include_str!("../../migrations/0008_step_durations.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "ingredient_nutrients",
+        sql: include_str!("../../migrations/0009_ingredient_nutrients.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "ingredient_source",
+        sql: include_str!("../../migrations/0010_ingredient_source.sql"),
+    },
+];
+
+// Highest applied version, 0 for a new database or one created before
+// migrations were tracked
+pub async fn current_version(pool: &Pool<Sqlite>) -> Result<i64> {
+    let tracked: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+    )
+    .fetch_one(pool)
+    .await?;
+    if !tracked {
+        return Ok(0);
+    }
+
+    let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(version.unwrap_or(0))
+}
+
+// Migrations that `run` would apply, without touching the database
+pub async fn pending(pool: &Pool<Sqlite>) -> Result<Vec<&'static Migration>> {
+    let version = current_version(pool).await?;
+    Ok(MIGRATIONS.iter().filter(|m| m.version > version).collect())
+}
+
+// Apply pending migrations, each in its own transaction, and return them
+pub async fn run(pool: &Pool<Sqlite>) -> Result<Vec<&'static Migration>> {
+    pool.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .await?;
+
+    let migrations = pending(pool).await?;
+    for migration in &migrations {
+        let mut tx = pool.begin().await?;
+        for statement in statements(migration.sql) {
+            if let Some((table, column)) = added_column(&statement) {
+                if has_column(&mut tx, table, column).await? {
+                    continue;
+                }
+            }
+            tx.execute(statement.as_str()).await?;
+        }
+        sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    Ok(migrations)
+}
+
+// Split a migration into statements, dropping comments. Semicolons in
+// quotes, comments and trigger bodies don't end a statement
+fn statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut keywords = Keywords::default();
+    let mut word = String::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            current.push(c);
+            continue;
+        }
+        keywords.word(&word);
+        word.clear();
+        match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                current.push(c);
+                // a doubled quote closes and reopens, which comes out the same
+                for c in chars.by_ref() {
+                    current.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().find(|&c| c == '\n');
+                current.push('\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                current.push(' ');
+            }
+            ';' if !keywords.in_body => {
+                let statement = current.trim();
+                if !statement.is_empty() {
+                    statements.push(statement.to_string());
+                }
+                current.clear();
+                keywords = Keywords::default();
+            }
+            c => current.push(c),
+        }
+    }
+    let statement = current.trim();
+    if !statement.is_empty() {
+        statements.push(statement.to_string());
+    }
+
+    statements
+}
+
+// Just enough of the statement to tell whether a `;` is inside the
+// BEGIN .. END body of a CREATE TRIGGER
+#[derive(Default)]
+struct Keywords {
+    create: Option<bool>,
+    trigger: bool,
+    in_body: bool,
+    case_depth: u32,
+}
+
+impl Keywords {
+    fn word(&mut self, word: &str) {
+        if word.is_empty() {
+            return;
+        }
+        let word = word.to_ascii_uppercase();
+        let create = *self.create.get_or_insert(word == "CREATE");
+        if !self.in_body {
+            self.trigger |= create && word == "TRIGGER";
+            self.in_body = self.trigger && word == "BEGIN";
+            return;
+        }
+        match word.as_str() {
+            "CASE" => self.case_depth += 1,
+            "END" if self.case_depth > 0 => self.case_depth -= 1,
+            "END" => self.in_body = false,
+            _ => {}
+        }
+    }
+}
+
+// Databases created before migrations were tracked may already have some of
+// the later columns, so `ALTER TABLE .. ADD COLUMN` is skipped when the column
+// exists. Returns the table and column for such a statement
+fn added_column(statement: &str) -> Option<(&str, &str)> {
+    let words: Vec<&str> = statement.split_whitespace().collect();
+    match words.as_slice() {
+        [alter, table_kw, table, add, column_kw, column, ..]
+            if alter.eq_ignore_ascii_case("ALTER")
+                && table_kw.eq_ignore_ascii_case("TABLE")
+                && add.eq_ignore_ascii_case("ADD")
+                && column_kw.eq_ignore_ascii_case("COLUMN") =>
+        {
+            Some((table, column))
+        }
+        _ => None,
+    }
+}
+
+async fn has_column(tx: &mut Transaction<'_, Sqlite>, table: &str, column: &str) -> Result<bool> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    Ok(count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{get_connection_pool, ingredient, migration::*, recipe};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_versions_are_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert_eq!(pair[0].version + 1, pair[1].version);
+        }
+        assert_eq!(
+            added_column("ALTER TABLE recipes ADD COLUMN servings FLOAT"),
+            Some(("recipes", "servings"))
+        );
+        assert_eq!(added_column("CREATE TABLE recipes (id INTEGER)"), None);
+    }
+
+    #[test]
+    fn test_statements() {
+        let sql = "-- a comment; with a semicolon
+            CREATE TABLE a (id INTEGER, text TEXT DEFAULT ';'); /* one; more */
+            INSERT INTO a (text) VALUES ('it''s; fine'), (\"x;y\");
+            CREATE TRIGGER a_insert AFTER INSERT ON a BEGIN
+                UPDATE a SET text = CASE WHEN text = '' THEN ';' ELSE text END;
+                DELETE FROM a WHERE id < 0;
+            END;
+            SELECT 1";
+        assert_eq!(
+            statements(sql),
+            vec![
+                "CREATE TABLE a (id INTEGER, text TEXT DEFAULT ';')",
+                "INSERT INTO a (text) VALUES ('it''s; fine'), (\"x;y\")",
+                "CREATE TRIGGER a_insert AFTER INSERT ON a BEGIN
+                UPDATE a SET text = CASE WHEN text = '' THEN ';' ELSE text END;
+                DELETE FROM a WHERE id < 0;
+            END",
+                "SELECT 1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_database() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(pending(&pool).await.unwrap().len(), MIGRATIONS.len());
+        assert_eq!(run(&pool).await.unwrap().len(), MIGRATIONS.len());
+        assert_eq!(
+            current_version(&pool).await.unwrap(),
+            MIGRATIONS.last().unwrap().version
+        );
+
+        // running again is a no-op
+        assert!(pending(&pool).await.unwrap().is_empty());
+        assert!(run(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_initial_schema() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        // a database created before servings, meal plans etc. existed
+        pool.execute(MIGRATIONS[0].sql).await.unwrap();
+        sqlx::query(
+            "INSERT INTO recipes (name, notes, instructions)
+             VALUES ('tacos', NULL, 'Warm the tortillas;Fill them; ;Serve')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO ingredients (name, unit, amount, calories_kcal, protein_g,
+                carbohydrates_g, sugar_g, fat_g, saturated_fat_g, fiber_g, potassium_mg,
+                sodium_mg, cholesterol_mg)
+             VALUES ('oats', 'cup', 1, 300, 10, 54, 1, 5, 1, 8, 300, 5, 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // a dry run lists everything and changes nothing
+        let pending_versions: Vec<i64> = pending(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|m| m.version)
+            .collect();
+        assert_eq!(pending_versions, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(current_version(&pool).await.unwrap(), 0);
+
+        run(&pool).await.unwrap();
+        let recipe = recipe::read_one(&pool, 1).await.unwrap();
+        assert_eq!(recipe.name, "tacos");
+        assert_eq!(recipe.servings, 1.0);
+        assert!(recipe.recipe_yield.is_none());
+        let steps: Vec<&str> = recipe
+            .instructions
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect();
+        assert_eq!(steps, vec!["Warm the tortillas", "Fill them", "Serve"]);
+        assert!(recipe.notes.is_empty());
+        // nutrient columns moved to ingredient_nutrients, new nutrients are unknown
+        let oats = ingredient::read_one(&pool, 1).await.unwrap();
+        assert_eq!(oats.nutrients.calories_kcal, Some(300.0));
+        assert_eq!(oats.nutrients.cholesterol_mg, Some(0.0));
+        assert_eq!(oats.nutrients.iron_mg, None);
+        assert!(pending(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_untracked_current_schema() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        // everything in place but no schema_version, as created by the old
+        // CREATE TABLE IF NOT EXISTS setup before versions were tracked
+        for migration in MIGRATIONS.iter().filter(|m| m.version <= 6) {
+            pool.execute(migration.sql).await.unwrap();
+        }
+        assert_eq!(current_version(&pool).await.unwrap(), 0);
+
+        run(&pool).await.unwrap();
+        assert_eq!(
+            current_version(&pool).await.unwrap(),
+            MIGRATIONS.last().unwrap().version
+        );
+    }
+}
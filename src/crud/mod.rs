@@ -1,116 +1,38 @@
-use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::{Executor, SqlitePool};
-use sqlx::{FromRow, Pool, Sqlite};
-use std::fs::File;
-use std::io::BufReader;
-use std::{fs, path::PathBuf};
-
-use self::ingredient::Ingredient;
-use self::tag::Tag;
-
+use log::LevelFilter;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{ConnectOptions, Pool, Sqlite};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use self::seed::SeedReport;
+use crate::config::DEFAULT_DATA_DIR;
+use crate::error::Result;
+
+pub mod grocery_list;
 pub mod ingredient;
+pub mod meal_plan;
+pub mod migration;
 pub mod recipe;
+pub mod seed;
 pub mod tag;
+pub mod unit_conversion;
 
-const DB_FILENAME: &str = "database.db";
-
+// Bring the schema up to date, see `migration::MIGRATIONS`
 pub async fn create_tables(pool: &Pool<Sqlite>) -> Result<()> {
-    pool.execute(
-        "CREATE TABLE IF NOT EXISTS tags (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE
-        )",
-    )
-    .await?;
-
-    pool.execute(
-        "CREATE TABLE IF NOT EXISTS ingredients (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            unit TEXT NOT NULL,
-            amount FLOAT NOT NULL,
-            calories_kcal FLOAT NOT NULL,
-            protein_g FLOAT NOT NULL,
-            carbohydrates_g FLOAT NOT NULL,
-            sugar_g FLOAT NOT NULL,
-            fat_g FLOAT NOT NULL,
-            saturated_fat_g FLOAT NOT NULL,
-            fiber_g FLOAT NOT NULL,
-            potassium_mg FLOAT NOT NULL,
-            sodium_mg FLOAT NOT NULL,
-            cholesterol_mg FLOAT NOT NULL
-        )",
-    )
-    .await?;
-
-    pool.execute(
-        "CREATE TABLE IF NOT EXISTS recipes (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL UNIQUE,
-            notes TEXT,
-            instructions TEXT
-        )",
-    )
-    .await?;
-
-    pool.execute(
-        "CREATE TABLE IF NOT EXISTS recipes_tags (
-            id INTEGER PRIMARY KEY,
-            recipe_id INTEGER NOT NULL,
-            tag_id INTEGER NOT NULL,
-            FOREIGN KEY (recipe_id) REFERENCES recipes(id),
-            FOREIGN KEY (tag_id) REFERENCES tags(id),
-            CONSTRAINT unique_recipes_tags UNIQUE (recipe_id, tag_id)
-        )",
-    )
-    .await?;
-
-    pool.execute(
-        "CREATE TABLE IF NOT EXISTS recipes_ingredients (
-            id INTEGER PRIMARY KEY,
-            recipe_id INTEGER NOT NULL,
-            ingredient_id INTEGER NOT NULL,
-            amount FLOAT NOT NULL,
-            unit TEXT NOT NULL,
-            FOREIGN KEY (recipe_id) REFERENCES recipes(id),
-            FOREIGN KEY (ingredient_id) REFERENCES ingredients(id),
-            CONSTRAINT unique_recipes_ingredients UNIQUE (recipe_id, ingredient_id)
-        )",
-    )
-    .await?;
+    migration::run(pool).await?;
 
     Ok(())
 }
 
-pub async fn populate_tables(pool: &Pool<Sqlite>) -> Result<()> {
-    // pre-populate db with ingredients
-    let path = PathBuf::from("data/ingredients");
-    let file_paths = get_json_files(path)?;
-    for file_path in file_paths {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-        let ingredients: Vec<Ingredient> = serde_json::from_reader(reader)?;
-        for ingredient in ingredients {
-            ingredient::create(&pool, &ingredient).await?;
-        }
-    }
-
-    // pre-populate db with tags
-    let file_path = PathBuf::from("data/tags.json");
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let tags: Vec<Tag> = serde_json::from_reader(reader)?;
-    for tag in tags {
-        tag::create(&pool, &tag).await?;
-    }
-
-    Ok(())
+// Seed ingredients and tags from the default data directory, see `seed::run`
+// to use another one
+pub async fn populate_tables(pool: &Pool<Sqlite>) -> Result<SeedReport> {
+    seed::run(pool, Path::new(DEFAULT_DATA_DIR)).await
 }
 
 // Recursively search for all JSON files in a folder and return their paths
-fn get_json_files(path: PathBuf) -> Result<Vec<PathBuf>> {
+pub(crate) fn get_json_files(path: PathBuf) -> Result<Vec<PathBuf>> {
     let mut file_paths = Vec::new();
 
     if path.is_dir() {
@@ -134,53 +56,147 @@ fn get_json_files(path: PathBuf) -> Result<Vec<PathBuf>> {
     Ok(file_paths)
 }
 
-// Return db connection for use in subsequent queries
-pub async fn get_connection_pool(db_filename: &str) -> Pool<Sqlite> {
-    // Open a connection to the SQLite database
-    SqlitePool::connect_with(
-        SqliteConnectOptions::default()
-            .filename(db_filename)
-            .create_if_missing(true),
-    )
-    .await
-    .unwrap()
+// Connection settings for the shared pool, the defaults suit a single
+// server process with a handful of concurrent requests
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub busy_timeout: Duration,
+    pub wal: bool,
+    pub foreign_keys: bool,
 }
 
-/*
-// TODO
-#[derive(FromRow, Serialize, Deserialize, Debug)]
-struct MealPlan {
-    id: i32,
-    name: String, // meal-plan_{date}
-    recipes: Vec<recipe::Recipe>,
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_connections: 8,
+            busy_timeout: Duration::from_secs(5),
+            wal: true,
+            foreign_keys: true,
+        }
+    }
 }
 
-fn create_meal_plan() {}
-
-fn read_meal_plans() {}
-
-// from trait to convert to grocery list
-
-#[derive(Serialize, Deserialize, Debug)]
-struct GroceryListIngredient {
-    ingredient: ingredient::Ingredient,
-    amount: f32,
+// Return db connection for use in subsequent queries
+pub async fn get_connection_pool(db_filename: impl AsRef<Path>) -> Result<Pool<Sqlite>> {
+    get_connection_pool_with(db_filename, &PoolConfig::default()).await
 }
 
-#[derive(FromRow, Serialize, Deserialize, Debug)]
-struct GroceryList {
-    id: i32,
-    name: String,
-    ingredients: Vec<GroceryListIngredient>,
+pub async fn get_connection_pool_with(
+    db_filename: impl AsRef<Path>,
+    config: &PoolConfig,
+) -> Result<Pool<Sqlite>> {
+    let journal_mode = if config.wal {
+        SqliteJournalMode::Wal
+    } else {
+        SqliteJournalMode::Delete
+    };
+    let mut options = SqliteConnectOptions::default()
+        .filename(db_filename.as_ref())
+        .create_if_missing(true)
+        .journal_mode(journal_mode)
+        .busy_timeout(config.busy_timeout)
+        .foreign_keys(config.foreign_keys);
+    // every statement is logged at info by default, which drowns out the
+    // request log
+    options.log_statements(LevelFilter::Debug);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.max_connections)
+        .connect_with(options)
+        .await?;
+
+    Ok(pool)
 }
 
-fn create_grocery_list() {}
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, *};
+    use std::time::Instant;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_pool_pragmas() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+        let foreign_keys: i64 = sqlx::query_scalar("PRAGMA foreign_keys")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(foreign_keys, 1);
+        let busy_timeout: i64 = sqlx::query_scalar("PRAGMA busy_timeout")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(busy_timeout, 5000);
+
+        crud::create_tables(&pool).await.unwrap();
+        let err = sqlx::query("INSERT INTO recipes_tags (recipe_id, tag_id) VALUES (999, 999)")
+            .execute(&pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            crate::error::Error::from(err),
+            crate::error::Error::Validation(_)
+        ));
+    }
 
-fn read_grocery_lists() {}
+    // Compares opening a pool per request, as the handlers used to, against
+    // reusing one shared pool. Run with `cargo test load_test -- --ignored --nocapture`
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn load_test_shared_pool() {
+        const REQUESTS: usize = 500;
+        const CONCURRENCY: usize = 8;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path().to_str().unwrap().to_string();
+        let pool = get_connection_pool(&temp_filename).await.unwrap();
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let start = Instant::now();
+        let mut handles = Vec::new();
+        for _ in 0..CONCURRENCY {
+            let filename = temp_filename.clone();
+            handles.push(tokio::spawn(async move {
+                for _ in 0..REQUESTS / CONCURRENCY {
+                    let pool = get_connection_pool(&filename).await.unwrap();
+                    tag::read(&pool).await.unwrap();
+                    pool.close().await;
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        let per_request = REQUESTS as f64 / start.elapsed().as_secs_f64();
+
+        let start = Instant::now();
+        let mut handles = Vec::new();
+        for _ in 0..CONCURRENCY {
+            let pool = pool.clone();
+            handles.push(tokio::spawn(async move {
+                for _ in 0..REQUESTS / CONCURRENCY {
+                    tag::read(&pool).await.unwrap();
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        let shared = REQUESTS as f64 / start.elapsed().as_secs_f64();
 
-UnitConversion
-id (primary key)
-from_unit
-to_unit
-conversion_factor
- */
+        println!("pool per request: {:.0} req/s", per_request);
+        println!("shared pool:      {:.0} req/s", shared);
+        assert!(shared > per_request);
+    }
+}
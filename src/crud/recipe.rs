@@ -1,15 +1,77 @@
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, Pool, Row, Sqlite};
+use sqlx::{Executor, FromRow, Pool, QueryBuilder, Row, Sqlite, Transaction};
 
-use super::ingredient::Ingredient;
+use super::ingredient::{self, Ingredient};
+use super::meal_plan;
 use super::tag::Tag;
+use crate::error::{Error, Result};
+use crate::nutrition::NutritionFacts;
+use crate::timing::RecipeTime;
+use crate::unit::Unit;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RecipeIngredient {
     pub ingredient: Ingredient,
     pub amount: f32,
-    pub unit: String,
+    pub unit: Unit,
+}
+
+// One instruction. `section` is a heading that starts a new part of the
+// recipe at this step, e.g. "For the sauce". Durations left as None are
+// parsed from the text, see `timing::step_minutes`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "StepInput")]
+pub struct RecipeStep {
+    pub section: Option<String>,
+    pub text: String,
+    pub active_minutes: Option<f32>,
+    pub passive_minutes: Option<f32>,
+}
+
+impl From<&str> for RecipeStep {
+    fn from(text: &str) -> Self {
+        RecipeStep {
+            section: None,
+            text: text.to_string(),
+            active_minutes: None,
+            passive_minutes: None,
+        }
+    }
+}
+
+// Steps without a section can be sent as plain strings
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StepInput {
+    Text(String),
+    Step {
+        #[serde(default)]
+        section: Option<String>,
+        text: String,
+        #[serde(default)]
+        active_minutes: Option<f32>,
+        #[serde(default)]
+        passive_minutes: Option<f32>,
+    },
+}
+
+impl From<StepInput> for RecipeStep {
+    fn from(input: StepInput) -> Self {
+        match input {
+            StepInput::Text(text) => RecipeStep::from(text.as_str()),
+            StepInput::Step {
+                section,
+                text,
+                active_minutes,
+                passive_minutes,
+            } => RecipeStep {
+                section,
+                text,
+                active_minutes,
+                passive_minutes,
+            },
+        }
+    }
 }
 
 #[derive(FromRow, Serialize, Deserialize, Debug)]
@@ -19,7 +81,56 @@ pub struct Recipe {
     pub tags: Vec<Tag>,
     pub ingredients: Vec<RecipeIngredient>,
     pub notes: Vec<String>,
-    pub instructions: Vec<String>,
+    pub instructions: Vec<RecipeStep>,
+    pub servings: f32,
+    // free text yield, e.g. "1 loaf" or "24 cookies"
+    pub recipe_yield: Option<String>,
+    // computed from the steps when reading
+    #[serde(default)]
+    pub time: RecipeTime,
+}
+
+impl Recipe {
+    // Scale ingredient amounts so the recipe makes `servings` servings
+    pub fn rescale(&mut self, servings: f32) {
+        if servings <= 0.0 || self.servings <= 0.0 {
+            return;
+        }
+        let factor = servings / self.servings;
+        for ingredient in &mut self.ingredients {
+            ingredient.amount *= factor;
+        }
+        self.servings = servings;
+    }
+
+    // Multiply the recipe by `factor` for a cook to follow, unlike `rescale`
+    // the amounts are rounded and moved to friendly units (see `Unit::friendly`)
+    pub fn scale(&mut self, factor: f32) -> Result<()> {
+        if factor <= 0.0 || !factor.is_finite() {
+            return Err(Error::Validation(format!(
+                "Scale must be greater than 0, got {}",
+                factor
+            )));
+        }
+        for ingredient in &mut self.ingredients {
+            let (amount, unit) = ingredient.unit.friendly(ingredient.amount * factor);
+            ingredient.amount = amount;
+            ingredient.unit = unit;
+        }
+        self.servings *= factor;
+
+        Ok(())
+    }
+
+    pub fn scale_to_servings(&mut self, servings: f32) -> Result<()> {
+        if servings <= 0.0 || !servings.is_finite() {
+            return Err(Error::Validation(format!(
+                "Servings must be greater than 0, got {}",
+                servings
+            )));
+        }
+        self.scale(servings / self.servings)
+    }
 }
 
 #[derive(FromRow, Serialize, Deserialize, Debug)]
@@ -28,6 +139,168 @@ pub struct RecipeSimple {
     pub name: String,
 }
 
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct RecipeIngredientInput {
+    pub ingredient_id: i64,
+    pub amount: f32,
+    pub unit: Unit,
+}
+
+// Whole recipe document used to create or replace a recipe. Tags are matched
+// by id, or by name if there is no id, and created if they don't exist yet.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecipeInput {
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub ingredients: Vec<RecipeIngredientInput>,
+    #[serde(default)]
+    pub notes: Vec<String>,
+    #[serde(default)]
+    pub instructions: Vec<RecipeStep>,
+    #[serde(default = "default_servings")]
+    pub servings: f32,
+    #[serde(default)]
+    pub recipe_yield: Option<String>,
+}
+
+// Combined recipe filters, every filter that is set must match
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RecipeSearch {
+    // substring of the recipe name
+    pub name: Option<String>,
+    pub tag_ids: Vec<i64>,
+    // require every tag instead of any of them
+    pub match_all_tags: bool,
+    // recipes must use all of these ingredients
+    pub include_ingredient_ids: Vec<i64>,
+    // recipes must use none of these ingredients
+    pub exclude_ingredient_ids: Vec<i64>,
+}
+
+fn default_servings() -> f32 {
+    1.0
+}
+
+// Partial update, only the fields that are present get replaced
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RecipePatch {
+    pub name: Option<String>,
+    pub tags: Option<Vec<Tag>>,
+    pub ingredients: Option<Vec<RecipeIngredientInput>>,
+    pub notes: Option<Vec<String>>,
+    pub instructions: Option<Vec<RecipeStep>>,
+    pub servings: Option<f32>,
+    // missing leaves the yield alone, null clears it
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recipe_yield: Option<Option<String>>,
+}
+
+// Tells a field set to null apart from a missing one, which `default` covers
+fn present<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl From<Recipe> for RecipeInput {
+    fn from(recipe: Recipe) -> Self {
+        RecipeInput {
+            name: recipe.name,
+            tags: recipe.tags,
+            ingredients: recipe
+                .ingredients
+                .into_iter()
+                .filter_map(|line| {
+                    line.ingredient
+                        .id
+                        .map(|ingredient_id| RecipeIngredientInput {
+                            ingredient_id,
+                            amount: line.amount,
+                            unit: line.unit,
+                        })
+                })
+                .collect(),
+            notes: recipe.notes,
+            instructions: recipe.instructions,
+            servings: recipe.servings,
+            recipe_yield: recipe.recipe_yield,
+        }
+    }
+}
+
+impl RecipeInput {
+    pub fn apply(&mut self, patch: RecipePatch) {
+        if let Some(name) = patch.name {
+            self.name = name;
+        }
+        if let Some(tags) = patch.tags {
+            self.tags = tags;
+        }
+        if let Some(ingredients) = patch.ingredients {
+            self.ingredients = ingredients;
+        }
+        if let Some(notes) = patch.notes {
+            self.notes = notes;
+        }
+        if let Some(instructions) = patch.instructions {
+            self.instructions = instructions;
+        }
+        if let Some(servings) = patch.servings {
+            self.servings = servings;
+        }
+        if let Some(recipe_yield) = patch.recipe_yield {
+            self.recipe_yield = recipe_yield;
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(Error::Validation("Recipe name can't be empty".to_string()));
+        }
+        if self.servings <= 0.0 {
+            return Err(Error::Validation(format!(
+                "Servings must be greater than 0, got {}",
+                self.servings
+            )));
+        }
+        for (i, step) in self.instructions.iter().enumerate() {
+            if step.text.trim().is_empty() {
+                return Err(Error::Validation(format!("Step {} is empty", i + 1)));
+            }
+            for minutes in [step.active_minutes, step.passive_minutes]
+                .into_iter()
+                .flatten()
+            {
+                if minutes < 0.0 || !minutes.is_finite() {
+                    return Err(Error::Validation(format!(
+                        "Step {} has an invalid duration {}",
+                        i + 1,
+                        minutes
+                    )));
+                }
+            }
+        }
+        for line in &self.ingredients {
+            if line.amount < 0.0 {
+                return Err(Error::Validation(format!(
+                    "Ingredient {} has a negative amount {}",
+                    line.ingredient_id, line.amount
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub async fn create(pool: &Pool<Sqlite>, recipe_name: &str) -> Result<i64> {
     let result = sqlx::query(
         "INSERT OR IGNORE INTO recipes (
@@ -49,10 +322,25 @@ pub async fn read(pool: &Pool<Sqlite>) -> Result<Vec<RecipeSimple>> {
     Ok(recipes)
 }
 
-pub async fn read_by_tag_id(pool: &Pool<Sqlite>, tag_id: i64) -> Result<Vec<RecipeSimple>> {
+pub async fn read_by_tag_id<'c, E: Executor<'c, Database = Sqlite>>(
+    executor: E,
+    tag_id: i64,
+) -> Result<Vec<RecipeSimple>> {
     let recipes = sqlx::query_as::<_, RecipeSimple>(r#"SELECT recipes.id, recipes.name FROM recipes JOIN recipes_tags ON recipes.id = recipes_tags.recipe_id WHERE recipes_tags.tag_id = ?"#)
         .bind(tag_id)
-        .fetch_all(pool)
+        .fetch_all(executor)
+        .await?;
+
+    Ok(recipes)
+}
+
+pub async fn read_by_ingredient_id<'c, E: Executor<'c, Database = Sqlite>>(
+    executor: E,
+    ingredient_id: i64,
+) -> Result<Vec<RecipeSimple>> {
+    let recipes = sqlx::query_as::<_, RecipeSimple>(r#"SELECT recipes.id, recipes.name FROM recipes JOIN recipes_ingredients ON recipes.id = recipes_ingredients.recipe_id WHERE recipes_ingredients.ingredient_id = ?"#)
+        .bind(ingredient_id)
+        .fetch_all(executor)
         .await?;
 
     Ok(recipes)
@@ -72,35 +360,124 @@ pub async fn read_by_search_string(
     Ok(recipes)
 }
 
+pub async fn search(pool: &Pool<Sqlite>, search: &RecipeSearch) -> Result<Vec<RecipeSimple>> {
+    let mut query = QueryBuilder::<Sqlite>::new("SELECT id, name FROM recipes WHERE 1 = 1");
+
+    if let Some(name) = &search.name {
+        query.push(" AND name LIKE '%' || ");
+        query.push_bind(name);
+        query.push(" || '%'");
+    }
+
+    if !search.tag_ids.is_empty() {
+        query.push(" AND id IN (SELECT recipe_id FROM recipes_tags WHERE tag_id IN (");
+        let mut ids = query.separated(", ");
+        for tag_id in &search.tag_ids {
+            ids.push_bind(*tag_id);
+        }
+        query.push(")");
+        if search.match_all_tags {
+            query.push(" GROUP BY recipe_id HAVING COUNT(DISTINCT tag_id) = ");
+            query.push_bind(distinct_count(&search.tag_ids));
+        }
+        query.push(")");
+    }
+
+    if !search.include_ingredient_ids.is_empty() {
+        query
+            .push(" AND id IN (SELECT recipe_id FROM recipes_ingredients WHERE ingredient_id IN (");
+        let mut ids = query.separated(", ");
+        for ingredient_id in &search.include_ingredient_ids {
+            ids.push_bind(*ingredient_id);
+        }
+        query.push(") GROUP BY recipe_id HAVING COUNT(DISTINCT ingredient_id) = ");
+        query.push_bind(distinct_count(&search.include_ingredient_ids));
+        query.push(")");
+    }
+
+    if !search.exclude_ingredient_ids.is_empty() {
+        query.push(
+            " AND id NOT IN (SELECT recipe_id FROM recipes_ingredients WHERE ingredient_id IN (",
+        );
+        let mut ids = query.separated(", ");
+        for ingredient_id in &search.exclude_ingredient_ids {
+            ids.push_bind(*ingredient_id);
+        }
+        query.push("))");
+    }
+
+    query.push(" ORDER BY name");
+    let recipes = query
+        .build_query_as::<RecipeSimple>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(recipes)
+}
+
+fn distinct_count(ids: &[i64]) -> i64 {
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    ids.dedup();
+    ids.len() as i64
+}
+
 pub async fn read_one(pool: &Pool<Sqlite>, recipe_id: i64) -> Result<Recipe> {
     let ingredients = get_recipe_ingredients(pool, recipe_id);
     let tags = get_recipe_tags(pool, recipe_id);
     let ingredients = ingredients.await?;
     let tags = tags.await?;
+    let notes = get_recipe_notes(pool, recipe_id).await?;
+    let instructions = get_recipe_steps(pool, recipe_id).await?;
 
     let row = sqlx::query(
-        r#"SELECT *
+        r#"SELECT id, name, servings, recipe_yield
            FROM recipes
            WHERE id = ?"#,
     )
     .bind(recipe_id)
-    .fetch_one(pool)
-    .await?;
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| Error::NotFound(format!("Recipe {} not found", recipe_id)))?;
 
-    let notes: String = row.get(2);
-    let notes: Vec<String> = notes.split(";").map(|s| s.to_string()).collect();
-    let instructions: String = row.get(3);
-    let instructions: Vec<String> = instructions.split(";").map(|s| s.to_string()).collect();
     Ok(Recipe {
-        id: Some(row.get(0)),
-        name: row.get(1),
+        id: Some(row.get("id")),
+        name: row.get("name"),
         tags,
         ingredients,
         notes,
+        time: RecipeTime::from_steps(&instructions),
         instructions,
+        servings: row.get("servings"),
+        recipe_yield: row.get("recipe_yield"),
     })
 }
 
+pub async fn update_servings(
+    pool: &Pool<Sqlite>,
+    recipe_id: i64,
+    servings: f32,
+    recipe_yield: Option<&str>,
+) -> Result<()> {
+    if servings <= 0.0 {
+        return Err(Error::Validation(format!(
+            "Servings must be greater than 0, got {}",
+            servings
+        )));
+    }
+    let result = sqlx::query("UPDATE recipes SET servings = ?, recipe_yield = ? WHERE id = ?")
+        .bind(servings)
+        .bind(recipe_yield)
+        .bind(recipe_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("Recipe {} not found", recipe_id)));
+    }
+
+    Ok(())
+}
+
 pub async fn add_recipe_ingredient(
     pool: &Pool<Sqlite>,
     recipe_id: i64,
@@ -108,6 +485,8 @@ pub async fn add_recipe_ingredient(
     amount: f32,
     unit: &str,
 ) -> Result<i64> {
+    // store the canonical label so "teaspoon", "tsp" and "t" all read back the same
+    let unit: Unit = unit.parse()?;
     let result = sqlx::query(
         "INSERT OR IGNORE INTO recipes_ingredients (
             recipe_id, 
@@ -141,12 +520,255 @@ pub async fn add_recipe_tag(pool: &Pool<Sqlite>, recipe_id: i64, tag_id: i64) ->
     Ok(result.last_insert_rowid())
 }
 
+// Create a recipe with all of its tags and ingredients in one transaction
+pub async fn create_full(pool: &Pool<Sqlite>, recipe: &RecipeInput) -> Result<i64> {
+    recipe.validate()?;
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+        "INSERT INTO recipes (
+            name,
+            servings,
+            recipe_yield
+        ) VALUES (?, ?, ?)",
+    )
+    .bind(&recipe.name)
+    .bind(recipe.servings)
+    .bind(&recipe.recipe_yield)
+    .execute(&mut tx)
+    .await?;
+    let recipe_id = result.last_insert_rowid();
+    write_recipe_children(&mut tx, recipe_id, recipe).await?;
+    tx.commit().await?;
+
+    Ok(recipe_id)
+}
+
+// Replace the whole recipe, including its tags and ingredients
+pub async fn update(pool: &Pool<Sqlite>, recipe_id: i64, recipe: &RecipeInput) -> Result<()> {
+    recipe.validate()?;
+    let mut tx = pool.begin().await?;
+    write_recipe(&mut tx, recipe_id, recipe).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Read, patch and write back in one transaction so concurrent patches
+// can't overwrite each other's changes
+pub async fn patch(pool: &Pool<Sqlite>, recipe_id: i64, patch: RecipePatch) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let mut recipe = read_input(&mut tx, recipe_id).await?;
+    recipe.apply(patch);
+    recipe.validate()?;
+    write_recipe(&mut tx, recipe_id, &recipe).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Refuses to delete a recipe that meal plans still use, unless `cascade` is
+// set in which case its entries are removed from those plans as well
+pub async fn delete(pool: &Pool<Sqlite>, recipe_id: i64, cascade: bool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let meal_plans = meal_plan::read_by_recipe_id(&mut tx, recipe_id).await?;
+    if !meal_plans.is_empty() && !cascade {
+        return Err(Error::Conflict {
+            message: format!(
+                "Recipe {} is used by {} meal plan(s)",
+                recipe_id,
+                meal_plans.len()
+            ),
+            recipes: Vec::new(),
+            meal_plans,
+            grocery_lists: Vec::new(),
+        });
+    }
+
+    for query in [
+        "DELETE FROM recipes_ingredients WHERE recipe_id = ?",
+        "DELETE FROM recipes_tags WHERE recipe_id = ?",
+        "DELETE FROM recipe_steps WHERE recipe_id = ?",
+        "DELETE FROM recipe_notes WHERE recipe_id = ?",
+        "DELETE FROM meal_plan_entries WHERE recipe_id = ?",
+    ] {
+        sqlx::query(query).bind(recipe_id).execute(&mut tx).await?;
+    }
+    let result = sqlx::query("DELETE FROM recipes WHERE id = ?")
+        .bind(recipe_id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("Recipe {} not found", recipe_id)));
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// The stored recipe in the shape `update` takes
+async fn read_input(tx: &mut Transaction<'_, Sqlite>, recipe_id: i64) -> Result<RecipeInput> {
+    let row = sqlx::query("SELECT name, servings, recipe_yield FROM recipes WHERE id = ?")
+        .bind(recipe_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Recipe {} not found", recipe_id)))?;
+    let ingredients = sqlx::query_as::<_, RecipeIngredientInput>(
+        "SELECT ingredient_id, amount, unit FROM recipes_ingredients WHERE recipe_id = ?",
+    )
+    .bind(recipe_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    Ok(RecipeInput {
+        name: row.get("name"),
+        tags: get_recipe_tags(&mut *tx, recipe_id).await?,
+        ingredients,
+        notes: get_recipe_notes(&mut *tx, recipe_id).await?,
+        instructions: get_recipe_steps(&mut *tx, recipe_id).await?,
+        servings: row.get("servings"),
+        recipe_yield: row.get("recipe_yield"),
+    })
+}
+
+async fn write_recipe(
+    tx: &mut Transaction<'_, Sqlite>,
+    recipe_id: i64,
+    recipe: &RecipeInput,
+) -> Result<()> {
+    let result = sqlx::query(
+        "UPDATE recipes
+         SET name = ?, servings = ?, recipe_yield = ?
+         WHERE id = ?",
+    )
+    .bind(&recipe.name)
+    .bind(recipe.servings)
+    .bind(&recipe.recipe_yield)
+    .bind(recipe_id)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("Recipe {} not found", recipe_id)));
+    }
+    write_recipe_children(tx, recipe_id, recipe).await
+}
+
+async fn write_recipe_children(
+    tx: &mut Transaction<'_, Sqlite>,
+    recipe_id: i64,
+    recipe: &RecipeInput,
+) -> Result<()> {
+    for query in [
+        "DELETE FROM recipes_ingredients WHERE recipe_id = ?",
+        "DELETE FROM recipes_tags WHERE recipe_id = ?",
+        "DELETE FROM recipe_steps WHERE recipe_id = ?",
+        "DELETE FROM recipe_notes WHERE recipe_id = ?",
+    ] {
+        sqlx::query(query).bind(recipe_id).execute(&mut *tx).await?;
+    }
+
+    for (position, step) in recipe.instructions.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO recipe_steps (
+                recipe_id,
+                position,
+                section,
+                text,
+                active_minutes,
+                passive_minutes
+            ) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(recipe_id)
+        .bind(position as i64)
+        .bind(&step.section)
+        .bind(&step.text)
+        .bind(step.active_minutes)
+        .bind(step.passive_minutes)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for (position, note) in recipe.notes.iter().enumerate() {
+        sqlx::query("INSERT INTO recipe_notes (recipe_id, position, text) VALUES (?, ?, ?)")
+            .bind(recipe_id)
+            .bind(position as i64)
+            .bind(note)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    for line in &recipe.ingredients {
+        let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM ingredients WHERE id = ?")
+            .bind(line.ingredient_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if exists.is_none() {
+            return Err(Error::Validation(format!(
+                "Ingredient {} does not exist",
+                line.ingredient_id
+            )));
+        }
+        sqlx::query(
+            "INSERT INTO recipes_ingredients (
+                recipe_id,
+                ingredient_id,
+                amount,
+                unit
+            ) VALUES (?, ?, ?, ?)",
+        )
+        .bind(recipe_id)
+        .bind(line.ingredient_id)
+        .bind(line.amount)
+        .bind(line.unit)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for tag in &recipe.tags {
+        let tag_id: Option<i64> = match tag.id {
+            Some(id) => {
+                sqlx::query_scalar("SELECT id FROM tags WHERE id = ?")
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+            }
+            None => {
+                sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+                    .bind(&tag.name)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query_scalar("SELECT id FROM tags WHERE name = ?")
+                    .bind(&tag.name)
+                    .fetch_optional(&mut *tx)
+                    .await?
+            }
+        };
+        let tag_id = match tag_id {
+            Some(tag_id) => tag_id,
+            None => {
+                return Err(Error::Validation(format!(
+                    "Tag {:?} does not exist",
+                    tag.id
+                )))
+            }
+        };
+        sqlx::query("INSERT OR IGNORE INTO recipes_tags (recipe_id, tag_id) VALUES (?, ?)")
+            .bind(recipe_id)
+            .bind(tag_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
 async fn get_recipe_ingredients(
     pool: &Pool<Sqlite>,
     recipe_id: i64,
 ) -> Result<Vec<RecipeIngredient>> {
-    let result = sqlx::query(
-        r#"SELECT recipes_ingredients.amount, recipes_ingredients.unit, ingredients.*
+    let mut result = sqlx::query(
+        r#"SELECT recipes_ingredients.amount, recipes_ingredients.unit, ingredients.id,
+                  ingredients.name, ingredients.unit, ingredients.amount,
+                  ingredients.grams_per_unit, ingredients.source, ingredients.fdc_id
            FROM recipes_ingredients
            JOIN ingredients ON recipes_ingredients.ingredient_id = ingredients.id
            WHERE recipes_ingredients.recipe_id = ?"#,
@@ -158,16 +780,10 @@ async fn get_recipe_ingredients(
             name: row.get(3),
             unit: row.get(4),
             amount: row.get(5),
-            calories_kcal: row.get(6),
-            protein_g: row.get(7),
-            carbohydrates_g: row.get(8),
-            sugar_g: row.get(9),
-            fat_g: row.get(10),
-            saturated_fat_g: row.get(11),
-            fiber_g: row.get(12),
-            potassium_mg: row.get(13),
-            sodium_mg: row.get(14),
-            cholesterol_mg: row.get(15),
+            nutrients: NutritionFacts::default(),
+            grams_per_unit: row.get(6),
+            source: row.get(7),
+            fdc_id: row.get(8),
         };
         RecipeIngredient {
             ingredient,
@@ -177,11 +793,59 @@ async fn get_recipe_ingredients(
     })
     .fetch_all(pool)
     .await?;
+    for line in &mut result {
+        if let Some(id) = line.ingredient.id {
+            line.ingredient.nutrients = ingredient::read_nutrients(pool, id).await?;
+        }
+    }
 
     Ok(result)
 }
 
-async fn get_recipe_tags(pool: &Pool<Sqlite>, recipe_id: i64) -> Result<Vec<Tag>> {
+pub(crate) async fn get_recipe_steps<'c, E: Executor<'c, Database = Sqlite>>(
+    executor: E,
+    recipe_id: i64,
+) -> Result<Vec<RecipeStep>> {
+    let result = sqlx::query(
+        r#"SELECT section, text, active_minutes, passive_minutes
+           FROM recipe_steps
+           WHERE recipe_id = ?
+           ORDER BY position"#,
+    )
+    .bind(recipe_id)
+    .map(|row: sqlx::sqlite::SqliteRow| RecipeStep {
+        section: row.get("section"),
+        text: row.get("text"),
+        active_minutes: row.get("active_minutes"),
+        passive_minutes: row.get("passive_minutes"),
+    })
+    .fetch_all(executor)
+    .await?;
+
+    Ok(result)
+}
+
+async fn get_recipe_notes<'c, E: Executor<'c, Database = Sqlite>>(
+    executor: E,
+    recipe_id: i64,
+) -> Result<Vec<String>> {
+    let result = sqlx::query_scalar(
+        r#"SELECT text
+           FROM recipe_notes
+           WHERE recipe_id = ?
+           ORDER BY position"#,
+    )
+    .bind(recipe_id)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(result)
+}
+
+async fn get_recipe_tags<'c, E: Executor<'c, Database = Sqlite>>(
+    executor: E,
+    recipe_id: i64,
+) -> Result<Vec<Tag>> {
     let result = sqlx::query(
         r#"SELECT tags.id, tags.name
            FROM recipes_tags
@@ -193,7 +857,7 @@ async fn get_recipe_tags(pool: &Pool<Sqlite>, recipe_id: i64) -> Result<Vec<Tag>
         id: Some(row.get(0)),
         name: row.get(1),
     })
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await?;
 
     Ok(result)
@@ -208,7 +872,9 @@ mod tests {
     async fn test_simple_crud() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
@@ -228,11 +894,345 @@ mod tests {
         let _recipes = read(&pool).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_add_recipe_ingredient_unit() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+        let recipe_id = create(&pool, "test_recipe").await.unwrap();
+
+        add_recipe_ingredient(&pool, recipe_id, 1, 2.0, "teaspoons")
+            .await
+            .unwrap();
+        assert!(add_recipe_ingredient(&pool, recipe_id, 2, 1.0, "handful")
+            .await
+            .is_err());
+
+        let ingredients = get_recipe_ingredients(&pool, recipe_id).await.unwrap();
+        assert_eq!(ingredients.len(), 1);
+        assert_eq!(ingredients[0].unit, Unit::Teaspoon);
+    }
+
+    #[tokio::test]
+    async fn test_servings() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+        let recipe_id = create(&pool, "test_recipe").await.unwrap();
+        add_recipe_ingredient(&pool, recipe_id, 1, 2.0, "cup")
+            .await
+            .unwrap();
+
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.servings, 1.0);
+        assert!(recipe.recipe_yield.is_none());
+
+        update_servings(&pool, recipe_id, 4.0, Some("1 loaf"))
+            .await
+            .unwrap();
+        assert!(update_servings(&pool, recipe_id, 0.0, None).await.is_err());
+
+        let mut recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.servings, 4.0);
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("1 loaf"));
+
+        recipe.rescale(6.0);
+        assert_eq!(recipe.servings, 6.0);
+        assert_eq!(recipe.ingredients[0].amount, 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_scale() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+        let mut input = recipe_input("tacos");
+        input.ingredients = vec![
+            RecipeIngredientInput {
+                ingredient_id: 1,
+                amount: 0.25,
+                unit: Unit::Cup,
+            },
+            RecipeIngredientInput {
+                ingredient_id: 2,
+                amount: 2.0,
+                unit: Unit::Clove,
+            },
+        ];
+        let recipe_id = create_full(&pool, &input).await.unwrap();
+
+        // 4 to 7 servings
+        let mut recipe = read_one(&pool, recipe_id).await.unwrap();
+        recipe.scale_to_servings(7.0).unwrap();
+        assert_eq!(recipe.servings, 7.0);
+        assert_eq!(recipe.ingredients[0].amount, 7.0);
+        assert_eq!(recipe.ingredients[0].unit, Unit::Tablespoon);
+        assert_eq!(recipe.ingredients[1].amount, 4.0);
+
+        let mut recipe = read_one(&pool, recipe_id).await.unwrap();
+        recipe.scale(0.5).unwrap();
+        assert_eq!(recipe.servings, 2.0);
+        assert_eq!(recipe.ingredients[0].amount, 2.0);
+        assert_eq!(recipe.ingredients[0].unit, Unit::Tablespoon);
+        assert_eq!(recipe.ingredients[1].amount, 1.0);
+
+        assert!(recipe.scale(0.0).is_err());
+        assert!(recipe.scale_to_servings(-1.0).is_err());
+    }
+
+    fn recipe_input(name: &str) -> RecipeInput {
+        RecipeInput {
+            name: name.to_string(),
+            tags: vec![
+                Tag {
+                    id: Some(1),
+                    name: String::new(),
+                },
+                Tag {
+                    id: None,
+                    name: "weeknight".to_string(),
+                },
+            ],
+            ingredients: vec![
+                RecipeIngredientInput {
+                    ingredient_id: 1,
+                    amount: 2.0,
+                    unit: Unit::Cup,
+                },
+                RecipeIngredientInput {
+                    ingredient_id: 2,
+                    amount: 1.0,
+                    unit: Unit::Teaspoon,
+                },
+            ],
+            notes: vec!["keeps for 3 days".to_string()],
+            instructions: vec!["boil".into(), "serve".into()],
+            servings: 4.0,
+            recipe_yield: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_crud() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let recipe_id = create_full(&pool, &recipe_input("soup")).await.unwrap();
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.name, "soup");
+        assert_eq!(recipe.ingredients.len(), 2);
+        assert_eq!(recipe.tags.len(), 2);
+        assert!(recipe.tags.iter().any(|tag| tag.name == "weeknight"));
+        assert_eq!(
+            recipe.instructions,
+            vec![RecipeStep::from("boil"), RecipeStep::from("serve")]
+        );
+        assert_eq!(recipe.servings, 4.0);
+
+        // names are unique
+        assert!(create_full(&pool, &recipe_input("soup")).await.is_err());
+
+        let mut input = RecipeInput::from(recipe);
+        input.ingredients.pop();
+        input.tags.clear();
+        update(&pool, recipe_id, &input).await.unwrap();
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.ingredients.len(), 1);
+        assert!(recipe.tags.is_empty());
+
+        let changes = RecipePatch {
+            name: Some("stew".to_string()),
+            servings: Some(2.0),
+            ..Default::default()
+        };
+        patch(&pool, recipe_id, changes).await.unwrap();
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.name, "stew");
+        assert_eq!(recipe.servings, 2.0);
+        assert_eq!(recipe.ingredients.len(), 1);
+
+        // a missing yield is left alone, null clears it
+        let changes: RecipePatch = serde_json::from_str(r#"{"recipe_yield": "1 pot"}"#).unwrap();
+        patch(&pool, recipe_id, changes).await.unwrap();
+        let changes: RecipePatch = serde_json::from_str(r#"{"servings": 3}"#).unwrap();
+        patch(&pool, recipe_id, changes).await.unwrap();
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("1 pot"));
+        let changes: RecipePatch = serde_json::from_str(r#"{"recipe_yield": null}"#).unwrap();
+        patch(&pool, recipe_id, changes).await.unwrap();
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.recipe_yield, None);
+        assert_eq!(recipe.servings, 3.0);
+        assert_eq!(recipe.ingredients.len(), 1);
+        assert!(matches!(
+            patch(&pool, 9999, RecipePatch::default()).await,
+            Err(Error::NotFound(_))
+        ));
+
+        delete(&pool, recipe_id, false).await.unwrap();
+        assert!(read_one(&pool, recipe_id).await.is_err());
+        assert!(matches!(
+            delete(&pool, recipe_id, false).await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(get_recipe_ingredients(&pool, recipe_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_used_by_meal_plan() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        let recipe_id = create(&pool, "chili").await.unwrap();
+        let meal_plan_id = meal_plan::create(&pool, "week").await.unwrap();
+        let entry = meal_plan::MealPlanEntry {
+            id: None,
+            date: "2023-03-06".to_string(),
+            meal: meal_plan::MealSlot::Dinner,
+            recipe_id,
+            recipe_name: None,
+            servings: 2.0,
+        };
+        meal_plan::add_entry(&pool, meal_plan_id, &entry)
+            .await
+            .unwrap();
+
+        let err = delete(&pool, recipe_id, false).await.unwrap_err();
+        match err {
+            Error::Conflict { meal_plans, .. } => {
+                assert_eq!(meal_plans.len(), 1);
+                assert_eq!(meal_plans[0].id, Some(meal_plan_id));
+            }
+            err => panic!("expected a conflict, got {:?}", err),
+        }
+        assert!(read_one(&pool, recipe_id).await.is_ok());
+        assert_eq!(
+            meal_plan::read_entries(&pool, meal_plan_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        delete(&pool, recipe_id, true).await.unwrap();
+        assert!(read_one(&pool, recipe_id).await.is_err());
+        assert!(meal_plan::read_entries(&pool, meal_plan_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_steps_and_notes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let mut input: RecipeInput = serde_json::from_str(
+            r#"{
+                "name": "pasta",
+                "instructions": [
+                    {"section": "For the sauce", "text": "Fry the garlic; don't let it brown"},
+                    "Add the tomatoes",
+                    {"section": "For the pasta", "text": "Boil the pasta"}
+                ],
+                "notes": ["Keeps for 3 days; freeze the rest"]
+            }"#,
+        )
+        .unwrap();
+        let recipe_id = create_full(&pool, &input).await.unwrap();
+
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.instructions, input.instructions);
+        assert_eq!(
+            recipe.instructions[0].text,
+            "Fry the garlic; don't let it brown"
+        );
+        assert_eq!(recipe.instructions[1].section, None);
+        assert_eq!(recipe.notes, vec!["Keeps for 3 days; freeze the rest"]);
+
+        // reordering replaces the old rows
+        input.instructions.reverse();
+        input.notes.clear();
+        update(&pool, recipe_id, &input).await.unwrap();
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert_eq!(recipe.instructions[0].text, "Boil the pasta");
+        assert!(recipe.notes.is_empty());
+
+        // a recipe created by name only has neither
+        let recipe_id = create(&pool, "toast").await.unwrap();
+        let recipe = read_one(&pool, recipe_id).await.unwrap();
+        assert!(recipe.instructions.is_empty());
+        assert!(recipe.notes.is_empty());
+
+        input.instructions.push(" ".into());
+        assert!(update(&pool, recipe_id, &input).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_full_is_atomic() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let mut input = recipe_input("soup");
+        input.ingredients[1].ingredient_id = 100_000;
+        assert!(create_full(&pool, &input).await.is_err());
+        assert!(read_by_search_string(&pool, "soup")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let mut input = recipe_input("soup");
+        input.servings = 0.0;
+        assert!(create_full(&pool, &input).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_tagging_query() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
@@ -253,11 +1253,89 @@ mod tests {
         assert_eq!(recipes.len(), 3);
     }
 
+    #[tokio::test]
+    async fn test_combined_search() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        // (name, tags, ingredients)
+        let recipes: [(&str, &[i64], &[i64]); 4] = [
+            ("chicken tacos", &[1, 2], &[1, 2, 3]),
+            ("chicken soup", &[1], &[1, 4]),
+            ("beef tacos", &[2], &[2, 3, 5]),
+            ("salad", &[1, 2, 3], &[6]),
+        ];
+        for (name, tags, ingredients) in recipes {
+            let recipe_id = create(&pool, name).await.unwrap();
+            for tag_id in tags {
+                add_recipe_tag(&pool, recipe_id, *tag_id).await.unwrap();
+            }
+            for ingredient_id in ingredients {
+                add_recipe_ingredient(&pool, recipe_id, *ingredient_id, 1.0, "cup")
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let names = |recipes: Vec<RecipeSimple>| -> Vec<String> {
+            recipes.into_iter().map(|recipe| recipe.name).collect()
+        };
+
+        let all = search(&pool, &RecipeSearch::default()).await.unwrap();
+        assert_eq!(all.len(), 4);
+
+        let filters = RecipeSearch {
+            name: Some("tacos".to_string()),
+            ..Default::default()
+        };
+        let found = names(search(&pool, &filters).await.unwrap());
+        assert_eq!(found, vec!["beef tacos", "chicken tacos"]);
+
+        let filters = RecipeSearch {
+            tag_ids: vec![1, 2],
+            ..Default::default()
+        };
+        assert_eq!(search(&pool, &filters).await.unwrap().len(), 4);
+
+        let filters = RecipeSearch {
+            tag_ids: vec![1, 2, 2],
+            match_all_tags: true,
+            ..Default::default()
+        };
+        let found = names(search(&pool, &filters).await.unwrap());
+        assert_eq!(found, vec!["chicken tacos", "salad"]);
+
+        let filters = RecipeSearch {
+            include_ingredient_ids: vec![2, 3],
+            exclude_ingredient_ids: vec![5],
+            ..Default::default()
+        };
+        let found = names(search(&pool, &filters).await.unwrap());
+        assert_eq!(found, vec!["chicken tacos"]);
+
+        let filters = RecipeSearch {
+            name: Some("chicken".to_string()),
+            tag_ids: vec![1],
+            exclude_ingredient_ids: vec![4],
+            ..Default::default()
+        };
+        let found = names(search(&pool, &filters).await.unwrap());
+        assert_eq!(found, vec!["chicken tacos"]);
+    }
+
     #[tokio::test]
     async fn test_search_string_query() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
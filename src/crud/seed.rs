@@ -0,0 +1,364 @@
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::get_json_files;
+use super::ingredient::{self, Ingredient};
+use super::tag::{self, Tag};
+use crate::error::{Error, Result};
+use crate::validate;
+
+// Where a row came from. Seeded rows follow the data files, rows created or
+// edited through the API or imported (see `usda::import`) are never
+// overwritten by seeding
+#[derive(sqlx::Type, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Provenance {
+    Seed,
+    User,
+    Import,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    // rows in the data files that the user has since edited or imported
+    pub skipped: usize,
+}
+
+impl fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} unchanged, {} skipped (edited by user or imported)",
+            self.added, self.updated, self.unchanged, self.skipped
+        )
+    }
+}
+
+// Seed ingredients from `<data_dir>/ingredients/**/*.json` and tags from
+// `<data_dir>/tags.json`. Rows from files whose content hash matches the last
+// run are not written again. Nothing is written if any file fails validation
+pub async fn run(pool: &Pool<Sqlite>, data_dir: &Path) -> Result<SeedReport> {
+    let diagnostics = validate::check_data_dir(data_dir)?;
+    if !diagnostics.is_empty() {
+        let diagnostics: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        return Err(Error::Validation(format!(
+            "Invalid seed data:\n{}",
+            diagnostics.join("\n")
+        )));
+    }
+
+    let mut report = SeedReport::default();
+
+    let mut file_paths = get_json_files(data_dir.join("ingredients"))?;
+    file_paths.sort();
+    for file_path in file_paths {
+        let content = fs::read(&file_path)?;
+        let key = seed_file_key(data_dir, &file_path);
+        let ingredients: Vec<Ingredient> = serde_json::from_slice(&content)?;
+        if is_unchanged(pool, &key, &content).await? {
+            let names = ingredients.iter().map(|i| i.name.as_str());
+            count_unchanged(pool, "ingredients", names, &mut report).await?;
+            continue;
+        }
+
+        for ingredient in &ingredients {
+            seed_ingredient(pool, ingredient, &mut report).await?;
+        }
+        record_seed_file(pool, &key, &content, ingredients.len()).await?;
+    }
+
+    let file_path = data_dir.join("tags.json");
+    let content = fs::read(&file_path)?;
+    let key = seed_file_key(data_dir, &file_path);
+    let tags: Vec<Tag> = serde_json::from_slice(&content)?;
+    if is_unchanged(pool, &key, &content).await? {
+        let names = tags.iter().map(|t| t.name.as_str());
+        count_unchanged(pool, "tags", names, &mut report).await?;
+    } else {
+        for tag in &tags {
+            seed_tag(pool, tag, &mut report).await?;
+        }
+        record_seed_file(pool, &key, &content, tags.len()).await?;
+    }
+
+    Ok(report)
+}
+
+async fn seed_ingredient(
+    pool: &Pool<Sqlite>,
+    ingredient: &Ingredient,
+    report: &mut SeedReport,
+) -> Result<()> {
+    let existing: Option<(i64, Provenance)> =
+        sqlx::query_as("SELECT id, provenance FROM ingredients WHERE name = ?")
+            .bind(&ingredient.name)
+            .fetch_optional(pool)
+            .await?;
+
+    match existing {
+        None => {
+            ingredient::create_seeded(pool, ingredient).await?;
+            report.added += 1;
+        }
+        Some((_, Provenance::User | Provenance::Import)) => report.skipped += 1,
+        Some((id, Provenance::Seed)) => {
+            let current = ingredient::read_one(pool, id).await?;
+            let current = Ingredient {
+                id: ingredient.id,
+                ..current
+            };
+            if current == *ingredient {
+                report.unchanged += 1;
+            } else {
+                ingredient::update_seeded(pool, id, ingredient).await?;
+                report.updated += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Tags only have a name, so they are either missing or unchanged
+async fn seed_tag(pool: &Pool<Sqlite>, tag: &Tag, report: &mut SeedReport) -> Result<()> {
+    let existing: Option<(i64, Provenance)> =
+        sqlx::query_as("SELECT id, provenance FROM tags WHERE name = ?")
+            .bind(&tag.name)
+            .fetch_optional(pool)
+            .await?;
+
+    match existing {
+        None => {
+            tag::create_seeded(pool, tag).await?;
+            report.added += 1;
+        }
+        Some((_, Provenance::User | Provenance::Import)) => report.skipped += 1,
+        Some((_, Provenance::Seed)) => report.unchanged += 1,
+    }
+
+    Ok(())
+}
+
+fn seed_file_key(data_dir: &Path, file_path: &Path) -> String {
+    file_path
+        .strip_prefix(data_dir)
+        .unwrap_or(file_path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+// Whether the file's content is unchanged since it was last seeded
+async fn is_unchanged(pool: &Pool<Sqlite>, key: &str, content: &[u8]) -> Result<bool> {
+    let row_count: Option<i64> =
+        sqlx::query_scalar("SELECT row_count FROM seed_files WHERE path = ? AND content_hash = ?")
+            .bind(key)
+            .bind(content_hash(content))
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row_count.is_some())
+}
+
+// Rows of an unchanged file are not written, but the user may have edited
+// or deleted some of them since, so those count as skipped
+async fn count_unchanged<'a>(
+    pool: &Pool<Sqlite>,
+    table: &str,
+    names: impl Iterator<Item = &'a str>,
+    report: &mut SeedReport,
+) -> Result<()> {
+    let query = format!("SELECT provenance FROM {} WHERE name = ?", table);
+    for name in names {
+        let provenance: Option<Provenance> = sqlx::query_scalar(&query)
+            .bind(name)
+            .fetch_optional(pool)
+            .await?;
+        match provenance {
+            Some(Provenance::Seed) => report.unchanged += 1,
+            _ => report.skipped += 1,
+        }
+    }
+
+    Ok(())
+}
+
+async fn record_seed_file(
+    pool: &Pool<Sqlite>,
+    key: &str,
+    content: &[u8],
+    row_count: usize,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO seed_files (path, content_hash, row_count) VALUES (?, ?, ?)",
+    )
+    .bind(key)
+    .bind(content_hash(content))
+    .bind(row_count as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+// 64 bit FNV-1a, stable across Rust versions unlike `DefaultHasher`
+fn content_hash(content: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, get_connection_pool, ingredient, seed::*};
+    use crate::nutrition::NutritionFacts;
+    use std::path::PathBuf;
+    use tempfile::{tempdir, NamedTempFile};
+
+    fn write_ingredients(data_dir: &Path, oats_calories: f32, rice_calories: f32) {
+        let ingredients = format!(
+            r#"[
+                {{"name": "oats", "amount": 1, "unit": "cup", "calories_kcal": {},
+                  "protein_g": 10, "carbohydrates_g": 54, "sugar_g": 1, "fat_g": 5,
+                  "saturated_fat_g": 1, "fiber_g": 8, "potassium_mg": 300,
+                  "sodium_mg": 5, "cholesterol_mg": 0}},
+                {{"name": "rice", "amount": 1, "unit": "cup", "calories_kcal": {},
+                  "protein_g": 4, "carbohydrates_g": 45, "sugar_g": 0, "fat_g": 0.4,
+                  "saturated_fat_g": 0.1, "fiber_g": 0.6, "potassium_mg": 55,
+                  "sodium_mg": 2, "cholesterol_mg": 0}}
+            ]"#,
+            oats_calories, rice_calories
+        );
+        fs::write(data_dir.join("ingredients/grains.json"), ingredients).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_seed_is_idempotent_and_diff_aware() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+        crud::create_tables(&pool).await.unwrap();
+
+        let data_dir = tempdir().unwrap();
+        fs::create_dir(data_dir.path().join("ingredients")).unwrap();
+        fs::write(data_dir.path().join("tags.json"), r#"[{"name": "vegan"}]"#).unwrap();
+        write_ingredients(data_dir.path(), 300.0, 200.0);
+
+        let report = run(&pool, data_dir.path()).await.unwrap();
+        assert_eq!(report.added, 3);
+
+        let report = run(&pool, data_dir.path()).await.unwrap();
+        assert_eq!(
+            report,
+            SeedReport {
+                unchanged: 3,
+                ..Default::default()
+            }
+        );
+
+        // the user edits rice through the API, then both change in the file
+        let rice = ingredient::read_by_name(&pool, "rice")
+            .await
+            .unwrap()
+            .unwrap();
+        let rice_id = rice.id.unwrap();
+        ingredient::update(
+            &pool,
+            rice_id,
+            &Ingredient {
+                nutrients: NutritionFacts {
+                    calories_kcal: Some(210.0),
+                    ..rice.nutrients
+                },
+                ..rice
+            },
+        )
+        .await
+        .unwrap();
+        write_ingredients(data_dir.path(), 310.0, 250.0);
+
+        let report = run(&pool, data_dir.path()).await.unwrap();
+        assert_eq!(
+            report,
+            SeedReport {
+                added: 0,
+                updated: 1,
+                unchanged: 1,
+                skipped: 1,
+            }
+        );
+        let oats = ingredient::read_by_name(&pool, "oats")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(oats.nutrients.calories_kcal, Some(310.0));
+        let rice = ingredient::read_one(&pool, rice_id).await.unwrap();
+        assert_eq!(rice.nutrients.calories_kcal, Some(210.0));
+
+        // the file is unchanged now, but rice still belongs to the user
+        let report = run(&pool, data_dir.path()).await.unwrap();
+        assert_eq!(
+            report,
+            SeedReport {
+                added: 0,
+                updated: 0,
+                unchanged: 2,
+                skipped: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_seed_repo_data() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+        crud::create_tables(&pool).await.unwrap();
+
+        let data_dir = PathBuf::from("data");
+        let first = run(&pool, &data_dir).await.unwrap();
+        assert!(first.added > 0);
+        let second = run(&pool, &data_dir).await.unwrap();
+        assert_eq!(second.added, 0);
+        assert_eq!(second.updated, 0);
+        assert_eq!(second.unchanged, first.added);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_data_is_not_seeded() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+        crud::create_tables(&pool).await.unwrap();
+
+        let data_dir = tempdir().unwrap();
+        fs::create_dir(data_dir.path().join("ingredients")).unwrap();
+        fs::write(data_dir.path().join("tags.json"), r#"[{"name": "vegan"}]"#).unwrap();
+        write_ingredients(data_dir.path(), -300.0, 200.0);
+
+        let err = run(&pool, data_dir.path()).await.unwrap_err();
+        assert!(err.to_string().contains("grains.json:2:"));
+        assert!(ingredient::read(&pool).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(b""), "cbf29ce484222325");
+        assert_ne!(content_hash(b"[1]"), content_hash(b"[2]"));
+    }
+}
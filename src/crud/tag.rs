@@ -1,7 +1,10 @@
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Pool, Sqlite};
 
+use super::recipe;
+use super::seed::Provenance;
+use crate::error::{Error, Result};
+
 #[derive(FromRow, Serialize, Deserialize, Debug)]
 pub struct Tag {
     pub id: Option<i64>,
@@ -9,12 +12,23 @@ pub struct Tag {
 }
 
 pub async fn create(pool: &Pool<Sqlite>, tag: &Tag) -> Result<i64> {
+    insert(pool, tag, Provenance::User).await
+}
+
+// Only for rows coming from data/tags.json, see `seed::run`
+pub async fn create_seeded(pool: &Pool<Sqlite>, tag: &Tag) -> Result<i64> {
+    insert(pool, tag, Provenance::Seed).await
+}
+
+async fn insert(pool: &Pool<Sqlite>, tag: &Tag, provenance: Provenance) -> Result<i64> {
     let result = sqlx::query(
-        "INSERT OR IGNORE INTO tags (
-            name
-        ) VALUES (?)",
+        "INSERT INTO tags (
+            name,
+            provenance
+        ) VALUES (?, ?)",
     )
     .bind(&tag.name)
+    .bind(provenance)
     .execute(pool)
     .await?;
 
@@ -39,6 +53,15 @@ pub async fn read_by_search_string(pool: &Pool<Sqlite>, search_string: &str) ->
     Ok(tags)
 }
 
+pub async fn read_by_name(pool: &Pool<Sqlite>, name: &str) -> Result<Option<Tag>> {
+    let tag = sqlx::query_as::<_, Tag>(r#"SELECT * FROM tags WHERE name = ?"#)
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(tag)
+}
+
 pub async fn read_one(pool: &Pool<Sqlite>, id: i64) -> Result<Tag> {
     let tag = sqlx::query_as::<_, Tag>(
         r#"
@@ -48,10 +71,56 @@ pub async fn read_one(pool: &Pool<Sqlite>, id: i64) -> Result<Tag> {
         "#,
     )
     .bind(id)
-    .fetch_one(pool)
+    .fetch_optional(pool)
     .await?;
 
-    Ok(tag)
+    tag.ok_or_else(|| Error::NotFound(format!("Tag {} not found", id)))
+}
+
+// Marks the row as edited by the user so seeding leaves it alone
+pub async fn update(pool: &Pool<Sqlite>, id: i64, tag: &Tag) -> Result<()> {
+    let result = sqlx::query("UPDATE tags SET name = ?, provenance = ? WHERE id = ?")
+        .bind(&tag.name)
+        .bind(Provenance::User)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("Tag {} not found", id)));
+    }
+
+    Ok(())
+}
+
+// Refuses to delete a tag that recipes still use, unless `cascade` is set
+// in which case it is removed from those recipes as well
+pub async fn delete(pool: &Pool<Sqlite>, id: i64, cascade: bool) -> Result<()> {
+    // check in the same transaction so nothing can start using it in between
+    let mut tx = pool.begin().await?;
+    let recipes = recipe::read_by_tag_id(&mut tx, id).await?;
+    if !recipes.is_empty() && !cascade {
+        return Err(Error::Conflict {
+            message: format!("Tag {} is used by {} recipe(s)", id, recipes.len()),
+            recipes,
+            meal_plans: Vec::new(),
+            grocery_lists: Vec::new(),
+        });
+    }
+
+    sqlx::query("DELETE FROM recipes_tags WHERE tag_id = ?")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    let result = sqlx::query("DELETE FROM tags WHERE id = ?")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound(format!("Tag {} not found", id)));
+    }
+    tx.commit().await?;
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -63,7 +132,9 @@ mod tests {
     async fn test_create() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
@@ -78,23 +149,75 @@ mod tests {
         assert_eq!(tag.name, "test_tag".to_string());
     }
 
+    #[tokio::test]
+    async fn test_update_and_delete() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let tag = Tag {
+            id: None,
+            name: "vegan".to_string(),
+        };
+        assert!(create(&pool, &tag).await.is_err());
+
+        let tag_id = create(
+            &pool,
+            &Tag {
+                id: None,
+                name: "test_tag".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let renamed = Tag {
+            id: None,
+            name: "renamed".to_string(),
+        };
+        update(&pool, tag_id, &renamed).await.unwrap();
+        assert_eq!(read_one(&pool, tag_id).await.unwrap().name, "renamed");
+
+        let recipe_id = recipe::create(&pool, "test_recipe").await.unwrap();
+        recipe::add_recipe_tag(&pool, recipe_id, tag_id)
+            .await
+            .unwrap();
+        let err = delete(&pool, tag_id, false).await.unwrap_err();
+        assert!(matches!(err, Error::Conflict { recipes, .. } if recipes.len() == 1));
+
+        delete(&pool, tag_id, true).await.unwrap();
+        assert!(read_one(&pool, tag_id).await.is_err());
+        assert!(recipe::read_by_tag_id(&pool, tag_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_read() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
         let items = read(&pool).await.unwrap();
-        assert!(items.len() > 0);
+        assert!(!items.is_empty());
     }
 
     #[tokio::test]
     async fn test_search_string_query() {
         let temp_file = NamedTempFile::new().unwrap();
         let temp_filename = temp_file.path();
-        let pool = get_connection_pool(temp_filename.to_str().unwrap()).await;
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
 
         crud::create_tables(&pool).await.unwrap();
         crud::populate_tables(&pool).await.unwrap();
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+use std::collections::HashMap;
+
+use super::ingredient::Ingredient;
+use super::recipe::Recipe;
+use crate::error::Result;
+use crate::unit::{Unit, UnitKind};
+
+// 1 `from_unit` of the ingredient weighs/measures `conversion_factor` `to_unit`,
+// e.g. (flour, cup, g, 125.0) or (garlic, clove, g, 3.0)
+#[derive(FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct UnitConversion {
+    pub id: Option<i64>,
+    pub ingredient_id: i64,
+    pub from_unit: Unit,
+    pub to_unit: Unit,
+    pub conversion_factor: f32,
+}
+
+pub async fn create(pool: &Pool<Sqlite>, conversion: &UnitConversion) -> Result<i64> {
+    let result = sqlx::query(
+        "INSERT OR REPLACE INTO unit_conversions (
+            ingredient_id,
+            from_unit,
+            to_unit,
+            conversion_factor
+        ) VALUES (?, ?, ?, ?)",
+    )
+    .bind(conversion.ingredient_id)
+    .bind(conversion.from_unit)
+    .bind(conversion.to_unit)
+    .bind(conversion.conversion_factor)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn read_by_ingredient_id(
+    pool: &Pool<Sqlite>,
+    ingredient_id: i64,
+) -> Result<Vec<UnitConversion>> {
+    let conversions = sqlx::query_as::<_, UnitConversion>(
+        r#"SELECT * FROM unit_conversions WHERE ingredient_id = ?"#,
+    )
+    .bind(ingredient_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(conversions)
+}
+
+// Build the converter for an ingredient from its grams_per_unit and any
+// extra rows in the unit_conversions table
+pub async fn read_converter(pool: &Pool<Sqlite>, ingredient: &Ingredient) -> Result<UnitConverter> {
+    let conversions = match ingredient.id {
+        Some(id) => read_by_ingredient_id(pool, id).await?,
+        None => Vec::new(),
+    };
+    Ok(UnitConverter::new(ingredient, &conversions))
+}
+
+// Load the converters for every ingredient in the recipe, keyed by ingredient id
+pub async fn read_recipe_converters(
+    pool: &Pool<Sqlite>,
+    recipe: &Recipe,
+) -> Result<HashMap<i64, UnitConverter>> {
+    let mut converters = HashMap::new();
+    for line in &recipe.ingredients {
+        if let Some(id) = line.ingredient.id {
+            let converter = read_converter(pool, &line.ingredient).await?;
+            converters.insert(id, converter);
+        }
+    }
+
+    Ok(converters)
+}
+
+// Volume and mass units are freely convertible within their own kind, each
+// count unit (clove, tortilla, ...) is its own dimension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dimension {
+    Volume,
+    Mass,
+    Count(Unit),
+}
+
+impl From<Unit> for Dimension {
+    fn from(unit: Unit) -> Self {
+        match unit.kind() {
+            UnitKind::Volume => Dimension::Volume,
+            UnitKind::Mass => Dimension::Mass,
+            UnitKind::Count => Dimension::Count(unit),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UnitConverter {
+    // (a, b, factor): 1 base unit of a == factor base units of b
+    edges: Vec<(Dimension, Dimension, f32)>,
+}
+
+impl UnitConverter {
+    pub fn new(ingredient: &Ingredient, conversions: &[UnitConversion]) -> Self {
+        let mut converter = UnitConverter::default();
+        if let Some(grams) = ingredient.grams_per_unit {
+            converter.add(ingredient.unit, Unit::Gram, grams);
+        }
+        for conversion in conversions {
+            converter.add(
+                conversion.from_unit,
+                conversion.to_unit,
+                conversion.conversion_factor,
+            );
+        }
+        converter
+    }
+
+    // Register that 1 `from` equals `factor` `to`
+    pub fn add(&mut self, from: Unit, to: Unit, factor: f32) {
+        let (a, b) = (Dimension::from(from), Dimension::from(to));
+        if a == b || factor <= 0.0 {
+            return;
+        }
+        let factor = factor * to.base_amount() / from.base_amount();
+        self.edges.push((a, b, factor));
+        self.edges.push((b, a, 1.0 / factor));
+    }
+
+    // Convert between any two units reachable through the registered conversions,
+    // e.g. tsp -> g -> clove
+    pub fn convert(&self, amount: f32, from: Unit, to: Unit) -> Option<f32> {
+        if let Some(converted) = from.convert(amount, to) {
+            return Some(converted);
+        }
+
+        let (start, goal) = (Dimension::from(from), Dimension::from(to));
+        // breadth first search, the graph only has a handful of nodes
+        let mut visited = vec![start];
+        let mut queue = vec![(start, 1.0_f32)];
+        while !queue.is_empty() {
+            let (node, ratio) = queue.remove(0);
+            if node == goal {
+                return Some(amount * from.base_amount() * ratio / to.base_amount());
+            }
+            for (a, b, factor) in &self.edges {
+                if *a == node && !visited.contains(b) {
+                    visited.push(*b);
+                    queue.push((*b, ratio * factor));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, get_connection_pool, ingredient, unit_conversion::*};
+    use tempfile::NamedTempFile;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    #[tokio::test]
+    async fn test_create_and_convert() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        // garlic is seeded as 1 clove with 3g per clove
+        let garlic = ingredient::read(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == "garlic")
+            .unwrap();
+        let conversion = UnitConversion {
+            id: None,
+            ingredient_id: garlic.id.unwrap(),
+            from_unit: Unit::Tablespoon,
+            to_unit: Unit::Gram,
+            conversion_factor: 9.0,
+        };
+        create(&pool, &conversion).await.unwrap();
+        let conversions = read_by_ingredient_id(&pool, garlic.id.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(conversions.len(), 1);
+
+        let converter = read_converter(&pool, &garlic).await.unwrap();
+        assert_close(
+            converter.convert(2.0, Unit::Clove, Unit::Gram).unwrap(),
+            6.0,
+        );
+        assert_close(
+            converter.convert(1.0, Unit::Ounce, Unit::Clove).unwrap(),
+            9.45,
+        );
+        // volume -> mass -> count
+        assert_close(
+            converter
+                .convert(1.0, Unit::Tablespoon, Unit::Clove)
+                .unwrap(),
+            3.0,
+        );
+        assert_close(
+            converter.convert(1.0, Unit::Teaspoon, Unit::Clove).unwrap(),
+            1.0,
+        );
+        assert!(converter
+            .convert(1.0, Unit::Clove, Unit::Tortilla)
+            .is_none());
+    }
+
+    #[test]
+    fn test_convert_without_conversions() {
+        let converter = UnitConverter::default();
+        assert_close(
+            converter
+                .convert(3.0, Unit::Teaspoon, Unit::Tablespoon)
+                .unwrap(),
+            1.0,
+        );
+        assert!(converter.convert(1.0, Unit::Cup, Unit::Gram).is_none());
+    }
+
+    #[test]
+    fn test_density() {
+        let mut converter = UnitConverter::default();
+        // roughly all-purpose flour
+        converter.add(Unit::Cup, Unit::Gram, 125.0);
+        assert_close(
+            converter.convert(2.0, Unit::Cup, Unit::Gram).unwrap(),
+            250.0,
+        );
+        assert_close(
+            converter.convert(250.0, Unit::Gram, Unit::Cup).unwrap(),
+            2.0,
+        );
+        assert_close(
+            converter.convert(1.0, Unit::Pound, Unit::Cup).unwrap(),
+            3.63,
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+use std::fmt;
+
+use crate::crud::grocery_list::GroceryListSimple;
+use crate::crud::meal_plan::MealPlanSimple;
+use crate::crud::recipe::RecipeSimple;
+use crate::unit::UnknownUnitError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    NotFound(String),
+    // e.g. a duplicate name, or a row that recipes, meal plans or grocery
+    // lists still reference
+    Conflict {
+        message: String,
+        recipes: Vec<RecipeSimple>,
+        meal_plans: Vec<MealPlanSimple>,
+        grocery_lists: Vec<GroceryListSimple>,
+    },
+    Validation(String),
+    Database(sqlx::Error),
+    Io(std::io::Error),
+}
+
+impl Error {
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Error::Conflict {
+            message: message.into(),
+            recipes: Vec::new(),
+            meal_plans: Vec::new(),
+            grocery_lists: Vec::new(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::Conflict { .. } => "conflict",
+            Error::Validation(_) => "validation",
+            Error::Database(_) => "database",
+            Error::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(message) => write!(f, "{}", message),
+            Error::Conflict { message, .. } => write!(f, "{}", message),
+            Error::Validation(message) => write!(f, "{}", message),
+            Error::Database(err) => write!(f, "Database error: {}", err),
+            Error::Io(err) => write!(f, "IO error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => Error::NotFound("Row not found".to_string()),
+            sqlx::Error::Database(db_err) if db_err.message().contains("UNIQUE") => {
+                Error::conflict(db_err.message())
+            }
+            // e.g. a meal plan entry pointing at a recipe that doesn't exist
+            sqlx::Error::Database(db_err) if db_err.message().contains("FOREIGN KEY") => {
+                Error::Validation(db_err.message().to_string())
+            }
+            err => Error::Database(err),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Validation(err.to_string())
+    }
+}
+
+impl From<UnknownUnitError> for Error {
+    fn from(err: UnknownUnitError) -> Self {
+        Error::Validation(err.to_string())
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict { .. } => StatusCode::CONFLICT,
+            Error::Validation(_) => StatusCode::BAD_REQUEST,
+            Error::Database(_) | Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = json!({
+            "error": self.kind(),
+            "message": self.to_string(),
+        });
+        if let Error::Conflict {
+            recipes,
+            meal_plans,
+            grocery_lists,
+            ..
+        } = self
+        {
+            if !recipes.is_empty() {
+                body["recipes"] = json!(recipes);
+            }
+            if !meal_plans.is_empty() {
+                body["meal_plans"] = json!(meal_plans);
+            }
+            if !grocery_lists.is_empty() {
+                body["grocery_lists"] = json!(grocery_lists);
+            }
+        }
+        HttpResponse::build(self.status_code()).json(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::*;
+    use actix_web::body::to_bytes;
+
+    #[tokio::test]
+    async fn test_error_response() {
+        let err = Error::Conflict {
+            message: "Tag 1 is used by 1 recipe(s)".to_string(),
+            recipes: vec![RecipeSimple {
+                id: Some(3),
+                name: "tacos".to_string(),
+            }],
+            meal_plans: Vec::new(),
+            grocery_lists: Vec::new(),
+        };
+        let response = err.error_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "conflict");
+        assert_eq!(body["recipes"][0]["name"], "tacos");
+        assert!(body.get("grocery_lists").is_none());
+
+        let err = Error::from(sqlx::Error::RowNotFound);
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+        let err = Error::from("handful".parse::<crate::unit::Unit>().unwrap_err());
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
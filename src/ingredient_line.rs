@@ -0,0 +1,1204 @@
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use std::collections::HashSet;
+
+use crate::crud::ingredient;
+use crate::error::Result;
+use crate::unit::Unit;
+
+// Matches below this confidence are only suggestions, not used on import
+pub const MATCH_THRESHOLD: f32 = 0.5;
+
+// Measures without a `Unit`, as (singular, plural)
+const MEASURES: &[(&str, &str)] = &[
+    ("pinch", "pinches"),
+    ("dash", "dashes"),
+    ("splash", "splashes"),
+    ("drizzle", "drizzles"),
+    ("handful", "handfuls"),
+    ("sprig", "sprigs"),
+    ("bunch", "bunches"),
+    ("stick", "sticks"),
+    ("head", "heads"),
+    ("stalk", "stalks"),
+    ("package", "packages"),
+    ("packet", "packets"),
+    ("jar", "jars"),
+    ("bottle", "bottles"),
+    ("bag", "bags"),
+    ("box", "boxes"),
+];
+
+// Words that describe how an ingredient is prepared, they go into the note
+const PREPARATIONS: &[&str] = &[
+    "beaten",
+    "chilled",
+    "chopped",
+    "cooked",
+    "cored",
+    "crumbled",
+    "crushed",
+    "cubed",
+    "deveined",
+    "diced",
+    "divided",
+    "drained",
+    "grated",
+    "halved",
+    "juiced",
+    "julienned",
+    "mashed",
+    "melted",
+    "minced",
+    "packed",
+    "peeled",
+    "pitted",
+    "quartered",
+    "rinsed",
+    "seeded",
+    "separated",
+    "shredded",
+    "sifted",
+    "sliced",
+    "softened",
+    "toasted",
+    "trimmed",
+    "zested",
+];
+
+// Sizes and measuring hints, "2 large eggs" or "1 heaping tbsp sugar"
+const SIZES: &[&str] = &[
+    "small",
+    "medium",
+    "large",
+    "extra-large",
+    "jumbo",
+    "heaping",
+    "heaped",
+    "level",
+    "scant",
+    "generous",
+];
+
+// Adverbs that go into the note with the word after them, "finely chopped"
+// or "freshly ground"
+const ADVERBS: &[&str] = &[
+    "finely", "thinly", "roughly", "coarsely", "freshly", "lightly", "firmly", "loosely", "very",
+];
+
+// Phrases at the end of a line that say how much to use instead of an amount
+const AMOUNT_PHRASES: &[&str] = &[
+    "or to taste",
+    "to taste",
+    "as needed",
+    "if needed",
+    "optional",
+    "for garnish",
+    "for serving",
+    "for frying",
+];
+
+// A free text ingredient line like "2 1/2 cups chopped yellow onion, divided"
+// split into its parts
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IngredientLine {
+    pub amount: Option<f32>,
+    // upper end of a range like "1-2 tsp", `amount` is the lower end
+    pub amount_max: Option<f32>,
+    pub unit: Option<Unit>,
+    // a measure that is not a `Unit`, e.g. "pinch" or "bunch"
+    pub measure: Option<String>,
+    pub name: String,
+    // preparation, sizes, parentheticals and what follows the first comma,
+    // e.g. "chopped, divided"
+    pub note: Option<String>,
+}
+
+// A catalog ingredient an `IngredientLine` may refer to, confidence is 1.0
+// for the same name and lower the fewer words match
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IngredientMatch {
+    pub id: i64,
+    pub name: String,
+    pub confidence: f32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ParsedIngredient {
+    #[serde(flatten)]
+    pub line: IngredientLine,
+    // best first
+    pub candidates: Vec<IngredientMatch>,
+}
+
+impl ParsedIngredient {
+    // The best candidate if it is confident enough to use without asking
+    pub fn best_match(&self) -> Option<&IngredientMatch> {
+        self.candidates
+            .first()
+            .filter(|candidate| candidate.confidence >= MATCH_THRESHOLD)
+    }
+}
+
+// Parse a line like "2 1/2 cups chopped yellow onion, divided", "1-2 tsp
+// salt", "1 (14 oz) can diced tomatoes", "½ cup milk" or "black pepper to
+// taste". Nothing is ever rejected, whatever is not an amount, unit or note
+// ends up in the name
+pub fn parse(text: &str) -> IngredientLine {
+    let mut notes = Vec::new();
+    let text = normalize(text);
+
+    // "(14 oz)" or "(softened)" anywhere in the line
+    let mut rest = String::new();
+    let mut depth = 0;
+    let mut parenthetical = String::new();
+    for c in text.chars() {
+        match c {
+            '(' => {
+                if depth > 0 {
+                    parenthetical.push(c);
+                }
+                depth += 1;
+            }
+            ')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    notes.push(std::mem::take(&mut parenthetical));
+                    rest.push(' ');
+                } else {
+                    parenthetical.push(c);
+                }
+            }
+            c if depth > 0 => parenthetical.push(c),
+            c => rest.push(c),
+        }
+    }
+    // an unclosed parenthesis is kept as text
+    rest.push_str(&parenthetical);
+
+    let (head, comma_note) = match rest.split_once(',') {
+        Some((head, note)) => (head.to_string(), Some(note.to_string())),
+        None => (rest, None),
+    };
+    let mut head = head.trim().to_string();
+    let mut amount_phrase = None;
+    for phrase in AMOUNT_PHRASES {
+        if let Some(start) = head.to_ascii_lowercase().strip_suffix(phrase).map(str::len) {
+            // "salt and pepper to taste", not "potatoes"
+            if start == 0 || head[..start].ends_with(' ') {
+                amount_phrase = Some(phrase.trim_start_matches("or ").to_string());
+                head.truncate(start);
+                break;
+            }
+        }
+    }
+
+    let words = tokenize(&head);
+    let mut i = 0;
+    let mut amount = None;
+    let mut amount_max = None;
+    if let Some((value, next)) = number_at(&words, 0) {
+        amount = Some(value);
+        i = next;
+        // "1-2", "1 to 2" or "1 or 2"
+        if let Some(separator) = words.get(i) {
+            if ["-", "to", "or"].contains(&separator.to_lowercase().as_str()) {
+                if let Some((max, next)) = number_at(&words, i + 1) {
+                    if max > value {
+                        amount_max = Some(max);
+                        i = next;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut descriptors = Vec::new();
+    // sizes can come before the unit, "1 heaping tbsp"
+    while words
+        .get(i)
+        .is_some_and(|word| SIZES.contains(&word.to_lowercase().as_str()))
+    {
+        descriptors.push(words[i].to_lowercase());
+        i += 1;
+    }
+
+    // a unit or measure needs a name after it, "2 whole" is not a unit
+    let mut unit = None;
+    let mut measure = None;
+    if i + 2 < words.len() && amount.is_some() {
+        if let Ok(parsed) = words[i..i + 2].join(" ").parse::<Unit>() {
+            unit = Some(parsed);
+            i += 2;
+        }
+    }
+    if unit.is_none() && i + 1 < words.len() {
+        let word = words[i].to_lowercase();
+        // without an amount "whole milk" is not pieces of milk
+        let parsed = words[i].parse::<Unit>().ok().filter(|_| amount.is_some());
+        if let Some(parsed) = parsed {
+            unit = Some(parsed);
+            i += 1;
+        } else if let Some((singular, _)) = MEASURES
+            .iter()
+            .find(|(singular, plural)| word == *singular || word == *plural)
+        {
+            measure = Some(singular.to_string());
+            i += 1;
+        }
+    }
+    // "a pinch of salt", "2 cups of flour"
+    if (unit.is_some() || measure.is_some())
+        && words
+            .get(i)
+            .is_some_and(|word| word.eq_ignore_ascii_case("of"))
+        && i + 1 < words.len()
+    {
+        i += 1;
+    }
+
+    let mut name_words = Vec::new();
+    let mut j = i;
+    while j < words.len() {
+        let word = words[j].to_lowercase();
+        let next = words.get(j + 1).map(|word| word.to_lowercase());
+        if ADVERBS.contains(&word.as_str()) && next.is_some() && j + 2 < words.len() {
+            descriptors.push(format!("{} {}", word, next.unwrap_or_default()));
+            j += 2;
+        } else if PREPARATIONS.contains(&word.as_str()) || SIZES.contains(&word.as_str()) {
+            descriptors.push(word);
+            j += 1;
+        } else {
+            name_words.push(word);
+            j += 1;
+        }
+    }
+    let mut name = name_words.join(" ");
+    // a line that is only descriptors, like "chopped", keeps them as the name
+    if name.is_empty() && !descriptors.is_empty() && amount.is_none() {
+        name = std::mem::take(&mut descriptors).join(" ");
+    }
+    let name = name
+        .trim_matches(|c: char| !c.is_alphanumeric() && c != '%')
+        .to_string();
+
+    // in the order they appear in the line: parentheticals before the name
+    // are rare, so descriptors come first
+    let mut parts: Vec<String> = descriptors;
+    parts.extend(comma_note);
+    parts.extend(notes);
+    parts.extend(amount_phrase);
+    let parts: Vec<String> = parts
+        .iter()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    IngredientLine {
+        amount,
+        amount_max,
+        unit,
+        measure,
+        name,
+        note: (!parts.is_empty()).then(|| parts.join(", ")),
+    }
+}
+
+// Unicode fractions as "1/2" words, en dashes as "-"
+fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for c in text.chars() {
+        let fraction = match c {
+            '¼' => "1/4",
+            '½' => "1/2",
+            '¾' => "3/4",
+            '⅓' => "1/3",
+            '⅔' => "2/3",
+            '⅕' => "1/5",
+            '⅖' => "2/5",
+            '⅗' => "3/5",
+            '⅘' => "4/5",
+            '⅙' => "1/6",
+            '⅚' => "5/6",
+            '⅛' => "1/8",
+            '⅜' => "3/8",
+            '⅝' => "5/8",
+            '⅞' => "7/8",
+            '⁄' => {
+                normalized.push('/');
+                continue;
+            }
+            '–' | '—' => {
+                normalized.push('-');
+                continue;
+            }
+            '\u{a0}' => {
+                normalized.push(' ');
+                continue;
+            }
+            c => {
+                normalized.push(c);
+                continue;
+            }
+        };
+        // "1½" is "1 1/2"
+        normalized.push(' ');
+        normalized.push_str(fraction);
+        normalized.push(' ');
+    }
+    normalized
+}
+
+// Words, with "1-2" split into "1", "-", "2" and "500g" into "500", "g"
+fn tokenize(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for word in text.split_whitespace() {
+        let parts: Vec<&str> = word.split('-').collect();
+        if parts.len() > 1
+            && parts
+                .iter()
+                .all(|part| part.is_empty() || parse_number(part).is_some())
+        {
+            for (k, part) in parts.iter().enumerate() {
+                if k > 0 {
+                    words.push("-".to_string());
+                }
+                if !part.is_empty() {
+                    words.push(part.to_string());
+                }
+            }
+            continue;
+        }
+        let digits = word
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '/'))
+            .unwrap_or(word.len());
+        if digits > 0
+            && digits < word.len()
+            && parse_number(&word[..digits]).is_some()
+            && word[digits..]
+                .chars()
+                .all(|c| c.is_alphabetic() || c == '.')
+        {
+            words.push(word[..digits].to_string());
+            words.push(word[digits..].to_string());
+            continue;
+        }
+        words.push(word.to_string());
+    }
+    words
+}
+
+// "2", "1.5", "1/2", "1 1/2", "a", "an" or "one", and the index after it
+fn number_at(words: &[String], i: usize) -> Option<(f32, usize)> {
+    let word = words.get(i)?.to_lowercase();
+    let value = match word.as_str() {
+        "a" | "an" | "one" => 1.0,
+        "two" => 2.0,
+        "three" => 3.0,
+        "four" => 4.0,
+        "five" => 5.0,
+        "six" => 6.0,
+        "half" => 0.5,
+        "dozen" => 12.0,
+        word => parse_number(word)?,
+    };
+    // mixed number, "1 1/2"
+    if !word.contains('/') {
+        if let Some(fraction) = words.get(i + 1).filter(|word| word.contains('/')) {
+            if let Some(fraction) = parse_number(fraction) {
+                return Some((value + fraction, i + 2));
+            }
+        }
+    }
+    Some((value, i + 1))
+}
+
+fn parse_number(word: &str) -> Option<f32> {
+    let value = match word.split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator: f32 = denominator.parse().ok()?;
+            if denominator == 0.0 {
+                return None;
+            }
+            numerator.parse::<f32>().ok()? / denominator
+        }
+        None => word.parse().ok()?,
+    };
+    (value.is_finite() && value >= 0.0).then_some(value)
+}
+
+// Catalog ingredients whose names share words with `name`, best first. Words
+// are compared without plural endings, and the last word of a name counts
+// most: "yellow onion" matches "onions" better than "onion powder"
+pub async fn match_ingredient(pool: &Pool<Sqlite>, name: &str) -> Result<Vec<IngredientMatch>> {
+    let words = name_words(name);
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for word in &words {
+        if word.chars().count() < 3 {
+            continue;
+        }
+        for found in ingredient::read_by_search_string(pool, &stem(word)).await? {
+            let id = found.id.unwrap_or_default();
+            if !seen.insert(id) {
+                continue;
+            }
+            let confidence = confidence(&words, &found.name);
+            if confidence > 0.0 {
+                candidates.push(IngredientMatch {
+                    id,
+                    name: found.name,
+                    confidence,
+                });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then(a.name.len().cmp(&b.name.len()))
+            .then(a.id.cmp(&b.id))
+    });
+    candidates.truncate(5);
+
+    Ok(candidates)
+}
+
+pub async fn parse_and_match(pool: &Pool<Sqlite>, text: &str) -> Result<ParsedIngredient> {
+    let line = parse(text);
+    let candidates = match_ingredient(pool, &line.name).await?;
+    Ok(ParsedIngredient { line, candidates })
+}
+
+// 1.0 for the same words, 0.9 if the catalog name only adds a parenthetical
+// like "(80% lean, 20% fat)", otherwise the share of common words with a
+// bonus when the last words agree
+fn confidence(words: &[String], candidate: &str) -> f32 {
+    let (base, parenthetical) = match candidate.split_once('(') {
+        Some((base, _)) => (base, true),
+        None => (candidate, false),
+    };
+    let candidate_words = name_words(base);
+    if words.is_empty() || candidate_words.is_empty() {
+        return 0.0;
+    }
+    if words == candidate_words.as_slice() {
+        return if parenthetical { 0.9 } else { 1.0 };
+    }
+    let query: HashSet<&String> = words.iter().collect();
+    let other: HashSet<&String> = candidate_words.iter().collect();
+    let common = query.intersection(&other).count();
+    let dice = 2.0 * common as f32 / (query.len() + other.len()) as f32;
+    let head = if words.last() == candidate_words.last() {
+        0.2
+    } else {
+        0.0
+    };
+    // "salt and pepper" names two ingredients, neither is a sure match
+    let max = if words.iter().any(|word| word == "and" || word == "or") {
+        MATCH_THRESHOLD - 0.1
+    } else {
+        // never as sure as the same name
+        0.85
+    };
+    (0.8 * dice + head).min(max)
+}
+
+// Lowercase words without plural endings
+fn name_words(name: &str) -> Vec<String> {
+    name.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '%'))
+        .filter(|word| !word.is_empty())
+        .map(|word| singular(&word.to_lowercase()))
+        .collect()
+}
+
+// "berries" -> "berry", "tomatoes" -> "tomato", "onions" -> "onion"
+fn singular(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies").filter(|stem| stem.len() > 1) {
+        return format!("{}y", stem);
+    }
+    if let Some(stem) = word.strip_suffix("oes") {
+        return format!("{}o", stem);
+    }
+    match word.strip_suffix('s') {
+        Some(stem) if !stem.ends_with('s') && stem.len() > 2 => stem.to_string(),
+        _ => word.to_string(),
+    }
+}
+
+// What to search the catalog for so both "berry" and "berries" are found
+fn stem(word: &str) -> String {
+    word.strip_suffix('y').unwrap_or(word).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, get_connection_pool};
+    use crate::ingredient_line::*;
+    use tempfile::NamedTempFile;
+
+    // (line, amount, amount_max, unit, measure, name, note)
+    type Expected = (
+        &'static str,
+        Option<f32>,
+        Option<f32>,
+        Option<Unit>,
+        Option<&'static str>,
+        &'static str,
+        Option<&'static str>,
+    );
+
+    const LINES: &[Expected] = &[
+        (
+            "2 1/2 cups chopped yellow onion, divided",
+            Some(2.5),
+            None,
+            Some(Unit::Cup),
+            None,
+            "yellow onion",
+            Some("chopped, divided"),
+        ),
+        (
+            "2 cups flour",
+            Some(2.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "flour",
+            None,
+        ),
+        (
+            "1 cup milk",
+            Some(1.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "milk",
+            None,
+        ),
+        (
+            "1 c. sugar",
+            Some(1.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "sugar",
+            None,
+        ),
+        (
+            "1 Tbsp olive oil",
+            Some(1.0),
+            None,
+            Some(Unit::Tablespoon),
+            None,
+            "olive oil",
+            None,
+        ),
+        (
+            "1 T butter",
+            Some(1.0),
+            None,
+            Some(Unit::Tablespoon),
+            None,
+            "butter",
+            None,
+        ),
+        (
+            "1 t salt",
+            Some(1.0),
+            None,
+            Some(Unit::Teaspoon),
+            None,
+            "salt",
+            None,
+        ),
+        (
+            "3 tablespoons honey",
+            Some(3.0),
+            None,
+            Some(Unit::Tablespoon),
+            None,
+            "honey",
+            None,
+        ),
+        (
+            "2 tsps cumin",
+            Some(2.0),
+            None,
+            Some(Unit::Teaspoon),
+            None,
+            "cumin",
+            None,
+        ),
+        (
+            "8 fl oz whole milk",
+            Some(8.0),
+            None,
+            Some(Unit::FluidOunce),
+            None,
+            "whole milk",
+            None,
+        ),
+        (
+            "1 lb ground beef",
+            Some(1.0),
+            None,
+            Some(Unit::Pound),
+            None,
+            "ground beef",
+            None,
+        ),
+        (
+            "2 pounds chicken thighs",
+            Some(2.0),
+            None,
+            Some(Unit::Pound),
+            None,
+            "chicken thighs",
+            None,
+        ),
+        (
+            "500g spaghetti",
+            Some(500.0),
+            None,
+            Some(Unit::Gram),
+            None,
+            "spaghetti",
+            None,
+        ),
+        (
+            "1.5 kg potatoes",
+            Some(1.5),
+            None,
+            Some(Unit::Kilogram),
+            None,
+            "potatoes",
+            None,
+        ),
+        (
+            "250 ml chicken broth",
+            Some(250.0),
+            None,
+            Some(Unit::Milliliter),
+            None,
+            "chicken broth",
+            None,
+        ),
+        (
+            "3 cloves garlic, minced",
+            Some(3.0),
+            None,
+            Some(Unit::Clove),
+            None,
+            "garlic",
+            Some("minced"),
+        ),
+        (
+            "2 slices sourdough bread",
+            Some(2.0),
+            None,
+            Some(Unit::Slice),
+            None,
+            "sourdough bread",
+            None,
+        ),
+        (
+            "4 corn tortillas",
+            Some(4.0),
+            None,
+            None,
+            None,
+            "corn tortillas",
+            None,
+        ),
+        (
+            "2 bay leaves",
+            Some(2.0),
+            None,
+            None,
+            None,
+            "bay leaves",
+            None,
+        ),
+        ("3 eggs", Some(3.0), None, None, None, "eggs", None),
+        (
+            "2 large eggs, beaten",
+            Some(2.0),
+            None,
+            None,
+            None,
+            "eggs",
+            Some("large, beaten"),
+        ),
+        (
+            "1 medium onion, finely chopped",
+            Some(1.0),
+            None,
+            None,
+            None,
+            "onion",
+            Some("medium, finely chopped"),
+        ),
+        ("An onion", Some(1.0), None, None, None, "onion", None),
+        (
+            "a pinch of salt",
+            Some(1.0),
+            None,
+            None,
+            Some("pinch"),
+            "salt",
+            None,
+        ),
+        (
+            "2 pinches saffron",
+            Some(2.0),
+            None,
+            None,
+            Some("pinch"),
+            "saffron",
+            None,
+        ),
+        (
+            "1 bunch cilantro",
+            Some(1.0),
+            None,
+            None,
+            Some("bunch"),
+            "cilantro",
+            None,
+        ),
+        (
+            "1 stick butter, softened",
+            Some(1.0),
+            None,
+            None,
+            Some("stick"),
+            "butter",
+            Some("softened"),
+        ),
+        // unicode fractions
+        (
+            "½ cup milk",
+            Some(0.5),
+            None,
+            Some(Unit::Cup),
+            None,
+            "milk",
+            None,
+        ),
+        (
+            "1½ cups rice",
+            Some(1.5),
+            None,
+            Some(Unit::Cup),
+            None,
+            "rice",
+            None,
+        ),
+        (
+            "1 ½ cups rice",
+            Some(1.5),
+            None,
+            Some(Unit::Cup),
+            None,
+            "rice",
+            None,
+        ),
+        (
+            "⅓ cup sugar",
+            Some(1.0 / 3.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "sugar",
+            None,
+        ),
+        (
+            "¾ tsp baking soda",
+            Some(0.75),
+            None,
+            Some(Unit::Teaspoon),
+            None,
+            "baking soda",
+            None,
+        ),
+        (
+            "1/4 tsp cayenne",
+            Some(0.25),
+            None,
+            Some(Unit::Teaspoon),
+            None,
+            "cayenne",
+            None,
+        ),
+        (
+            "1 1/4 cups water",
+            Some(1.25),
+            None,
+            Some(Unit::Cup),
+            None,
+            "water",
+            None,
+        ),
+        // ranges
+        (
+            "1-2 tsp chili powder",
+            Some(1.0),
+            Some(2.0),
+            Some(Unit::Teaspoon),
+            None,
+            "chili powder",
+            None,
+        ),
+        (
+            "1 - 2 tsp salt",
+            Some(1.0),
+            Some(2.0),
+            Some(Unit::Teaspoon),
+            None,
+            "salt",
+            None,
+        ),
+        (
+            "2 to 3 cups spinach",
+            Some(2.0),
+            Some(3.0),
+            Some(Unit::Cup),
+            None,
+            "spinach",
+            None,
+        ),
+        (
+            "3–4 carrots",
+            Some(3.0),
+            Some(4.0),
+            None,
+            None,
+            "carrots",
+            None,
+        ),
+        (
+            "1 or 2 jalapenos",
+            Some(1.0),
+            Some(2.0),
+            None,
+            None,
+            "jalapenos",
+            None,
+        ),
+        (
+            "½-1 cup broth",
+            Some(0.5),
+            Some(1.0),
+            Some(Unit::Cup),
+            None,
+            "broth",
+            None,
+        ),
+        // parentheticals
+        (
+            "1 (14 oz) can diced tomatoes, drained",
+            Some(1.0),
+            None,
+            Some(Unit::Can),
+            None,
+            "tomatoes",
+            Some("diced, drained, 14 oz"),
+        ),
+        (
+            "2 cups (480 ml) milk",
+            Some(2.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "milk",
+            Some("480 ml"),
+        ),
+        (
+            "4 tbsp butter (softened)",
+            Some(4.0),
+            None,
+            Some(Unit::Tablespoon),
+            None,
+            "butter",
+            Some("softened"),
+        ),
+        (
+            "1 cup rice (such as basmati (or jasmine))",
+            Some(1.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "rice",
+            Some("such as basmati (or jasmine)"),
+        ),
+        // to taste and friends
+        (
+            "salt, to taste",
+            None,
+            None,
+            None,
+            None,
+            "salt",
+            Some("to taste"),
+        ),
+        (
+            "salt and pepper to taste",
+            None,
+            None,
+            None,
+            None,
+            "salt and pepper",
+            Some("to taste"),
+        ),
+        (
+            "black pepper, or to taste",
+            None,
+            None,
+            None,
+            None,
+            "black pepper",
+            Some("or to taste"),
+        ),
+        (
+            "freshly ground black pepper to taste",
+            None,
+            None,
+            None,
+            None,
+            "black pepper",
+            Some("freshly ground, to taste"),
+        ),
+        (
+            "1/2 tsp salt, or to taste",
+            Some(0.5),
+            None,
+            Some(Unit::Teaspoon),
+            None,
+            "salt",
+            Some("or to taste"),
+        ),
+        (
+            "cilantro for garnish",
+            None,
+            None,
+            None,
+            None,
+            "cilantro",
+            Some("for garnish"),
+        ),
+        (
+            "2 tbsp chia seeds (optional)",
+            Some(2.0),
+            None,
+            Some(Unit::Tablespoon),
+            None,
+            "chia seeds",
+            Some("optional"),
+        ),
+        (
+            "oil as needed",
+            None,
+            None,
+            None,
+            None,
+            "oil",
+            Some("as needed"),
+        ),
+        // things that look like units or amounts but are not
+        (
+            "2 whole chickens",
+            Some(2.0),
+            None,
+            Some(Unit::Piece),
+            None,
+            "chickens",
+            None,
+        ),
+        (
+            "1 cup whole milk",
+            Some(1.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "whole milk",
+            None,
+        ),
+        ("1 can", Some(1.0), None, None, None, "can", None),
+        ("2% milk", None, None, None, None, "2% milk", None),
+        (
+            "1 cup 2% milk",
+            Some(1.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "2% milk",
+            None,
+        ),
+        (
+            "all-purpose flour",
+            None,
+            None,
+            None,
+            None,
+            "all-purpose flour",
+            None,
+        ),
+        ("potatoes", None, None, None, None, "potatoes", None),
+        ("chopped", None, None, None, None, "chopped", None),
+        ("", None, None, None, None, "", None),
+        (
+            "  2   Cups  FLOUR  ",
+            Some(2.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "flour",
+            None,
+        ),
+        (
+            "1 heaping tbsp sugar",
+            Some(1.0),
+            None,
+            Some(Unit::Tablespoon),
+            None,
+            "sugar",
+            Some("heaping"),
+        ),
+        (
+            "1 1/2 cups cooked rice, cooled",
+            Some(1.5),
+            None,
+            Some(Unit::Cup),
+            None,
+            "rice",
+            Some("cooked, cooled"),
+        ),
+        (
+            "2 cups of flour",
+            Some(2.0),
+            None,
+            Some(Unit::Cup),
+            None,
+            "flour",
+            None,
+        ),
+        (
+            "1 lb shrimp, peeled and deveined",
+            Some(1.0),
+            None,
+            Some(Unit::Pound),
+            None,
+            "shrimp",
+            Some("peeled and deveined"),
+        ),
+        (
+            "1/0 cup sugar",
+            None,
+            None,
+            None,
+            None,
+            "1/0 cup sugar",
+            None,
+        ),
+    ];
+
+    #[test]
+    fn test_parse() {
+        for &(text, amount, amount_max, unit, measure, name, note) in LINES {
+            let line = parse(text);
+            let expected = IngredientLine {
+                amount,
+                amount_max,
+                unit,
+                measure: measure.map(str::to_string),
+                name: name.to_string(),
+                note: note.map(str::to_string),
+            };
+            assert_eq!(line, expected, "parsing '{}'", text);
+        }
+    }
+
+    #[test]
+    fn test_confidence() {
+        let words = |name: &str| name_words(name);
+        assert_eq!(confidence(&words("onion"), "onions"), 1.0);
+        assert_eq!(confidence(&words("blueberry"), "blueberries"), 1.0);
+        assert_eq!(confidence(&words("tomato"), "tomatoes"), 1.0);
+        assert_eq!(
+            confidence(&words("ground beef"), "ground beef (80% lean, 20% fat)"),
+            0.9
+        );
+        assert!(
+            confidence(&words("yellow onion"), "onions")
+                > confidence(&words("yellow onion"), "onion powder")
+        );
+        assert!(
+            confidence(&words("yellow onion"), "onions")
+                > confidence(&words("yellow onion"), "green onions")
+        );
+        assert!(confidence(&words("yellow onion"), "onions") < 1.0);
+        assert_eq!(confidence(&words("saffron"), "salmon"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_match_ingredient() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        // (name, best match with confidence >= MATCH_THRESHOLD)
+        let table = [
+            ("yellow onion", Some("onions")),
+            ("onion", Some("onions")),
+            ("onions", Some("onions")),
+            ("green onion", Some("green onions")),
+            ("onion powder", Some("onion powder")),
+            ("Garlic", Some("garlic")),
+            ("garlic cloves", Some("garlic")),
+            ("apples", Some("apple")),
+            ("blueberry", Some("blueberries")),
+            ("cherry tomatoes", Some("tomatoes")),
+            ("black beans", Some("black beans")),
+            ("canned black beans", Some("black beans")),
+            ("ground beef", Some("ground beef (73% lean, 27% fat)")),
+            ("whole milk", Some("whole milk")),
+            ("kosher salt", Some("salt")),
+            ("black pepper", Some("black pepper")),
+            ("red bell pepper", Some("bell pepper")),
+            ("baby spinach", Some("spinach")),
+            ("long grain white rice", Some("white rice")),
+            ("salt and pepper", None),
+            ("saffron", None),
+            ("all-purpose flour", None),
+            ("", None),
+        ];
+        for (name, expected) in table {
+            let candidates = match_ingredient(&pool, name).await.unwrap();
+            let best = candidates
+                .first()
+                .filter(|candidate| candidate.confidence >= MATCH_THRESHOLD)
+                .map(|candidate| candidate.name.as_str());
+            assert_eq!(best, expected, "matching '{}': {:?}", name, candidates);
+        }
+
+        let parsed = parse_and_match(&pool, "2 1/2 cups chopped yellow onion, divided")
+            .await
+            .unwrap();
+        assert_eq!(parsed.line.amount, Some(2.5));
+        assert_eq!(parsed.line.unit, Some(Unit::Cup));
+        assert_eq!(parsed.line.note.as_deref(), Some("chopped, divided"));
+        let best = parsed.best_match().unwrap();
+        assert_eq!(best.name, "onions");
+        assert!(best.confidence < 1.0);
+        assert!(parsed
+            .candidates
+            .iter()
+            .any(|candidate| candidate.name == "onion powder"));
+    }
+}
@@ -0,0 +1,789 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sqlx::{Pool, Sqlite};
+
+use crate::crud::ingredient;
+use crate::crud::recipe::{self, Recipe, RecipeIngredientInput, RecipeInput, RecipeStep};
+use crate::crud::tag::Tag;
+use crate::error::{Error, Result};
+use crate::ingredient_line;
+use crate::timing::RecipeTime;
+use crate::unit::Unit;
+
+// The parts of a schema.org Recipe we read, with HTML and entities removed
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct SchemaRecipe {
+    pub name: String,
+    pub ingredients: Vec<String>,
+    pub instructions: Vec<RecipeStep>,
+    // notes from HowToTip items
+    pub tips: Vec<String>,
+    pub servings: Option<f32>,
+    // recipeYield when it is not a number of servings, e.g. "1 loaf"
+    pub recipe_yield: Option<String>,
+    pub total_minutes: Option<f32>,
+    pub prep_minutes: Option<f32>,
+    pub cook_minutes: Option<f32>,
+    pub keywords: Vec<String>,
+}
+
+// A recipe ready to be created and the ingredient lines that matched nothing
+// in the catalog, those are kept as notes
+#[derive(Serialize, Debug)]
+pub struct RecipeImport {
+    pub recipe: RecipeInput,
+    pub unmatched: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportedRecipe {
+    pub recipe: Recipe,
+    pub unmatched: Vec<String>,
+}
+
+// Read the first schema.org Recipe from a JSON-LD document or from the
+// <script type="application/ld+json"> blocks of an HTML page
+pub fn parse(text: &str) -> Result<SchemaRecipe> {
+    let trimmed = text.trim_start();
+    let documents = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        vec![serde_json::from_str(trimmed)?]
+    } else {
+        let scripts = json_ld_scripts(text);
+        if scripts.is_empty() {
+            return Err(Error::Validation(
+                "No JSON-LD script found in the HTML".to_string(),
+            ));
+        }
+        // pages often have other, sometimes broken, JSON-LD blocks
+        scripts
+            .iter()
+            .filter_map(|script| serde_json::from_str::<Value>(script).ok())
+            .collect()
+    };
+    let recipe = documents
+        .iter()
+        .find_map(find_recipe)
+        .ok_or_else(|| Error::Validation("No schema.org Recipe found".to_string()))?;
+
+    let name = recipe.get("name").and_then(text_value).unwrap_or_default();
+    if name.is_empty() {
+        return Err(Error::Validation("Recipe has no name".to_string()));
+    }
+    let mut schema = SchemaRecipe {
+        name,
+        ..Default::default()
+    };
+    // "ingredients" is the pre 2015 name of recipeIngredient
+    if let Some(ingredients) = recipe
+        .get("recipeIngredient")
+        .or_else(|| recipe.get("ingredients"))
+    {
+        schema.ingredients = text_values(ingredients);
+    }
+    if let Some(instructions) = recipe.get("recipeInstructions") {
+        read_steps(instructions, &mut None, &mut schema);
+    }
+    if let Some(recipe_yield) = recipe.get("recipeYield") {
+        read_yield(recipe_yield, &mut schema);
+    }
+    let minutes = |key: &str| {
+        recipe
+            .get(key)
+            .and_then(text_value)
+            .and_then(|duration| parse_duration(&duration))
+    };
+    schema.total_minutes = minutes("totalTime");
+    schema.prep_minutes = minutes("prepTime");
+    schema.cook_minutes = minutes("cookTime");
+    if let Some(keywords) = recipe.get("keywords") {
+        for keyword in text_values(keywords)
+            .iter()
+            .flat_map(|keywords| keywords.split(','))
+        {
+            let keyword = keyword.trim();
+            if !keyword.is_empty()
+                && !schema
+                    .keywords
+                    .iter()
+                    .any(|existing| existing.eq_ignore_ascii_case(keyword))
+            {
+                schema.keywords.push(keyword.to_string());
+            }
+        }
+    }
+
+    Ok(schema)
+}
+
+// Match the ingredient lines against the catalog and build the recipe.
+// Lines for the same ingredient and unit are added up
+pub async fn to_recipe_input(pool: &Pool<Sqlite>, schema: &SchemaRecipe) -> Result<RecipeImport> {
+    let mut ingredients: Vec<RecipeIngredientInput> = Vec::new();
+    let mut unmatched = Vec::new();
+    for text in &schema.ingredients {
+        let parsed = ingredient_line::parse_and_match(pool, text).await?;
+        let ingredient_id = match parsed.best_match() {
+            Some(found) => found.id,
+            None => {
+                unmatched.push(text.clone());
+                continue;
+            }
+        };
+        let line = parsed.line;
+        // ranges count as their lower end, "1-2 tsp" is 1 tsp
+        let (amount, unit) = match (line.measure, line.unit, line.amount) {
+            (None, Some(unit), amount) => (amount.unwrap_or(0.0), unit),
+            // "3 eggs"
+            (None, None, Some(amount)) => (amount, Unit::Piece),
+            // "1 pinch saffron" or "salt, to taste" have no amount to store
+            _ => (0.0, ingredient::read_one(pool, ingredient_id).await?.unit),
+        };
+        match ingredients
+            .iter_mut()
+            .find(|existing| existing.ingredient_id == ingredient_id)
+        {
+            Some(existing) if existing.unit == unit => existing.amount += amount,
+            // one row per ingredient, a second line in another unit is a note
+            Some(_) => unmatched.push(text.clone()),
+            None => ingredients.push(RecipeIngredientInput {
+                ingredient_id,
+                amount,
+                unit,
+            }),
+        }
+    }
+
+    let mut instructions = schema.instructions.clone();
+    // recipes without durations in the step text get the ones from the
+    // document, prep time as active and the rest as passive
+    if RecipeTime::from_steps(&instructions).total_minutes == 0.0 && !instructions.is_empty() {
+        let (active, passive) = match (schema.prep_minutes, schema.cook_minutes) {
+            (None, None) => (schema.total_minutes, None),
+            (prep, cook) => {
+                let known = prep.unwrap_or(0.0) + cook.unwrap_or(0.0);
+                let rest = schema.total_minutes.unwrap_or(known) - known;
+                (prep, Some(cook.unwrap_or(0.0) + rest.max(0.0)))
+            }
+        };
+        instructions[0].active_minutes = active.filter(|&minutes| minutes > 0.0);
+        if let Some(last) = instructions.last_mut() {
+            last.passive_minutes = passive.filter(|&minutes| minutes > 0.0);
+        }
+    }
+
+    let mut notes = schema.tips.clone();
+    notes.extend(unmatched.iter().cloned());
+    let recipe = RecipeInput {
+        name: schema.name.clone(),
+        tags: schema
+            .keywords
+            .iter()
+            .map(|keyword| Tag {
+                id: None,
+                name: keyword.clone(),
+            })
+            .collect(),
+        ingredients,
+        notes,
+        instructions,
+        servings: schema.servings.unwrap_or(1.0),
+        recipe_yield: schema.recipe_yield.clone(),
+    };
+
+    Ok(RecipeImport { recipe, unmatched })
+}
+
+// Create a recipe from a JSON-LD document or HTML page, see `parse`
+pub async fn import(pool: &Pool<Sqlite>, text: &str) -> Result<ImportedRecipe> {
+    let schema = parse(text)?;
+    let import = to_recipe_input(pool, &schema).await?;
+    let recipe_id = recipe::create_full(pool, &import.recipe).await?;
+
+    Ok(ImportedRecipe {
+        recipe: recipe::read_one(pool, recipe_id).await?,
+        unmatched: import.unmatched,
+    })
+}
+
+// The recipe as a schema.org Recipe JSON-LD document
+pub fn export(recipe: &Recipe) -> Value {
+    let mut document = Map::new();
+    document.insert("@context".to_string(), json!("https://schema.org"));
+    document.insert("@type".to_string(), json!("Recipe"));
+    document.insert("name".to_string(), json!(recipe.name));
+
+    let servings = format!("{} servings", format_number(recipe.servings));
+    let recipe_yield = match &recipe.recipe_yield {
+        Some(recipe_yield) => json!([servings, recipe_yield]),
+        None => json!(servings),
+    };
+    document.insert("recipeYield".to_string(), recipe_yield);
+    if recipe.time.total_minutes > 0.0 {
+        document.insert(
+            "totalTime".to_string(),
+            json!(format_duration(recipe.time.total_minutes)),
+        );
+    }
+    if !recipe.tags.is_empty() {
+        let keywords: Vec<&str> = recipe.tags.iter().map(|tag| tag.name.as_str()).collect();
+        document.insert("keywords".to_string(), json!(keywords.join(", ")));
+    }
+
+    let ingredients: Vec<String> = recipe
+        .ingredients
+        .iter()
+        .map(|line| format_ingredient(line.amount, line.unit, &line.ingredient.name))
+        .collect();
+    document.insert("recipeIngredient".to_string(), json!(ingredients));
+
+    // steps before the first section stay at the top level, a section
+    // collects the steps up to the next one
+    let mut instructions: Vec<Value> = Vec::new();
+    let mut section: Option<(String, Vec<Value>)> = None;
+    for step in &recipe.instructions {
+        let how_to_step = json!({"@type": "HowToStep", "text": step.text});
+        if let Some(name) = &step.section {
+            if let Some((name, steps)) = section.take() {
+                instructions.push(how_to_section(name, steps));
+            }
+            section = Some((name.clone(), Vec::new()));
+        }
+        match &mut section {
+            Some((_, steps)) => steps.push(how_to_step),
+            None => instructions.push(how_to_step),
+        }
+    }
+    if let Some((name, steps)) = section {
+        instructions.push(how_to_section(name, steps));
+    }
+    document.insert("recipeInstructions".to_string(), json!(instructions));
+
+    Value::Object(document)
+}
+
+fn how_to_section(name: String, steps: Vec<Value>) -> Value {
+    json!({"@type": "HowToSection", "name": name, "itemListElement": steps})
+}
+
+// "2 cups white rice", "3 eggs" for pieces, just the name without an amount
+fn format_ingredient(amount: f32, unit: Unit, name: &str) -> String {
+    if amount <= 0.0 {
+        return name.to_string();
+    }
+    let amount_text = unit.format_amount(amount);
+    if unit == Unit::Piece {
+        return format!("{} {}", amount_text, name);
+    }
+    let label = match unit {
+        // abbreviations stay the same, words get a plural
+        Unit::Cup | Unit::Clove | Unit::Tortilla | Unit::Slice | Unit::Can if amount > 1.0 => {
+            format!("{}s", unit.label())
+        }
+        Unit::Leaf if amount > 1.0 => "leaves".to_string(),
+        _ => unit.label().to_string(),
+    };
+    format!("{} {} {}", amount_text, label, name)
+}
+
+fn format_number(value: f32) -> String {
+    crate::unit::format_decimal(value)
+}
+
+// The Recipe node of a document: the document itself, an item of an array
+// or of "@graph", or the "mainEntity" of a WebPage
+fn find_recipe(value: &Value) -> Option<&Map<String, Value>> {
+    match value {
+        Value::Array(items) => items.iter().find_map(find_recipe),
+        Value::Object(object) if is_recipe(object) => Some(object),
+        Value::Object(object) => ["@graph", "mainEntity"]
+            .iter()
+            .filter_map(|key| object.get(*key))
+            .find_map(find_recipe),
+        _ => None,
+    }
+}
+
+// "Recipe", "schema:Recipe", "http://schema.org/Recipe" or a list with one
+fn is_recipe(object: &Map<String, Value>) -> bool {
+    let is_recipe_type = |value: &Value| {
+        value.as_str().is_some_and(|name| {
+            name == "Recipe" || name.ends_with(":Recipe") || name.ends_with("/Recipe")
+        })
+    };
+    match object.get("@type") {
+        Some(Value::Array(types)) => types.iter().any(is_recipe_type),
+        Some(value) => is_recipe_type(value),
+        None => false,
+    }
+}
+
+// Walk recipeInstructions. `section` is the heading still waiting for its
+// first step
+fn read_steps(value: &Value, section: &mut Option<String>, schema: &mut SchemaRecipe) {
+    let mut push = |text: String, section: &mut Option<String>| {
+        if !text.is_empty() {
+            schema.instructions.push(RecipeStep {
+                section: section.take(),
+                ..RecipeStep::from(text.as_str())
+            });
+        }
+    };
+    match value {
+        // one step per line
+        Value::String(text) => {
+            for line in text.lines() {
+                push(strip_step_number(&clean_text(line)), section);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                read_steps(item, section, schema);
+            }
+        }
+        Value::Object(object) => {
+            let types = object.get("@type").map(text_values).unwrap_or_default();
+            let has_type = |name: &str| types.iter().any(|value| value == name);
+            let text = object
+                .get("text")
+                .or_else(|| object.get("name"))
+                .and_then(text_value)
+                .unwrap_or_default();
+            if has_type("HowToSection") {
+                let mut section = Some(text).filter(|name| !name.is_empty());
+                if let Some(items) = object.get("itemListElement") {
+                    read_steps(items, &mut section, schema);
+                }
+            } else if has_type("HowToTip") {
+                if !text.is_empty() {
+                    schema.tips.push(text);
+                }
+            } else if let Some(items) = object
+                .get("itemListElement")
+                .filter(|_| !object.contains_key("text"))
+            {
+                // an ItemList, or a HowToStep made of HowToDirections
+                read_steps(items, section, schema);
+            } else {
+                push(text, section);
+            }
+        }
+        _ => {}
+    }
+}
+
+// "1. Preheat" or "Step 2: Mix" without the number
+fn strip_step_number(text: &str) -> String {
+    let rest = text
+        .strip_prefix("Step ")
+        .or_else(|| text.strip_prefix("STEP "))
+        .unwrap_or(text);
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 {
+        let after = &rest[digits..];
+        if let Some(after) = after
+            .strip_prefix('.')
+            .or_else(|| after.strip_prefix(')'))
+            .or_else(|| after.strip_prefix(':'))
+        {
+            return after.trim().to_string();
+        }
+    }
+    text.to_string()
+}
+
+// recipeYield is a number, a text like "4 servings", "Serves 4-6" or
+// "1 loaf", or a list of those
+fn read_yield(value: &Value, schema: &mut SchemaRecipe) {
+    for text in text_values(value) {
+        let lower = text.to_lowercase();
+        let words: Vec<&str> = lower
+            .split(|c: char| c.is_whitespace() || c == '-' || c == '–')
+            .filter(|word| !word.is_empty())
+            .collect();
+        let number = words
+            .iter()
+            .find_map(|word| word.parse::<f32>().ok())
+            .filter(|number| *number > 0.0 && number.is_finite());
+        let servings_words = [
+            "serving", "servings", "serves", "portion", "portions", "people", "persons",
+        ];
+        let is_servings =
+            words.len() == 1 || words.iter().any(|word| servings_words.contains(word));
+        match number {
+            Some(number) if is_servings => {
+                schema.servings.get_or_insert(number);
+            }
+            _ => {
+                schema.recipe_yield.get_or_insert(text);
+            }
+        }
+    }
+}
+
+// ISO 8601 durations like "PT1H30M", "PT90M" or "P1DT2H" in minutes
+pub fn parse_duration(text: &str) -> Option<f32> {
+    let rest = text.trim().strip_prefix('P')?;
+    let mut minutes = 0.0;
+    let mut number = String::new();
+    let mut time = false;
+    for c in rest.chars() {
+        match c {
+            'T' => time = true,
+            '0'..='9' | '.' | ',' => number.push(if c == ',' { '.' } else { c }),
+            _ => {
+                let value: f32 = number.parse().ok()?;
+                number.clear();
+                minutes += value
+                    * match (c, time) {
+                        ('W', false) => 7.0 * 24.0 * 60.0,
+                        ('D', false) => 24.0 * 60.0,
+                        ('H', true) => 60.0,
+                        ('M', true) => 1.0,
+                        ('S', true) => 1.0 / 60.0,
+                        // years and months make no sense for a recipe
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    number.is_empty().then_some(minutes)
+}
+
+// "PT1H30M", rounded to whole minutes
+pub fn format_duration(minutes: f32) -> String {
+    let minutes = minutes.round() as i64;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("PT{}M", minutes),
+        (hours, 0) => format!("PT{}H", hours),
+        (hours, minutes) => format!("PT{}H{}M", hours, minutes),
+    }
+}
+
+// The first text of a string, number or list, or the "@value" or "name" of an object
+fn text_value(value: &Value) -> Option<String> {
+    text_values(value).into_iter().next()
+}
+
+fn text_values(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(text) => vec![clean_text(text)],
+        Value::Number(number) => vec![number.to_string()],
+        Value::Array(items) => items.iter().flat_map(text_values).collect(),
+        Value::Object(object) => object
+            .get("@value")
+            .or_else(|| object.get("name"))
+            .map(text_values)
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+    .into_iter()
+    .filter(|text| !text.is_empty())
+    .collect()
+}
+
+// The contents of <script type="application/ld+json"> elements
+fn json_ld_scripts(html: &str) -> Vec<&str> {
+    // ASCII lowercasing keeps the byte offsets
+    let lower = html.to_ascii_lowercase();
+    let mut scripts = Vec::new();
+    let mut position = 0;
+    while let Some(start) = lower[position..].find("<script") {
+        let start = position + start;
+        let tag_end = match lower[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        let end = match lower[tag_end..].find("</script") {
+            Some(end) => tag_end + end,
+            None => break,
+        };
+        if lower[start..tag_end].contains("application/ld+json") {
+            scripts.push(&html[tag_end..end]);
+        }
+        position = end;
+    }
+    scripts
+}
+
+const INLINE_TAGS: &[&str] = &["a", "b", "em", "i", "span", "strong", "sub", "sup", "u"];
+
+// Text without HTML tags, with entities decoded and whitespace collapsed
+fn clean_text(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let starts_tag = chars
+            .peek()
+            .is_some_and(|next| next.is_ascii_alphabetic() || *next == '/' || *next == '!');
+        if c == '<' && starts_tag {
+            let tag: String = chars.by_ref().take_while(|&c| c != '>').collect();
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            // block tags like "<br>" or "</p><p>" separate words
+            if !INLINE_TAGS.contains(&name.as_str()) {
+                stripped.push(' ');
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    decode_entities(&stripped)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "deg" => '°',
+        "frac12" => '½',
+        "frac14" => '¼',
+        "frac34" => '¾',
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, get_connection_pool};
+    use crate::jsonld::*;
+    use tempfile::NamedTempFile;
+
+    const DOCUMENT: &str = r#"{
+        "@context": "https://schema.org",
+        "@graph": [
+            {"@type": "WebPage", "name": "Rice &amp; beans | Example"},
+            {
+                "@type": ["Recipe", "NewsArticle"],
+                "name": "Rice &amp; Beans",
+                "recipeYield": ["4", "4 servings"],
+                "totalTime": "PT1H5M",
+                "prepTime": "PT15M",
+                "cookTime": "PT45M",
+                "keywords": "dinner, Vegetarian,dinner",
+                "recipeIngredient": [
+                    "2 cups white rice",
+                    "1 1/2 cups black beans, drained",
+                    "1 onion, chopped",
+                    "3 cloves garlic",
+                    "1 pinch saffron",
+                    "salt, to taste"
+                ],
+                "recipeInstructions": [
+                    {"@type": "HowToStep", "text": "Rinse the <b>rice</b>."},
+                    {
+                        "@type": "HowToSection",
+                        "name": "For the beans",
+                        "itemListElement": [
+                            {"@type": "HowToStep", "text": "Fry the onion and garlic."},
+                            {"@type": "HowToStep", "name": "Add the beans"},
+                            {"@type": "HowToTip", "text": "Canned beans work fine."}
+                        ]
+                    },
+                    {"@type": "HowToStep", "text": "Serve."}
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse() {
+        let schema = parse(DOCUMENT).unwrap();
+        assert_eq!(schema.name, "Rice & Beans");
+        assert_eq!(schema.servings, Some(4.0));
+        assert_eq!(schema.recipe_yield, None);
+        assert_eq!(schema.total_minutes, Some(65.0));
+        assert_eq!(schema.keywords, vec!["dinner", "Vegetarian"]);
+        assert_eq!(schema.ingredients.len(), 6);
+        let steps: Vec<(Option<&str>, &str)> = schema
+            .instructions
+            .iter()
+            .map(|step| (step.section.as_deref(), step.text.as_str()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                (None, "Rinse the rice."),
+                (Some("For the beans"), "Fry the onion and garlic."),
+                (None, "Add the beans"),
+                (None, "Serve."),
+            ]
+        );
+        assert_eq!(schema.tips, vec!["Canned beans work fine."]);
+    }
+
+    #[test]
+    fn test_parse_html() {
+        let html = r#"<html><head>
+            <script type="application/ld+json">{ broken</script>
+            <script type="text/javascript">var recipe = {};</script>
+            <SCRIPT TYPE="application/ld+json">
+            {"@context": "http://schema.org", "@type": "http://schema.org/Recipe",
+             "name": "Banana Bread", "recipeYield": "1 loaf",
+             "recipeInstructions": "1. Mash the bananas.\n2. Bake for 1 hour."}
+            </SCRIPT></head><body>...</body></html>"#;
+        let schema = parse(html).unwrap();
+        assert_eq!(schema.name, "Banana Bread");
+        assert_eq!(schema.servings, None);
+        assert_eq!(schema.recipe_yield.as_deref(), Some("1 loaf"));
+        assert_eq!(schema.instructions[0].text, "Mash the bananas.");
+        assert_eq!(schema.instructions[1].text, "Bake for 1 hour.");
+
+        assert!(parse("<html><body>No recipe here</body></html>").is_err());
+        assert!(parse(r#"{"@type": "Article", "name": "Not a recipe"}"#).is_err());
+        assert!(parse(r#"{"@type": "Recipe"}"#).is_err());
+    }
+
+    #[test]
+    fn test_yield() {
+        let servings = |value: Value| {
+            let mut schema = SchemaRecipe::default();
+            read_yield(&value, &mut schema);
+            (schema.servings, schema.recipe_yield)
+        };
+        assert_eq!(servings(json!(6)), (Some(6.0), None));
+        assert_eq!(servings(json!("Serves 4-6")), (Some(4.0), None));
+        assert_eq!(
+            servings(json!("24 cookies")),
+            (None, Some("24 cookies".to_string()))
+        );
+        assert_eq!(
+            servings(json!(["8 servings", "1 cake"])),
+            (Some(8.0), Some("1 cake".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(parse_duration("PT1H30M"), Some(90.0));
+        assert_eq!(parse_duration("PT90M"), Some(90.0));
+        assert_eq!(parse_duration("P1DT2H"), Some(1560.0));
+        assert_eq!(parse_duration("PT0.5H"), Some(30.0));
+        assert_eq!(parse_duration("P1M"), None);
+        assert_eq!(parse_duration("90 minutes"), None);
+        assert_eq!(format_duration(90.0), "PT1H30M");
+        assert_eq!(format_duration(120.0), "PT2H");
+        assert_eq!(format_duration(25.4), "PT25M");
+    }
+
+    #[test]
+    fn test_clean_text() {
+        assert_eq!(
+            clean_text("<p>Mix &amp; stir</p><p>at 350&deg;F,&#160;then&#x21;</p>"),
+            "Mix & stir at 350°F, then!"
+        );
+        assert_eq!(clean_text("a < b && c"), "a < b && c");
+    }
+
+    #[tokio::test]
+    async fn test_import_export() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let imported = import(&pool, DOCUMENT).await.unwrap();
+        assert_eq!(imported.unmatched, vec!["1 pinch saffron"]);
+        let recipe = imported.recipe;
+        assert_eq!(recipe.servings, 4.0);
+        let lines: Vec<(&str, f32, Unit)> = recipe
+            .ingredients
+            .iter()
+            .map(|line| (line.ingredient.name.as_str(), line.amount, line.unit))
+            .collect();
+        assert!(lines.contains(&("white rice", 2.0, Unit::Cup)));
+        assert!(lines.contains(&("black beans", 1.5, Unit::Cup)));
+        assert!(lines.contains(&("onions", 1.0, Unit::Piece)));
+        assert!(lines.contains(&("garlic", 3.0, Unit::Clove)));
+        assert!(lines.contains(&("salt", 0.0, Unit::Teaspoon)));
+        assert_eq!(
+            recipe.notes,
+            vec!["Canned beans work fine.", "1 pinch saffron"]
+        );
+        let mut tags: Vec<&str> = recipe.tags.iter().map(|tag| tag.name.as_str()).collect();
+        tags.sort();
+        assert_eq!(tags, vec!["Vegetarian", "dinner"]);
+        // the steps have no durations, the document's are used
+        assert_eq!(recipe.time.active_minutes, 15.0);
+        assert_eq!(recipe.time.passive_minutes, 50.0);
+
+        let document = export(&recipe);
+        assert_eq!(document["@type"], "Recipe");
+        assert_eq!(document["totalTime"], "PT1H5M");
+        assert_eq!(document["recipeYield"], "4 servings");
+        assert!(document["recipeIngredient"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("2 cups white rice")));
+        assert_eq!(document["recipeInstructions"][1]["@type"], "HowToSection");
+        assert_eq!(
+            document["recipeInstructions"][1]["itemListElement"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+
+        // and back again
+        let schema = parse(&document.to_string()).unwrap();
+        assert_eq!(schema.name, recipe.name);
+        assert_eq!(schema.servings, Some(4.0));
+        assert_eq!(schema.total_minutes, Some(65.0));
+        let texts = |steps: &[RecipeStep]| -> Vec<(Option<String>, String)> {
+            steps
+                .iter()
+                .map(|step| (step.section.clone(), step.text.clone()))
+                .collect()
+        };
+        assert_eq!(texts(&schema.instructions), texts(&recipe.instructions));
+        let again = to_recipe_input(&pool, &schema).await.unwrap();
+        assert!(again.unmatched.is_empty());
+        assert_eq!(again.recipe.ingredients.len(), recipe.ingredients.len());
+    }
+}
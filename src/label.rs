@@ -0,0 +1,651 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+
+use crate::crud::{recipe, unit_conversion};
+use crate::error::{Error, Result};
+use crate::nutrition::{self, IncompleteIngredient, NutritionError, NutritionFacts};
+use crate::unit::format_decimal;
+
+pub const DEFAULT_REFERENCE_CALORIES: f32 = 2000.0;
+
+// Daily values the percentages are based on. The default is the FDA's 2000
+// kcal reference diet (21 CFR 101.9)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReferenceDiet {
+    pub calories_kcal: f32,
+    pub fat_g: f32,
+    pub saturated_fat_g: f32,
+    pub cholesterol_mg: f32,
+    pub sodium_mg: f32,
+    pub carbohydrates_g: f32,
+    pub fiber_g: f32,
+    pub added_sugar_g: f32,
+    pub protein_g: f32,
+    pub vitamin_d_mcg: f32,
+    pub calcium_mg: f32,
+    pub iron_mg: f32,
+    pub potassium_mg: f32,
+    pub vitamin_c_mg: f32,
+    pub magnesium_mg: f32,
+}
+
+impl Default for ReferenceDiet {
+    fn default() -> Self {
+        ReferenceDiet {
+            calories_kcal: DEFAULT_REFERENCE_CALORIES,
+            fat_g: 78.0,
+            saturated_fat_g: 20.0,
+            cholesterol_mg: 300.0,
+            sodium_mg: 2300.0,
+            carbohydrates_g: 275.0,
+            fiber_g: 28.0,
+            added_sugar_g: 50.0,
+            protein_g: 50.0,
+            vitamin_d_mcg: 20.0,
+            calcium_mg: 1300.0,
+            iron_mg: 18.0,
+            potassium_mg: 4700.0,
+            vitamin_c_mg: 90.0,
+            magnesium_mg: 420.0,
+        }
+    }
+}
+
+impl ReferenceDiet {
+    // The FDA diet for a different calorie level. Energy nutrients scale with
+    // the calories, the limits for cholesterol and sodium and the vitamins
+    // and minerals don't
+    pub fn for_calories(calories_kcal: f32) -> Result<Self> {
+        if calories_kcal <= 0.0 || !calories_kcal.is_finite() {
+            return Err(Error::Validation(format!(
+                "Reference calories must be greater than 0, got {}",
+                calories_kcal
+            )));
+        }
+        let default = ReferenceDiet::default();
+        let factor = calories_kcal / default.calories_kcal;
+        Ok(ReferenceDiet {
+            calories_kcal,
+            fat_g: default.fat_g * factor,
+            saturated_fat_g: default.saturated_fat_g * factor,
+            carbohydrates_g: default.carbohydrates_g * factor,
+            fiber_g: default.fiber_g * factor,
+            added_sugar_g: default.added_sugar_g * factor,
+            protein_g: default.protein_g * factor,
+            ..default
+        })
+    }
+}
+
+// Percent of the daily value, unrounded. None where the amount is unknown
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct DailyValues {
+    pub fat: Option<f32>,
+    pub saturated_fat: Option<f32>,
+    pub cholesterol: Option<f32>,
+    pub sodium: Option<f32>,
+    pub carbohydrates: Option<f32>,
+    pub fiber: Option<f32>,
+    pub added_sugar: Option<f32>,
+    pub protein: Option<f32>,
+    pub vitamin_d: Option<f32>,
+    pub calcium: Option<f32>,
+    pub iron: Option<f32>,
+    pub potassium: Option<f32>,
+    pub vitamin_c: Option<f32>,
+    pub magnesium: Option<f32>,
+}
+
+impl DailyValues {
+    pub fn new(facts: &NutritionFacts, diet: &ReferenceDiet) -> Self {
+        let percent = |amount: Option<f32>, daily_value: f32| {
+            amount.map(|amount| {
+                if daily_value > 0.0 {
+                    amount / daily_value * 100.0
+                } else {
+                    0.0
+                }
+            })
+        };
+        DailyValues {
+            fat: percent(facts.fat_g, diet.fat_g),
+            saturated_fat: percent(facts.saturated_fat_g, diet.saturated_fat_g),
+            cholesterol: percent(facts.cholesterol_mg, diet.cholesterol_mg),
+            sodium: percent(facts.sodium_mg, diet.sodium_mg),
+            carbohydrates: percent(facts.carbohydrates_g, diet.carbohydrates_g),
+            fiber: percent(facts.fiber_g, diet.fiber_g),
+            added_sugar: percent(facts.added_sugar_g, diet.added_sugar_g),
+            protein: percent(facts.protein_g, diet.protein_g),
+            vitamin_d: percent(facts.vitamin_d_mcg, diet.vitamin_d_mcg),
+            calcium: percent(facts.calcium_mg, diet.calcium_mg),
+            iron: percent(facts.iron_mg, diet.iron_mg),
+            potassium: percent(facts.potassium_mg, diet.potassium_mg),
+            vitamin_c: percent(facts.vitamin_c_mg, diet.vitamin_c_mg),
+            magnesium: percent(facts.magnesium_mg, diet.magnesium_mg),
+        }
+    }
+}
+
+// Nutrition Facts for one serving of a recipe
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NutritionLabel {
+    pub recipe_id: Option<i64>,
+    pub recipe_name: String,
+    pub servings: f32,
+    pub per_serving: NutritionFacts,
+    pub daily_values: DailyValues,
+    pub reference_diet: ReferenceDiet,
+    // ingredients left out of the values
+    pub errors: Vec<NutritionError>,
+    // ingredients that make some values unknown
+    pub incomplete: Vec<IncompleteIngredient>,
+}
+
+// Shown for amounts that are not known for every ingredient
+const UNKNOWN: &str = "n/a";
+
+// One row of the panel, amounts already rounded for display
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelLine {
+    pub name: &'static str,
+    pub amount: String,
+    pub daily_value: Option<String>,
+    // sub nutrients like saturated fat are indented under their parent
+    pub indent: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelFormat {
+    #[default]
+    Html,
+    Text,
+    Json,
+}
+
+impl NutritionLabel {
+    // Rows in the order of the FDA panel. Protein has no %DV on a standard
+    // label, sugars and trans fat have no daily value at all. Vitamin C and
+    // magnesium are voluntary, so they are only listed when known
+    pub fn lines(&self) -> Vec<LabelLine> {
+        let facts = &self.per_serving;
+        let dv = &self.daily_values;
+        let line = |name,
+                    amount: Option<f32>,
+                    round: fn(f32) -> String,
+                    daily_value: Option<f32>,
+                    indent| LabelLine {
+            name,
+            amount: amount.map_or_else(|| UNKNOWN.to_string(), round),
+            daily_value: daily_value.map(|percent| format!("{}%", percent.round())),
+            indent,
+        };
+        let mut lines = vec![
+            line("Total Fat", facts.fat_g, round_fat, dv.fat, false),
+            line(
+                "Saturated Fat",
+                facts.saturated_fat_g,
+                round_fat,
+                dv.saturated_fat,
+                true,
+            ),
+            line("Trans Fat", facts.trans_fat_g, round_fat, None, true),
+            line(
+                "Cholesterol",
+                facts.cholesterol_mg,
+                round_cholesterol,
+                dv.cholesterol,
+                false,
+            ),
+            line(
+                "Sodium",
+                facts.sodium_mg,
+                round_milligrams,
+                dv.sodium,
+                false,
+            ),
+            line(
+                "Total Carbohydrate",
+                facts.carbohydrates_g,
+                round_grams,
+                dv.carbohydrates,
+                false,
+            ),
+            line("Dietary Fiber", facts.fiber_g, round_grams, dv.fiber, true),
+            line("Total Sugars", facts.sugar_g, round_grams, None, true),
+            line(
+                "Added Sugars",
+                facts.added_sugar_g,
+                round_grams,
+                dv.added_sugar,
+                true,
+            ),
+            line("Protein", facts.protein_g, round_grams, None, false),
+            line(
+                "Vitamin D",
+                facts.vitamin_d_mcg,
+                |mcg| round_micro(mcg, 0.1, "mcg"),
+                dv.vitamin_d,
+                false,
+            ),
+            line(
+                "Calcium",
+                facts.calcium_mg,
+                |mg| round_micro(mg, 10.0, "mg"),
+                dv.calcium,
+                false,
+            ),
+            line(
+                "Iron",
+                facts.iron_mg,
+                |mg| round_micro(mg, 0.1, "mg"),
+                dv.iron,
+                false,
+            ),
+            line(
+                "Potassium",
+                facts.potassium_mg,
+                round_milligrams,
+                dv.potassium,
+                false,
+            ),
+        ];
+        if facts.vitamin_c_mg.is_some() {
+            lines.push(line(
+                "Vitamin C",
+                facts.vitamin_c_mg,
+                |mg| round_micro(mg, 1.0, "mg"),
+                dv.vitamin_c,
+                false,
+            ));
+        }
+        if facts.magnesium_mg.is_some() {
+            lines.push(line(
+                "Magnesium",
+                facts.magnesium_mg,
+                |mg| round_micro(mg, 5.0, "mg"),
+                dv.magnesium,
+                false,
+            ));
+        }
+        lines
+    }
+
+    // Note on values that are left out or unknown, if any
+    fn incomplete_note(&self) -> Option<String> {
+        let mut notes = Vec::new();
+        if !self.errors.is_empty() {
+            notes.push(format!(
+                "{} ingredient(s) could not be included",
+                self.errors.len()
+            ));
+        }
+        if !self.incomplete.is_empty() {
+            notes.push(format!(
+                "{} means the value is not known for every ingredient",
+                UNKNOWN
+            ));
+        }
+        (!notes.is_empty()).then(|| format!("Incomplete: {}", notes.join(", ")))
+    }
+
+    pub fn calories(&self) -> String {
+        self.per_serving
+            .calories_kcal
+            .map_or_else(|| UNKNOWN.to_string(), round_calories)
+    }
+
+    fn footnote(&self) -> String {
+        format!(
+            "* The % Daily Value (DV) tells you how much a nutrient in a serving of \
+             food contributes to a daily diet. {} calories a day is used for general \
+             nutrition advice.",
+            thousands(self.reference_diet.calories_kcal.round() as i64)
+        )
+    }
+
+    fn servings_line(&self) -> String {
+        let servings = crate::unit::format_fraction(self.servings);
+        if self.servings == 1.0 {
+            "1 serving per recipe".to_string()
+        } else {
+            format!("{} servings per recipe", servings)
+        }
+    }
+
+    pub fn render_text(&self) -> String {
+        const WIDTH: usize = 40;
+        let rule = "-".repeat(WIDTH);
+        let mut out = vec![
+            "Nutrition Facts".to_string(),
+            self.recipe_name.clone(),
+            self.servings_line(),
+            "Serving size 1 serving".to_string(),
+            "=".repeat(WIDTH),
+            "Amount per serving".to_string(),
+            format!("{:<w$}{:>5}", "Calories", self.calories(), w = WIDTH - 5),
+            rule.clone(),
+            format!("{:>w$}", "% Daily Value*", w = WIDTH),
+        ];
+        for line in self.lines() {
+            let label = format!(
+                "{}{} {}",
+                if line.indent { "  " } else { "" },
+                line.name,
+                line.amount
+            );
+            let daily_value = line.daily_value.unwrap_or_default();
+            out.push(format!("{:<w$}{:>5}", label, daily_value, w = WIDTH - 5));
+        }
+        out.push(rule);
+        out.push(self.footnote());
+        out.extend(self.incomplete_note());
+        out.join("\n") + "\n"
+    }
+
+    pub fn render_html(&self) -> String {
+        let mut rows = String::new();
+        for line in self.lines() {
+            let class = if line.indent { " class=\"indent\"" } else { "" };
+            rows.push_str(&format!(
+                "<tr{}><th><b>{}</b> {}</th><td>{}</td></tr>\n",
+                class,
+                line.name,
+                line.amount,
+                line.daily_value.unwrap_or_default()
+            ));
+        }
+        let incomplete = self
+            .incomplete_note()
+            .map(|note| format!("<p class=\"incomplete\">{}</p>\n", note))
+            .unwrap_or_default();
+
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Nutrition Facts - {name}</title>
+<style>
+.nutrition-facts {{ border: 1px solid #000; padding: 4px 8px; width: 280px; font-family: Helvetica, Arial, sans-serif; }}
+.nutrition-facts h1 {{ font-size: 2em; font-weight: 900; margin: 0; }}
+.nutrition-facts table {{ width: 100%; border-collapse: collapse; }}
+.nutrition-facts th, .nutrition-facts td {{ border-top: 1px solid #000; font-weight: normal; text-align: left; padding: 2px 0; }}
+.nutrition-facts td {{ text-align: right; }}
+.nutrition-facts .indent th {{ padding-left: 1em; }}
+.nutrition-facts .calories {{ border-top: 8px solid #000; border-bottom: 4px solid #000; font-size: 1.5em; font-weight: 900; }}
+.nutrition-facts .footnote {{ border-top: 4px solid #000; font-size: 0.7em; }}
+</style>
+</head>
+<body>
+<section class="nutrition-facts">
+<h1>Nutrition Facts</h1>
+<p>{name}<br>{servings}<br><b>Serving size</b> 1 serving</p>
+<table>
+<tr class="calories"><th>Calories</th><td>{calories}</td></tr>
+<tr><th></th><td><b>% Daily Value*</b></td></tr>
+{rows}</table>
+<p class="footnote">{footnote}</p>
+{incomplete}</section>
+</body>
+</html>
+"#,
+            name = escape_html(&self.recipe_name),
+            servings = self.servings_line(),
+            calories = self.calories(),
+            rows = rows,
+            footnote = self.footnote(),
+            incomplete = incomplete,
+        )
+    }
+}
+
+// FDA rounding rules for the declared amounts (21 CFR 101.9(c))
+fn round_calories(kcal: f32) -> String {
+    if kcal < 5.0 {
+        "0".to_string()
+    } else if kcal <= 50.0 {
+        format!("{}", round_to(kcal, 5.0))
+    } else {
+        format!("{}", round_to(kcal, 10.0))
+    }
+}
+
+fn round_fat(grams: f32) -> String {
+    if grams < 0.5 {
+        "0g".to_string()
+    } else if grams < 5.0 {
+        format!("{}g", round_to(grams, 0.5))
+    } else {
+        format!("{}g", grams.round())
+    }
+}
+
+fn round_cholesterol(milligrams: f32) -> String {
+    if milligrams < 2.0 {
+        "0mg".to_string()
+    } else if milligrams <= 5.0 {
+        "less than 5mg".to_string()
+    } else {
+        format!("{}mg", round_to(milligrams, 5.0))
+    }
+}
+
+// sodium and potassium
+fn round_milligrams(milligrams: f32) -> String {
+    if milligrams < 5.0 {
+        "0mg".to_string()
+    } else if milligrams <= 140.0 {
+        format!("{}mg", round_to(milligrams, 5.0))
+    } else {
+        format!("{}mg", round_to(milligrams, 10.0))
+    }
+}
+
+// carbohydrates, fiber, sugars and protein
+fn round_grams(grams: f32) -> String {
+    if grams < 0.5 {
+        "0g".to_string()
+    } else if grams < 1.0 {
+        "less than 1g".to_string()
+    } else {
+        format!("{}g", grams.round())
+    }
+}
+
+// vitamins and minerals other than potassium
+fn round_micro(amount: f32, step: f32, unit: &str) -> String {
+    format!("{}{}", format_decimal(round_to(amount, step)), unit)
+}
+
+fn round_to(amount: f32, step: f32) -> f32 {
+    (amount / step).round() * step
+}
+
+fn thousands(value: i64) -> String {
+    let digits = value.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub async fn read_label(
+    pool: &Pool<Sqlite>,
+    recipe_id: i64,
+    diet: &ReferenceDiet,
+) -> Result<NutritionLabel> {
+    let recipe = recipe::read_one(pool, recipe_id).await?;
+    let converters = unit_conversion::read_recipe_converters(pool, &recipe).await?;
+    let nutrition = nutrition::calculate(&recipe, &converters);
+
+    Ok(NutritionLabel {
+        recipe_id: recipe.id,
+        recipe_name: recipe.name,
+        servings: nutrition.servings,
+        daily_values: DailyValues::new(&nutrition.per_serving, diet),
+        per_serving: nutrition.per_serving,
+        reference_diet: diet.clone(),
+        errors: nutrition.errors,
+        incomplete: nutrition.incomplete,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, get_connection_pool, ingredient};
+    use crate::label::*;
+    use tempfile::NamedTempFile;
+
+    fn label() -> NutritionLabel {
+        let per_serving = NutritionFacts {
+            calories_kcal: Some(234.0),
+            protein_g: Some(12.4),
+            carbohydrates_g: Some(30.2),
+            sugar_g: Some(0.7),
+            fat_g: Some(8.26),
+            saturated_fat_g: Some(3.1),
+            fiber_g: Some(4.0),
+            potassium_mg: Some(413.0),
+            sodium_mg: Some(468.0),
+            cholesterol_mg: Some(3.0),
+            added_sugar_g: Some(0.2),
+            trans_fat_g: Some(0.0),
+            iron_mg: Some(2.34),
+            calcium_mg: Some(261.0),
+            vitamin_d_mcg: Some(0.0),
+            ..Default::default()
+        };
+        let diet = ReferenceDiet::default();
+        NutritionLabel {
+            recipe_id: Some(1),
+            recipe_name: "Mac & cheese".to_string(),
+            servings: 4.0,
+            daily_values: DailyValues::new(&per_serving, &diet),
+            per_serving,
+            reference_diet: diet,
+            errors: vec![],
+            incomplete: vec![],
+        }
+    }
+
+    #[test]
+    fn test_daily_values() {
+        let label = label();
+        assert_eq!(label.daily_values.sodium.unwrap().round(), 20.0);
+        assert_eq!(label.daily_values.fiber.unwrap().round(), 14.0);
+        assert_eq!(label.daily_values.iron.unwrap().round(), 13.0);
+        assert_eq!(label.daily_values.magnesium, None);
+
+        // fat scales with calories, sodium doesn't
+        let diet = ReferenceDiet::for_calories(2500.0).unwrap();
+        assert_eq!(diet.fat_g, 97.5);
+        assert_eq!(diet.sodium_mg, 2300.0);
+        assert!(ReferenceDiet::for_calories(0.0).is_err());
+    }
+
+    #[test]
+    fn test_fda_rounding() {
+        assert_eq!(round_calories(3.0), "0");
+        assert_eq!(round_calories(47.0), "45");
+        assert_eq!(round_calories(234.0), "230");
+        assert_eq!(round_fat(0.4), "0g");
+        assert_eq!(round_fat(3.1), "3g");
+        assert_eq!(round_fat(3.3), "3.5g");
+        assert_eq!(round_fat(8.26), "8g");
+        assert_eq!(round_cholesterol(3.0), "less than 5mg");
+        assert_eq!(round_cholesterol(37.0), "35mg");
+        assert_eq!(round_milligrams(468.0), "470mg");
+        assert_eq!(round_milligrams(62.0), "60mg");
+        assert_eq!(round_grams(0.7), "less than 1g");
+        assert_eq!(round_grams(30.2), "30g");
+        assert_eq!(round_micro(2.34, 0.1, "mg"), "2.3mg");
+        assert_eq!(round_micro(261.0, 10.0, "mg"), "260mg");
+        assert_eq!(thousands(2000), "2,000");
+        assert_eq!(thousands(500), "500");
+    }
+
+    #[test]
+    fn test_render() {
+        let label = label();
+        let text = label.render_text();
+        assert!(text.starts_with("Nutrition Facts\nMac & cheese\n4 servings per recipe\n"));
+        assert!(text.contains(&format!("{:<35}{:>5}\n", "Calories", "230")));
+        assert!(text.contains(&format!("{:<35}{:>5}\n", "Sodium 470mg", "20%")));
+        assert!(text.contains(&format!("{:<35}{:>5}\n", "  Total Sugars less than 1g", "")));
+        assert!(text.contains(&format!("{:<35}{:>5}\n", "Iron 2.3mg", "13%")));
+        assert!(text.contains("2,000 calories a day"));
+        // voluntary nutrients are left out when unknown
+        assert!(!text.contains("Vitamin C"));
+
+        let html = label.render_html();
+        assert!(html.contains("<title>Nutrition Facts - Mac &amp; cheese</title>"));
+        assert!(html.contains("<tr><th><b>Sodium</b> 470mg</th><td>20%</td></tr>"));
+        assert!(
+            html.contains("<tr class=\"indent\"><th><b>Saturated Fat</b> 3g</th><td>16%</td></tr>")
+        );
+        assert!(!html.contains("Incomplete"));
+
+        // unknown values are marked, not shown as 0
+        let mut label = label;
+        label.per_serving.sodium_mg = None;
+        label.daily_values.sodium = None;
+        label.incomplete = vec![IncompleteIngredient {
+            ingredient_id: Some(1),
+            ingredient_name: "cheese".to_string(),
+            missing: vec!["sodium_mg".to_string()],
+        }];
+        let text = label.render_text();
+        assert!(text.contains(&format!("{:<35}{:>5}\n", "Sodium n/a", "")));
+        assert!(text.contains("Incomplete: n/a means the value is not known for every ingredient"));
+    }
+
+    #[tokio::test]
+    async fn test_read_label() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let butter = ingredient::read_by_name(&pool, "butter")
+            .await
+            .unwrap()
+            .unwrap();
+        let recipe_id = recipe::create(&pool, "butter").await.unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, butter.id.unwrap(), 4.0, "Tbsp")
+            .await
+            .unwrap();
+        recipe::update_servings(&pool, recipe_id, 2.0, None)
+            .await
+            .unwrap();
+
+        let label = read_label(&pool, recipe_id, &ReferenceDiet::default())
+            .await
+            .unwrap();
+        assert_eq!(label.servings, 2.0);
+        let butter = butter.nutrients;
+        assert_eq!(label.per_serving.fat_g, butter.fat_g.map(|fat| fat * 2.0));
+        assert_eq!(
+            label.daily_values.saturated_fat,
+            butter
+                .saturated_fat_g
+                .map(|saturated_fat| saturated_fat * 2.0 / 20.0 * 100.0)
+        );
+        assert!(label.errors.is_empty());
+    }
+}
@@ -0,0 +1,13 @@
+pub mod api;
+pub mod config;
+pub mod crud;
+pub mod error;
+pub mod ingredient_line;
+pub mod jsonld;
+pub mod label;
+pub mod nutrition;
+pub mod planner;
+pub mod timing;
+pub mod unit;
+pub mod usda;
+pub mod validate;
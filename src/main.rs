@@ -1,45 +1,580 @@
 use actix_files::Files;
-use actix_web::{get, http::header::ContentType, App, HttpResponse, HttpServer, Responder};
+use actix_web::middleware::Logger;
+use actix_web::{web, App, HttpServer};
+use clap::{Parser, Subcommand};
+use meal_planner::config::{Config, ConfigLayer};
+use meal_planner::crud::grocery_list::{GroceryList, RecipeServings};
+use meal_planner::crud::recipe::{Recipe, RecipeInput};
+use meal_planner::crud::seed;
+use meal_planner::error::{Error, Result};
+use meal_planner::ingredient_line::{self, ParsedIngredient};
+use meal_planner::label::{self, ReferenceDiet};
+use meal_planner::planner::{self, PlanRequest};
+use meal_planner::unit::{format_decimal, format_fraction};
+use meal_planner::usda::{self, ImportAction, ImportOptions, ImportPreview};
+use meal_planner::{api, crud, jsonld, validate};
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
 
-mod crud;
+#[derive(Parser)]
+#[command(about = "Meal planner web server and database tools")]
+struct Cli {
+    /// TOML config file [default: meal-planner.toml if it exists]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// SQLite database file [default: foodbuddy.db]
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+    /// Seed data directory [default: data]
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Static files served at / [default: web-ui/build]
+    #[arg(long, global = true)]
+    static_dir: Option<PathBuf>,
+    /// Address the server listens on [default: 127.0.0.1:8000]
+    #[arg(long, global = true)]
+    bind: Option<String>,
+    /// off, error, warn, info, debug or trace [default: info]
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    /// Print results as JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the web server (the default)
+    Serve,
+    /// Apply migrations and seed ingredients and tags from data/
+    Seed,
+    /// Validate the seed data files without touching the database
+    Check,
+    /// Apply pending migrations
+    Migrate {
+        /// Only list the migrations that would be applied
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Add, list, show and delete recipes
+    #[command(subcommand)]
+    Recipe(RecipeCommand),
+    /// Look up ingredients
+    #[command(subcommand)]
+    Ingredient(IngredientCommand),
+    /// Show meal plans
+    #[command(subcommand)]
+    Plan(PlanCommand),
+    /// Create a grocery list from a meal plan or recipes and print it
+    GroceryList {
+        name: String,
+        /// Meal plan to shop for
+        #[arg(long, conflicts_with = "recipe")]
+        plan: Option<i64>,
+        /// Recipe to shop for as ID or ID:SERVINGS, can be repeated
+        #[arg(long, value_parser = parse_recipe_servings)]
+        recipe: Vec<RecipeServings>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RecipeCommand {
+    /// Add a recipe from a JSON file in the POST /api/recipes format, `-` reads stdin
+    Add {
+        file: PathBuf,
+    },
+    /// Import a schema.org Recipe from a JSON-LD file or a saved HTML page,
+    /// `-` reads stdin
+    Import {
+        file: PathBuf,
+    },
+    /// Print a recipe as schema.org Recipe JSON-LD
+    Export {
+        id: i64,
+    },
+    List,
+    /// Show a recipe, optionally scaled with amounts in friendly units
+    Show {
+        id: i64,
+        #[arg(long, conflicts_with = "scale")]
+        servings: Option<f32>,
+        #[arg(long)]
+        scale: Option<f32>,
+    },
+    /// Print the Nutrition Facts label of one serving
+    Label {
+        id: i64,
+        /// HTML instead of plain text
+        #[arg(long)]
+        html: bool,
+    },
+    /// Refused while meal plans use the recipe, unless --cascade removes
+    /// it from them
+    Delete {
+        id: i64,
+        #[arg(long)]
+        cascade: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum IngredientCommand {
+    Search {
+        query: String,
+    },
+    /// Parse free text lines like "2 cups chopped onion" and match them
+    /// against the catalog
+    Parse {
+        lines: Vec<String>,
+    },
+    /// Import foods from an unzipped USDA FoodData Central CSV download
+    ImportUsda {
+        dir: PathBuf,
+        /// Food to import, can be repeated [default: all foods in the bundle]
+        #[arg(long = "fdc-id")]
+        fdc_ids: Vec<i64>,
+        /// Ingredient name for a food as FDC_ID=NAME, can be repeated
+        #[arg(long = "name", value_parser = parse_fdc_name)]
+        names: Vec<(i64, String)>,
+        /// Overwrite ingredients with the same name that were not imported
+        #[arg(long)]
+        replace: bool,
+        /// Show what would be imported without changing the database
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
 
-const DB_FILENAME: &str = "foodbuddy.db";
+#[derive(Subcommand)]
+enum PlanCommand {
+    List,
+    Show {
+        id: i64,
+    },
+    /// Generate a plan from a JSON file in the POST /api/meal-plans/generate
+    /// format, `-` reads stdin
+    Generate {
+        file: PathBuf,
+    },
+}
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let pool = crud::get_connection_pool(DB_FILENAME).await;
-    crud::create_tables(&pool).await.unwrap();
-    crud::populate_tables(&pool).await.unwrap();
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = run(cli).await {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let config = Config::load(
+        cli.config.as_deref(),
+        ConfigLayer {
+            database: cli.db,
+            data_dir: cli.data_dir,
+            static_dir: cli.static_dir,
+            bind: cli.bind,
+            log_level: cli.log_level,
+            ..Default::default()
+        },
+    )?;
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .init();
+
+    let command = cli.command.unwrap_or(Command::Serve);
+    if let Command::Check = command {
+        let diagnostics = validate::check_data_dir(&config.data_dir)?;
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        if !diagnostics.is_empty() {
+            process::exit(1);
+        }
+        println!("Seed data is valid");
+        return Ok(());
+    }
 
-    HttpServer::new(|| {
+    let pool = crud::get_connection_pool_with(&config.database, &config.pool).await?;
+    if let Command::Migrate { dry_run } = command {
+        let migrations = if dry_run {
+            crud::migration::pending(&pool).await?
+        } else {
+            crud::migration::run(&pool).await?
+        };
+        if migrations.is_empty() {
+            println!("Database is up to date");
+        }
+        for migration in migrations {
+            println!("{:04} {}", migration.version, migration.name);
+        }
+        return Ok(());
+    }
+
+    crud::create_tables(&pool).await?;
+    match command {
+        Command::Serve => {
+            let report = seed::run(&pool, &config.data_dir).await?;
+            log::info!("Seeded data: {}", report);
+            serve(pool, &config).await?;
+        }
+        Command::Seed => {
+            let report = seed::run(&pool, &config.data_dir).await?;
+            print(cli.json, &report, |report| println!("{}", report));
+        }
+        Command::Recipe(RecipeCommand::Add { file }) => {
+            let recipe: RecipeInput = serde_json::from_str(&read_input(&file)?)?;
+            let recipe_id = crud::recipe::create_full(&pool, &recipe).await?;
+            let recipe = crud::recipe::read_one(&pool, recipe_id).await?;
+            print(cli.json, &recipe, print_recipe);
+        }
+        Command::Recipe(RecipeCommand::Import { file }) => {
+            let imported = jsonld::import(&pool, &read_input(&file)?).await?;
+            print(cli.json, &imported, |imported| {
+                print_recipe(&imported.recipe);
+                for line in &imported.unmatched {
+                    println!("Not in the ingredient catalog, kept as a note: {}", line);
+                }
+            });
+        }
+        Command::Recipe(RecipeCommand::Export { id }) => {
+            let recipe = crud::recipe::read_one(&pool, id).await?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&jsonld::export(&recipe))?
+            );
+        }
+        Command::Recipe(RecipeCommand::List) => {
+            let recipes = crud::recipe::read(&pool).await?;
+            print(cli.json, &recipes, |recipes| {
+                for recipe in recipes {
+                    println!("{:>4}  {}", recipe.id.unwrap_or_default(), recipe.name);
+                }
+            });
+        }
+        Command::Recipe(RecipeCommand::Show {
+            id,
+            servings,
+            scale,
+        }) => {
+            let mut recipe = crud::recipe::read_one(&pool, id).await?;
+            if let Some(servings) = servings {
+                recipe.scale_to_servings(servings)?;
+            }
+            if let Some(scale) = scale {
+                recipe.scale(scale)?;
+            }
+            print(cli.json, &recipe, print_recipe);
+        }
+        Command::Recipe(RecipeCommand::Label { id, html }) => {
+            let diet = ReferenceDiet::for_calories(config.reference_calories)?;
+            let label = label::read_label(&pool, id, &diet).await?;
+            print(cli.json, &label, |label| {
+                if html {
+                    print!("{}", label.render_html());
+                } else {
+                    print!("{}", label.render_text());
+                }
+            });
+        }
+        Command::Recipe(RecipeCommand::Delete { id, cascade }) => {
+            crud::recipe::delete(&pool, id, cascade).await?;
+            println!("Deleted recipe {}", id);
+        }
+        Command::Ingredient(IngredientCommand::Search { query }) => {
+            let ingredients = crud::ingredient::read_by_search_string(&pool, &query).await?;
+            print(cli.json, &ingredients, |ingredients| {
+                for ingredient in ingredients {
+                    println!(
+                        "{:>4}  {}",
+                        ingredient.id.unwrap_or_default(),
+                        ingredient.name
+                    );
+                }
+            });
+        }
+        Command::Ingredient(IngredientCommand::Parse { lines }) => {
+            let mut parsed = Vec::new();
+            for line in &lines {
+                parsed.push(ingredient_line::parse_and_match(&pool, line).await?);
+            }
+            print(cli.json, &parsed, |parsed| {
+                for ingredient in parsed {
+                    print_parsed_ingredient(ingredient);
+                }
+            });
+        }
+        Command::Ingredient(IngredientCommand::ImportUsda {
+            dir,
+            fdc_ids,
+            names,
+            replace,
+            dry_run,
+        }) => {
+            let foods = usda::read_bundle(&dir, &fdc_ids)?;
+            let options = ImportOptions {
+                names: names.into_iter().collect(),
+                replace,
+            };
+            let previews = usda::preview(&pool, &foods, &options).await?;
+            if dry_run || !cli.json {
+                print(cli.json, &previews, |previews| {
+                    for preview in previews {
+                        print_import_preview(preview);
+                    }
+                });
+            }
+            if !dry_run {
+                let report = usda::import(&pool, &foods, &options).await?;
+                print(cli.json, &report, |report| println!("{}", report));
+            }
+        }
+        Command::Plan(PlanCommand::List) => {
+            let meal_plans = crud::meal_plan::read(&pool).await?;
+            print(cli.json, &meal_plans, |meal_plans| {
+                for meal_plan in meal_plans {
+                    println!(
+                        "{:>4}  {}",
+                        meal_plan.id.unwrap_or_default(),
+                        meal_plan.name
+                    );
+                }
+            });
+        }
+        Command::Plan(PlanCommand::Show { id }) => {
+            let meal_plan = crud::meal_plan::read_one(&pool, id).await?;
+            print(cli.json, &meal_plan, |meal_plan| {
+                println!("{}", meal_plan.name);
+                for entry in &meal_plan.entries {
+                    println!(
+                        "  {}  {:<9}  {} ({} servings)",
+                        entry.date,
+                        entry.meal,
+                        entry.recipe_name.as_deref().unwrap_or("?"),
+                        entry.servings
+                    );
+                }
+                for warning in &meal_plan.warnings {
+                    println!("Warning: {}", warning);
+                }
+            });
+        }
+        Command::Plan(PlanCommand::Generate { file }) => {
+            let request: PlanRequest = serde_json::from_str(&read_input(&file)?)?;
+            let generated = planner::create(&pool, &request).await?;
+            print(cli.json, &generated, |generated| {
+                println!(
+                    "Created meal plan {} ({})",
+                    generated.meal_plan.id.unwrap_or_default(),
+                    generated.meal_plan.name
+                );
+                for day in &generated.days {
+                    println!(
+                        "{}  {} kcal, {}g protein, {}g carbs, {}g fat, {}g fiber, {}mg sodium",
+                        day.date,
+                        day.totals.calories_kcal.unwrap_or_default().round(),
+                        day.totals.protein_g.unwrap_or_default().round(),
+                        day.totals.carbohydrates_g.unwrap_or_default().round(),
+                        day.totals.fat_g.unwrap_or_default().round(),
+                        day.totals.fiber_g.unwrap_or_default().round(),
+                        day.totals.sodium_mg.unwrap_or_default().round()
+                    );
+                    for entry in &day.entries {
+                        println!(
+                            "  {:<9}  {} ({} servings)",
+                            entry.meal,
+                            entry.recipe_name.as_deref().unwrap_or("?"),
+                            entry.servings
+                        );
+                    }
+                }
+            });
+        }
+        Command::GroceryList { name, plan, recipe } => {
+            let grocery_list_id = match plan {
+                Some(meal_plan_id) => {
+                    crud::grocery_list::create_from_meal_plan(&pool, &name, meal_plan_id).await?
+                }
+                None if recipe.is_empty() => {
+                    return Err(Error::Validation(
+                        "Pass --plan or at least one --recipe".to_string(),
+                    ))
+                }
+                None => crud::grocery_list::create(&pool, &name, None, &recipe).await?,
+            };
+            let grocery_list = crud::grocery_list::read_one(&pool, grocery_list_id).await?;
+            print(cli.json, &grocery_list, print_grocery_list);
+        }
+        Command::Check | Command::Migrate { .. } => unreachable!(),
+    }
+
+    Ok(())
+}
+
+async fn serve(pool: Pool<Sqlite>, config: &Config) -> Result<()> {
+    let static_dir = config.static_dir.clone();
+    let diet = web::Data::new(ReferenceDiet::for_calories(config.reference_calories)?);
+    log::info!("Listening on http://{}", config.bind);
+    HttpServer::new(move || {
         App::new()
-            .service(get_recipes)
-            .service(get_ingredients)
-            .service(get_tags)
-            .service(Files::new("/", "./web-ui/build").index_file("index.html"))
+            .wrap(Logger::default())
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(diet.clone())
+            .configure(api::configure)
+            .service(Files::new("/", &static_dir).index_file("index.html"))
     })
-    .bind("127.0.0.1:8000")?
+    .bind(&config.bind)?
     .run()
-    .await
+    .await?;
+
+    Ok(())
+}
+
+// Contents of `file`, or of stdin for `-`
+fn read_input(file: &Path) -> Result<String> {
+    if file == Path::new("-") {
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        Ok(content)
+    } else {
+        Ok(fs::read_to_string(file)?)
+    }
 }
 
-#[get("/api/recipes")]
-async fn get_recipes() -> impl Responder {
-    let pool = crud::get_connection_pool(DB_FILENAME).await;
-    let recipes = crud::recipe::read(&pool).await.unwrap();
-    HttpResponse::Ok().json(recipes)
+fn print<T: Serialize>(json: bool, value: &T, text: impl Fn(&T)) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value).unwrap());
+    } else {
+        text(value);
+    }
 }
 
-#[get("/api/ingredients")]
-async fn get_ingredients() -> impl Responder {
-    let pool = crud::get_connection_pool(DB_FILENAME).await;
-    let ingredients = crud::ingredient::read(&pool).await.unwrap();
-    HttpResponse::Ok().json(ingredients)
+fn print_recipe(recipe: &Recipe) {
+    println!("{}", recipe.name);
+    match &recipe.recipe_yield {
+        Some(recipe_yield) => println!("Serves {} ({})", recipe.servings, recipe_yield),
+        None => println!("Serves {}", recipe.servings),
+    }
+    if recipe.time.total_minutes > 0.0 {
+        println!(
+            "Time: {} min ({} active, {} passive)",
+            format_decimal(recipe.time.total_minutes),
+            format_decimal(recipe.time.active_minutes),
+            format_decimal(recipe.time.passive_minutes)
+        );
+    }
+    if !recipe.tags.is_empty() {
+        let tags: Vec<&str> = recipe.tags.iter().map(|tag| tag.name.as_str()).collect();
+        println!("Tags: {}", tags.join(", "));
+    }
+    println!("\nIngredients:");
+    for ingredient in &recipe.ingredients {
+        println!(
+            "  {} {} {}",
+            ingredient.unit.format_amount(ingredient.amount),
+            ingredient.unit,
+            ingredient.ingredient.name
+        );
+    }
+    println!("\nInstructions:");
+    for (i, step) in recipe.instructions.iter().enumerate() {
+        if let Some(section) = &step.section {
+            println!("  {}:", section);
+        }
+        println!("  {}. {}", i + 1, step.text);
+    }
+    if !recipe.notes.is_empty() {
+        println!("\nNotes:");
+        for note in &recipe.notes {
+            println!("  - {}", note);
+        }
+    }
 }
 
-#[get("/api/tags")]
-async fn get_tags() -> impl Responder {
-    let pool = crud::get_connection_pool(DB_FILENAME).await;
-    let tags = crud::tag::read(&pool).await.unwrap();
-    HttpResponse::Ok().json(tags)
+fn print_grocery_list(grocery_list: &GroceryList) {
+    println!("{}", grocery_list.name);
+    for item in &grocery_list.items {
+        let checked = if item.checked { "x" } else { " " };
+        println!(
+            "  [{}] {} {} {}",
+            checked,
+            item.unit.format_amount(item.amount),
+            item.unit,
+            item.ingredient_name
+        );
+    }
+}
+
+fn print_parsed_ingredient(parsed: &ParsedIngredient) {
+    let line = &parsed.line;
+    let mut amount = line.amount.map(format_fraction).unwrap_or_default();
+    if let Some(max) = line.amount_max {
+        amount = format!("{}-{}", amount, format_fraction(max));
+    }
+    let unit = line
+        .unit
+        .map(|unit| unit.label().to_string())
+        .or_else(|| line.measure.clone())
+        .unwrap_or_default();
+    print!("{:>5} {:<6} {}", amount, unit, line.name);
+    match &line.note {
+        Some(note) => println!(" ({})", note),
+        None => println!(),
+    }
+    for candidate in &parsed.candidates {
+        println!(
+            "             {:>4}  {:<30} {:.2}",
+            candidate.id, candidate.name, candidate.confidence
+        );
+    }
+}
+
+fn print_import_preview(preview: &ImportPreview) {
+    let action = match &preview.action {
+        ImportAction::Add => "add".to_string(),
+        ImportAction::Update { ingredient_id } => format!("update {}", ingredient_id),
+        ImportAction::Skip { reason, .. } => format!("skip: {}", reason),
+    };
+    println!("{:>7}  {}  ({})", preview.fdc_id, preview.name, action);
+    for similar in &preview.similar {
+        println!("         similar to {:>4}  {}", similar.id, similar.name);
+    }
+}
+
+// FDC_ID=NAME
+fn parse_fdc_name(value: &str) -> std::result::Result<(i64, String), String> {
+    let (fdc_id, name) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected FDC_ID=NAME, got '{}'", value))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("Missing name for food {}", fdc_id));
+    }
+    Ok((
+        fdc_id
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid FDC id '{}'", fdc_id))?,
+        name.to_string(),
+    ))
+}
+
+// ID or ID:SERVINGS, servings default to 1
+fn parse_recipe_servings(value: &str) -> std::result::Result<RecipeServings, String> {
+    let (recipe_id, servings) = value.split_once(':').unwrap_or((value, "1"));
+    Ok(RecipeServings {
+        recipe_id: recipe_id
+            .parse()
+            .map_err(|_| format!("Invalid recipe id '{}'", recipe_id))?,
+        servings: servings
+            .parse()
+            .map_err(|_| format!("Invalid servings '{}'", servings))?,
+    })
 }
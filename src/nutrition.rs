@@ -0,0 +1,400 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use std::ops::AddAssign;
+
+use crate::crud::recipe::{self, Recipe};
+use crate::crud::unit_conversion::{self, UnitConverter};
+use crate::error::Result;
+use crate::unit::Unit;
+
+// Nutrient amounts, None where the value is unknown. Stored as rows of
+// `ingredient_nutrients` keyed by the field name, see `values`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct NutritionFacts {
+    pub calories_kcal: Option<f32>,
+    pub protein_g: Option<f32>,
+    pub carbohydrates_g: Option<f32>,
+    pub sugar_g: Option<f32>,
+    pub fat_g: Option<f32>,
+    pub saturated_fat_g: Option<f32>,
+    pub fiber_g: Option<f32>,
+    pub potassium_mg: Option<f32>,
+    pub sodium_mg: Option<f32>,
+    pub cholesterol_mg: Option<f32>,
+    pub added_sugar_g: Option<f32>,
+    pub trans_fat_g: Option<f32>,
+    pub iron_mg: Option<f32>,
+    pub calcium_mg: Option<f32>,
+    pub magnesium_mg: Option<f32>,
+    pub vitamin_c_mg: Option<f32>,
+    pub vitamin_d_mcg: Option<f32>,
+}
+
+impl NutritionFacts {
+    // Every nutrient known and 0, the starting point for totals
+    pub fn zero() -> Self {
+        let mut facts = NutritionFacts::default();
+        for (_, value) in facts.values_mut() {
+            *value = Some(0.0);
+        }
+        facts
+    }
+
+    // (name, value) for every nutrient, the name is the field name
+    pub fn values(&self) -> [(&'static str, Option<f32>); 17] {
+        let mut facts = self.clone();
+        facts.values_mut().map(|(name, value)| (name, *value))
+    }
+
+    fn values_mut(&mut self) -> [(&'static str, &mut Option<f32>); 17] {
+        [
+            ("calories_kcal", &mut self.calories_kcal),
+            ("protein_g", &mut self.protein_g),
+            ("carbohydrates_g", &mut self.carbohydrates_g),
+            ("sugar_g", &mut self.sugar_g),
+            ("fat_g", &mut self.fat_g),
+            ("saturated_fat_g", &mut self.saturated_fat_g),
+            ("fiber_g", &mut self.fiber_g),
+            ("potassium_mg", &mut self.potassium_mg),
+            ("sodium_mg", &mut self.sodium_mg),
+            ("cholesterol_mg", &mut self.cholesterol_mg),
+            ("added_sugar_g", &mut self.added_sugar_g),
+            ("trans_fat_g", &mut self.trans_fat_g),
+            ("iron_mg", &mut self.iron_mg),
+            ("calcium_mg", &mut self.calcium_mg),
+            ("magnesium_mg", &mut self.magnesium_mg),
+            ("vitamin_c_mg", &mut self.vitamin_c_mg),
+            ("vitamin_d_mcg", &mut self.vitamin_d_mcg),
+        ]
+    }
+
+    // Set a nutrient by name, false if there is no such nutrient
+    pub fn set(&mut self, nutrient: &str, amount: Option<f32>) -> bool {
+        match self
+            .values_mut()
+            .into_iter()
+            .find(|(name, _)| *name == nutrient)
+        {
+            Some((_, value)) => {
+                *value = amount;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Names of the nutrients with unknown values
+    pub fn missing(&self) -> Vec<&'static str> {
+        self.values()
+            .into_iter()
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    pub fn scaled(&self, factor: f32) -> Self {
+        let mut facts = self.clone();
+        for (_, value) in facts.values_mut() {
+            *value = value.map(|value| value * factor);
+        }
+        facts
+    }
+}
+
+// An unknown value makes the sum unknown, it is not counted as 0
+impl AddAssign<&NutritionFacts> for NutritionFacts {
+    fn add_assign(&mut self, other: &NutritionFacts) {
+        for ((_, value), (_, other)) in self.values_mut().into_iter().zip(other.values()) {
+            *value = value.zip(other).map(|(a, b)| a + b);
+        }
+    }
+}
+
+// An ingredient line that could not be included in the totals
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NutritionError {
+    pub ingredient_id: Option<i64>,
+    pub ingredient_name: String,
+    pub amount: f32,
+    pub unit: Unit,
+    pub reference_unit: Unit,
+    pub message: String,
+}
+
+// An ingredient line without values for some nutrients, which makes those
+// nutrients unknown in the recipe totals
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IncompleteIngredient {
+    pub ingredient_id: Option<i64>,
+    pub ingredient_name: String,
+    pub missing: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecipeNutrition {
+    pub recipe_id: Option<i64>,
+    pub servings: f32,
+    pub totals: NutritionFacts,
+    pub per_serving: NutritionFacts,
+    pub errors: Vec<NutritionError>,
+    pub incomplete: Vec<IncompleteIngredient>,
+}
+
+// Sum each ingredient's nutrients scaled by
+// (recipe amount converted to the reference unit / reference amount).
+// `converters` is keyed by ingredient id, missing entries only allow
+// conversions within the same kind of unit. Lines that can't be converted
+// are left out and listed in `errors`, nutrients an included ingredient has
+// no value for are unknown in the totals and listed in `incomplete`
+pub fn calculate(recipe: &Recipe, converters: &HashMap<i64, UnitConverter>) -> RecipeNutrition {
+    let default_converter = UnitConverter::default();
+    let mut totals = NutritionFacts::zero();
+    let mut errors = Vec::new();
+    let mut incomplete = Vec::new();
+
+    for line in &recipe.ingredients {
+        let ingredient = &line.ingredient;
+        let converter = ingredient
+            .id
+            .and_then(|id| converters.get(&id))
+            .unwrap_or(&default_converter);
+
+        let error = |message: String| NutritionError {
+            ingredient_id: ingredient.id,
+            ingredient_name: ingredient.name.clone(),
+            amount: line.amount,
+            unit: line.unit,
+            reference_unit: ingredient.unit,
+            message,
+        };
+
+        if ingredient.amount <= 0.0 {
+            errors.push(error(format!(
+                "'{}' has a reference amount of {}",
+                ingredient.name, ingredient.amount
+            )));
+            continue;
+        }
+
+        match converter.convert(line.amount, line.unit, ingredient.unit) {
+            Some(amount) => {
+                let missing = ingredient.nutrients.missing();
+                if !missing.is_empty() {
+                    incomplete.push(IncompleteIngredient {
+                        ingredient_id: ingredient.id,
+                        ingredient_name: ingredient.name.clone(),
+                        missing: missing.into_iter().map(String::from).collect(),
+                    });
+                }
+                totals += &ingredient.nutrients.scaled(amount / ingredient.amount);
+            }
+            None => errors.push(error(format!(
+                "can't convert {} to {} for '{}'",
+                line.unit, ingredient.unit, ingredient.name
+            ))),
+        }
+    }
+
+    let per_serving = if recipe.servings > 0.0 {
+        totals.scaled(1.0 / recipe.servings)
+    } else {
+        totals.clone()
+    };
+
+    RecipeNutrition {
+        recipe_id: recipe.id,
+        servings: recipe.servings,
+        totals,
+        per_serving,
+        errors,
+        incomplete,
+    }
+}
+
+// Nutrition for the recipe, optionally rescaled to a number of servings
+pub async fn read_recipe_nutrition(
+    pool: &Pool<Sqlite>,
+    recipe_id: i64,
+    servings: Option<f32>,
+) -> Result<RecipeNutrition> {
+    let mut recipe = recipe::read_one(pool, recipe_id).await?;
+    if let Some(servings) = servings {
+        recipe.rescale(servings);
+    }
+    let converters = unit_conversion::read_recipe_converters(pool, &recipe).await?;
+
+    Ok(calculate(&recipe, &converters))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::ingredient::{self, Ingredient};
+    use crate::crud::{self, get_connection_pool, recipe};
+    use crate::nutrition::*;
+    use tempfile::NamedTempFile;
+
+    fn assert_close(a: Option<f32>, b: f32) {
+        let a = a.expect("value is unknown");
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    async fn find_ingredient(pool: &Pool<Sqlite>, name: &str) -> Ingredient {
+        ingredient::read(pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|i| i.name == name)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_recipe_nutrition() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        // butter is 1 Tbsp, garlic is 1 clove at 3g, bay leaves have no volume conversion
+        let butter = find_ingredient(&pool, "butter").await;
+        let garlic = find_ingredient(&pool, "garlic").await;
+        let bay_leaves = find_ingredient(&pool, "bay leaves").await;
+
+        let recipe_id = recipe::create(&pool, "garlic butter").await.unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, butter.id.unwrap(), 6.0, "tsp")
+            .await
+            .unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, garlic.id.unwrap(), 9.0, "g")
+            .await
+            .unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, bay_leaves.id.unwrap(), 1.0, "cup")
+            .await
+            .unwrap();
+
+        let nutrition = read_recipe_nutrition(&pool, recipe_id, None).await.unwrap();
+        let (butter, garlic) = (&butter.nutrients, &garlic.nutrients);
+        let expected = butter.calories_kcal.unwrap() * 2.0 + garlic.calories_kcal.unwrap() * 3.0;
+        assert_close(nutrition.totals.calories_kcal, expected);
+        assert_close(
+            nutrition.totals.fat_g,
+            butter.fat_g.unwrap() * 2.0 + garlic.fat_g.unwrap() * 3.0,
+        );
+
+        assert_eq!(nutrition.errors.len(), 1);
+        assert_eq!(nutrition.errors[0].ingredient_name, "bay leaves");
+        assert_eq!(nutrition.errors[0].unit, Unit::Cup);
+        assert_eq!(nutrition.errors[0].reference_unit, Unit::Leaf);
+    }
+
+    #[tokio::test]
+    async fn test_per_serving_nutrition() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let butter = find_ingredient(&pool, "butter").await;
+        let recipe_id = recipe::create(&pool, "butter").await.unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, butter.id.unwrap(), 4.0, "Tbsp")
+            .await
+            .unwrap();
+        recipe::update_servings(&pool, recipe_id, 2.0, None)
+            .await
+            .unwrap();
+
+        let calories_kcal = butter.nutrients.calories_kcal.unwrap();
+
+        let nutrition = read_recipe_nutrition(&pool, recipe_id, None).await.unwrap();
+        assert_eq!(nutrition.servings, 2.0);
+        assert_close(nutrition.totals.calories_kcal, calories_kcal * 4.0);
+        assert_close(nutrition.per_serving.calories_kcal, calories_kcal * 2.0);
+
+        // rescaling changes the totals but not the per serving values
+        let nutrition = read_recipe_nutrition(&pool, recipe_id, Some(6.0))
+            .await
+            .unwrap();
+        assert_eq!(nutrition.servings, 6.0);
+        assert_close(nutrition.totals.calories_kcal, calories_kcal * 12.0);
+        assert_close(nutrition.per_serving.calories_kcal, calories_kcal * 2.0);
+    }
+
+    #[test]
+    fn test_scaled_and_add() {
+        let facts = NutritionFacts {
+            calories_kcal: Some(100.0),
+            protein_g: Some(10.0),
+            fat_g: Some(0.0),
+            ..Default::default()
+        };
+        let mut totals = NutritionFacts::zero();
+        totals += &facts.scaled(0.5);
+        totals += &facts;
+        assert_close(totals.calories_kcal, 150.0);
+        assert_close(totals.protein_g, 15.0);
+        assert_close(totals.fat_g, 0.0);
+        // unknown is not summed as 0
+        assert_eq!(totals.iron_mg, None);
+        assert!(totals.missing().contains(&"iron_mg"));
+
+        let mut facts = NutritionFacts::default();
+        assert!(facts.set("iron_mg", Some(2.0)));
+        assert!(!facts.set("unobtainium_mg", Some(2.0)));
+        assert_eq!(facts.iron_mg, Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_nutrition() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let mut butter = find_ingredient(&pool, "butter").await;
+        butter.nutrients.iron_mg = Some(0.1);
+        ingredient::update(&pool, butter.id.unwrap(), &butter)
+            .await
+            .unwrap();
+        let mut garlic = find_ingredient(&pool, "garlic").await;
+        garlic.nutrients.sodium_mg = None;
+        garlic.nutrients.iron_mg = Some(0.3);
+        ingredient::update(&pool, garlic.id.unwrap(), &garlic)
+            .await
+            .unwrap();
+
+        let recipe_id = recipe::create(&pool, "garlic butter").await.unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, butter.id.unwrap(), 1.0, "Tbsp")
+            .await
+            .unwrap();
+        recipe::add_recipe_ingredient(&pool, recipe_id, garlic.id.unwrap(), 1.0, "clove")
+            .await
+            .unwrap();
+
+        let nutrition = read_recipe_nutrition(&pool, recipe_id, None).await.unwrap();
+        assert!(nutrition.errors.is_empty());
+        assert_close(nutrition.totals.iron_mg, 0.4);
+        assert_eq!(nutrition.totals.sodium_mg, None);
+        assert_eq!(nutrition.per_serving.sodium_mg, None);
+        assert!(nutrition.totals.calories_kcal.is_some());
+
+        assert_eq!(nutrition.incomplete.len(), 2);
+        let garlic = &nutrition.incomplete[1];
+        assert_eq!(garlic.ingredient_name, "garlic");
+        assert!(garlic.missing.contains(&"sodium_mg".to_string()));
+        assert!(!nutrition.incomplete[0]
+            .missing
+            .contains(&"sodium_mg".to_string()));
+    }
+}
@@ -0,0 +1,684 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+
+use crate::crud::meal_plan::{self, MealPlan, MealPlanEntry, MealSlot};
+use crate::crud::{recipe, unit_conversion};
+use crate::error::{Error, Result};
+use crate::nutrition::{self, NutritionFacts};
+use crate::timing::parse_date;
+
+// Daily targets. Fiber only counts when there is too little of it and
+// sodium is a cap, only going over it counts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NutritionTargets {
+    pub calories_kcal: f32,
+    pub protein_g: f32,
+    pub carbohydrates_g: f32,
+    pub fat_g: f32,
+    pub fiber_g: f32,
+    pub sodium_mg: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlanRequest {
+    pub name: String,
+    pub start_date: String, // YYYY-MM-DD
+    #[serde(default = "default_days")]
+    pub days: u32,
+    #[serde(default = "default_meals")]
+    pub meals: Vec<MealSlot>,
+    pub targets: NutritionTargets,
+    // tag names, recipes need all of the required ones and none of the excluded
+    #[serde(default)]
+    pub required_tags: Vec<String>,
+    #[serde(default)]
+    pub excluded_tags: Vec<String>,
+    // a recipe is used at most once in any this many days in a row
+    #[serde(default = "default_variety_days")]
+    pub variety_days: u32,
+    // servings per meal go from 0.5 up to this in steps of 0.5
+    #[serde(default = "default_max_servings")]
+    pub max_servings: f32,
+}
+
+fn default_days() -> u32 {
+    7
+}
+
+fn default_meals() -> Vec<MealSlot> {
+    vec![MealSlot::Breakfast, MealSlot::Lunch, MealSlot::Dinner]
+}
+
+fn default_variety_days() -> u32 {
+    3
+}
+
+fn default_max_servings() -> f32 {
+    3.0
+}
+
+// A recipe the planner can pick, with nutrients for one serving
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlanRecipe {
+    pub id: i64,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub per_serving: NutritionFacts,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlannedDay {
+    pub date: String,
+    pub entries: Vec<MealPlanEntry>,
+    pub totals: NutritionFacts,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GeneratedPlan {
+    pub meal_plan: MealPlan,
+    pub days: Vec<PlannedDay>,
+}
+
+impl PlanRequest {
+    fn validate(&self) -> Result<()> {
+        if parse_date(&self.start_date).is_none() {
+            return Err(Error::Validation(format!(
+                "Invalid date '{}', expected YYYY-MM-DD",
+                self.start_date
+            )));
+        }
+        if !(1..=366).contains(&self.days) {
+            return Err(Error::Validation(format!(
+                "Days must be between 1 and 366, got {}",
+                self.days
+            )));
+        }
+        if self.meals.is_empty() {
+            return Err(Error::Validation(
+                "Plan at least one meal a day".to_string(),
+            ));
+        }
+        for (i, meal) in self.meals.iter().enumerate() {
+            if self.meals[..i].contains(meal) {
+                return Err(Error::Validation(format!("Meal {} is listed twice", meal)));
+            }
+        }
+        if self.targets.calories_kcal <= 0.0 {
+            return Err(Error::Validation(
+                "The calorie target must be greater than 0".to_string(),
+            ));
+        }
+        if self.variety_days == 0 {
+            return Err(Error::Validation(
+                "Variety days must be at least 1".to_string(),
+            ));
+        }
+        if self.max_servings < 0.5 {
+            return Err(Error::Validation(format!(
+                "Max servings must be at least 0.5, got {}",
+                self.max_servings
+            )));
+        }
+
+        Ok(())
+    }
+
+    // A recipe with a tag named after a meal ("breakfast") is only planned
+    // for that meal, recipes without one fit any meal
+    fn allows(&self, recipe: &PlanRecipe, meal: MealSlot) -> bool {
+        let has_tag = |name: &str| recipe.tags.iter().any(|tag| tag.eq_ignore_ascii_case(name));
+        let meal_tags: Vec<MealSlot> = [
+            MealSlot::Breakfast,
+            MealSlot::Lunch,
+            MealSlot::Dinner,
+            MealSlot::Snack,
+        ]
+        .into_iter()
+        .filter(|slot| has_tag(&slot.to_string()))
+        .collect();
+
+        self.required_tags.iter().all(|tag| has_tag(tag))
+            && !self.excluded_tags.iter().any(|tag| has_tag(tag))
+            && (meal_tags.is_empty() || meal_tags.contains(&meal))
+    }
+
+    fn servings_options(&self) -> Vec<f32> {
+        let steps = (self.max_servings / 0.5).floor() as u32;
+        (1..=steps).map(|step| step as f32 * 0.5).collect()
+    }
+}
+
+// Pick a recipe and servings for every meal of every day. Each day starts
+// from a greedy pick per meal, then one meal at a time is swapped for the
+// best alternative until nothing improves the day's score. Recipes are tried
+// in the given order and only strictly better picks win, so the same input
+// always gives the same plan
+pub fn generate(recipes: &[PlanRecipe], request: &PlanRequest) -> Result<Vec<PlannedDay>> {
+    request.validate()?;
+    let servings_options = request.servings_options();
+    // day index each recipe was last planned on
+    let mut last_used: HashMap<usize, u32> = HashMap::new();
+    let mut days = Vec::new();
+
+    for day in 0..request.days {
+        let date = add_days(&request.start_date, day).unwrap_or_default();
+        let mut candidates = Vec::new();
+        for meal in &request.meals {
+            let available: Vec<usize> = (0..recipes.len())
+                .filter(|i| request.allows(&recipes[*i], *meal))
+                .filter(|i| {
+                    last_used
+                        .get(i)
+                        .is_none_or(|used| day - used >= request.variety_days)
+                })
+                .collect();
+            if available.is_empty() {
+                return Err(Error::Validation(format!(
+                    "Not enough recipes to plan {} on {}, add recipes or relax the tags or variety \
+                     (recipes with incomplete nutrition are skipped)",
+                    meal, date
+                )));
+            }
+            candidates.push(available);
+        }
+
+        let picks = plan_day(recipes, &candidates, &servings_options, &request.targets)?;
+        let mut totals = NutritionFacts::zero();
+        let mut entries = Vec::new();
+        for (meal, (recipe, servings)) in request.meals.iter().zip(&picks) {
+            last_used.insert(*recipe, day);
+            totals += &recipes[*recipe].per_serving.scaled(*servings);
+            entries.push(MealPlanEntry {
+                id: None,
+                date: date.clone(),
+                meal: *meal,
+                recipe_id: recipes[*recipe].id,
+                recipe_name: Some(recipes[*recipe].name.clone()),
+                servings: *servings,
+            });
+        }
+        days.push(PlannedDay {
+            date,
+            entries,
+            totals,
+        });
+    }
+
+    Ok(days)
+}
+
+// (recipe index, servings) for each meal of one day
+fn plan_day(
+    recipes: &[PlanRecipe],
+    candidates: &[Vec<usize>],
+    servings_options: &[f32],
+    targets: &NutritionTargets,
+) -> Result<Vec<(usize, f32)>> {
+    let meals = candidates.len();
+    let mut picks: Vec<(usize, f32)> = Vec::new();
+
+    // greedy, each meal aims for its share of the day so far
+    for (meal, meal_candidates) in candidates.iter().enumerate() {
+        let share = (meal + 1) as f32 / meals as f32;
+        let best = best_pick(
+            recipes,
+            &picks,
+            None,
+            meal_candidates,
+            servings_options,
+            targets,
+            share,
+        )
+        .ok_or_else(|| Error::Validation("Not enough different recipes for one day".to_string()))?;
+        picks.push(best.0);
+    }
+
+    let mut score = day_score(recipes, &picks, targets, 1.0);
+    for _ in 0..20 {
+        let mut improved = false;
+        for (meal, meal_candidates) in candidates.iter().enumerate() {
+            let best = best_pick(
+                recipes,
+                &picks,
+                Some(meal),
+                meal_candidates,
+                servings_options,
+                targets,
+                1.0,
+            );
+            if let Some((pick, best_score)) = best {
+                if best_score < score - 1e-6 {
+                    picks[meal] = pick;
+                    score = best_score;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    Ok(picks)
+}
+
+// Best (recipe, servings) for the meal at `replace`, or for a new meal
+// after `picks` when `replace` is None, and the score of the day with it
+fn best_pick(
+    recipes: &[PlanRecipe],
+    picks: &[(usize, f32)],
+    replace: Option<usize>,
+    candidates: &[usize],
+    servings_options: &[f32],
+    targets: &NutritionTargets,
+    share: f32,
+) -> Option<((usize, f32), f32)> {
+    let mut best: Option<((usize, f32), f32)> = None;
+    let mut trial: Vec<(usize, f32)> = picks.to_vec();
+    let index = match replace {
+        Some(index) => index,
+        None => {
+            trial.push((0, 0.0));
+            trial.len() - 1
+        }
+    };
+
+    for &recipe in candidates {
+        // the same recipe twice in a day is never varied enough
+        let taken = trial
+            .iter()
+            .enumerate()
+            .any(|(i, (other, _))| i != index && *other == recipe);
+        if taken {
+            continue;
+        }
+        for &servings in servings_options {
+            trial[index] = (recipe, servings);
+            let score = day_score(recipes, &trial, targets, share);
+            if best.is_none_or(|(_, best_score)| score < best_score) {
+                best = Some(((recipe, servings), score));
+            }
+        }
+    }
+
+    best
+}
+
+fn day_score(
+    recipes: &[PlanRecipe],
+    picks: &[(usize, f32)],
+    targets: &NutritionTargets,
+    share: f32,
+) -> f32 {
+    let mut totals = NutritionFacts::zero();
+    for (recipe, servings) in picks {
+        totals += &recipes[*recipe].per_serving.scaled(*servings);
+    }
+    score(&totals, targets, share)
+}
+
+// Sum of squared relative misses, calories count double and going over the
+// sodium cap four times. The targeted nutrients are known for every recipe,
+// see `read_recipes`
+fn score(totals: &NutritionFacts, targets: &NutritionTargets, share: f32) -> f32 {
+    let miss = |actual: Option<f32>, target: f32| {
+        let actual = actual.unwrap_or(0.0);
+        let target = target * share;
+        if target > 0.0 {
+            (actual - target) / target
+        } else {
+            0.0
+        }
+    };
+    let fiber = miss(totals.fiber_g, targets.fiber_g).min(0.0);
+    let sodium = miss(totals.sodium_mg, targets.sodium_mg).max(0.0);
+
+    2.0 * miss(totals.calories_kcal, targets.calories_kcal).powi(2)
+        + miss(totals.protein_g, targets.protein_g).powi(2)
+        + miss(totals.carbohydrates_g, targets.carbohydrates_g).powi(2)
+        + miss(totals.fat_g, targets.fat_g).powi(2)
+        + fiber.powi(2)
+        + 4.0 * sodium.powi(2)
+}
+
+// Every recipe with complete nutrition, by id. Recipes with ingredients that
+// can't be converted or that lack a targeted nutrient would make the totals
+// look lower than they are
+pub async fn read_recipes(pool: &Pool<Sqlite>) -> Result<Vec<PlanRecipe>> {
+    let mut recipes = Vec::new();
+    for simple in recipe::read(pool).await? {
+        let recipe_id = match simple.id {
+            Some(recipe_id) => recipe_id,
+            None => continue,
+        };
+        let recipe = recipe::read_one(pool, recipe_id).await?;
+        let converters = unit_conversion::read_recipe_converters(pool, &recipe).await?;
+        let nutrition = nutrition::calculate(&recipe, &converters);
+        let facts = &nutrition.per_serving;
+        let targeted = [
+            facts.calories_kcal,
+            facts.protein_g,
+            facts.carbohydrates_g,
+            facts.fat_g,
+            facts.fiber_g,
+            facts.sodium_mg,
+        ];
+        if !nutrition.errors.is_empty()
+            || targeted.iter().any(Option::is_none)
+            || facts.calories_kcal <= Some(0.0)
+        {
+            continue;
+        }
+        recipes.push(PlanRecipe {
+            id: recipe_id,
+            name: recipe.name,
+            tags: recipe.tags.into_iter().map(|tag| tag.name).collect(),
+            per_serving: nutrition.per_serving,
+        });
+    }
+    recipes.sort_by_key(|recipe| recipe.id);
+
+    Ok(recipes)
+}
+
+// Generate a plan from the recipe library and save it as a meal plan
+pub async fn create(pool: &Pool<Sqlite>, request: &PlanRequest) -> Result<GeneratedPlan> {
+    let recipes = read_recipes(pool).await?;
+    let days = generate(&recipes, request)?;
+    let entries: Vec<MealPlanEntry> = days
+        .iter()
+        .flat_map(|day| day.entries.iter().cloned())
+        .collect();
+    let meal_plan_id = meal_plan::create_with_entries(pool, &request.name, &entries).await?;
+    let meal_plan = meal_plan::read_one(pool, meal_plan_id).await?;
+
+    Ok(GeneratedPlan { meal_plan, days })
+}
+
+// `date` plus `days` days, both YYYY-MM-DD. Converts to days since
+// 1970-01-01 and back, see http://howardhinnant.github.io/date_algorithms.html
+pub fn add_days(date: &str, days: u32) -> Option<String> {
+    let (year, month, day) = parse_date(date)?;
+    // years start in March so the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days_since_epoch = era * 146097 + day_of_era - 719468 + days as i64;
+
+    let shifted = days_since_epoch + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::recipe::{RecipeIngredientInput, RecipeInput};
+    use crate::crud::tag::Tag;
+    use crate::crud::{self, get_connection_pool, ingredient};
+    use crate::planner::*;
+    use crate::unit::Unit;
+    use tempfile::NamedTempFile;
+
+    fn plan_recipe(id: i64, name: &str, tags: &[&str], facts: [f32; 6]) -> PlanRecipe {
+        let [calories_kcal, protein_g, carbohydrates_g, fat_g, fiber_g, sodium_mg] = facts;
+        PlanRecipe {
+            id,
+            name: name.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            per_serving: NutritionFacts {
+                calories_kcal: Some(calories_kcal),
+                protein_g: Some(protein_g),
+                carbohydrates_g: Some(carbohydrates_g),
+                fat_g: Some(fat_g),
+                fiber_g: Some(fiber_g),
+                sodium_mg: Some(sodium_mg),
+                ..NutritionFacts::zero()
+            },
+        }
+    }
+
+    // [calories, protein, carbs, fat, fiber, sodium] per serving
+    fn library() -> Vec<PlanRecipe> {
+        vec![
+            plan_recipe(
+                1,
+                "oatmeal",
+                &["breakfast", "vegetarian"],
+                [300.0, 10.0, 54.0, 5.0, 8.0, 5.0],
+            ),
+            plan_recipe(
+                2,
+                "eggs on toast",
+                &["breakfast", "vegetarian"],
+                [350.0, 20.0, 30.0, 16.0, 3.0, 500.0],
+            ),
+            plan_recipe(
+                3,
+                "yogurt bowl",
+                &["breakfast", "vegetarian", "gluten-free"],
+                [250.0, 15.0, 35.0, 6.0, 4.0, 90.0],
+            ),
+            plan_recipe(
+                4,
+                "lentil soup",
+                &["vegetarian", "gluten-free"],
+                [400.0, 24.0, 60.0, 6.0, 16.0, 700.0],
+            ),
+            plan_recipe(
+                5,
+                "chicken salad",
+                &["gluten-free"],
+                [450.0, 40.0, 15.0, 25.0, 6.0, 600.0],
+            ),
+            plan_recipe(
+                6,
+                "bean burrito",
+                &["vegetarian"],
+                [550.0, 22.0, 75.0, 18.0, 14.0, 900.0],
+            ),
+            plan_recipe(
+                7,
+                "salmon and rice",
+                &["gluten-free"],
+                [600.0, 38.0, 55.0, 22.0, 2.0, 400.0],
+            ),
+            plan_recipe(
+                8,
+                "veggie stir fry",
+                &["vegetarian", "gluten-free"],
+                [420.0, 18.0, 50.0, 16.0, 9.0, 800.0],
+            ),
+            plan_recipe(
+                9,
+                "beef chili",
+                &["gluten-free"],
+                [520.0, 35.0, 40.0, 22.0, 12.0, 950.0],
+            ),
+            plan_recipe(
+                10,
+                "pasta primavera",
+                &["vegetarian"],
+                [580.0, 20.0, 85.0, 16.0, 8.0, 500.0],
+            ),
+        ]
+    }
+
+    fn request() -> PlanRequest {
+        serde_json::from_str(
+            r#"{
+                "name": "week",
+                "start_date": "2023-03-06",
+                "targets": {
+                    "calories_kcal": 2000, "protein_g": 100, "carbohydrates_g": 250,
+                    "fat_g": 65, "fiber_g": 30, "sodium_mg": 2300
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_generate() {
+        let recipes = library();
+        let request = request();
+        let days = generate(&recipes, &request).unwrap();
+        assert_eq!(days.len(), 7);
+        assert_eq!(days[0].date, "2023-03-06");
+        assert_eq!(days[6].date, "2023-03-12");
+
+        for (day, planned) in days.iter().enumerate() {
+            assert_eq!(planned.entries.len(), 3);
+            let calories_kcal = planned.totals.calories_kcal.unwrap();
+            assert!(
+                (calories_kcal - 2000.0).abs() <= 200.0,
+                "{} has {} kcal",
+                planned.date,
+                calories_kcal
+            );
+            assert!(planned.totals.sodium_mg.unwrap() <= 2300.0 * 1.1);
+            // breakfast recipes only at breakfast
+            for entry in &planned.entries {
+                let recipe = recipes.iter().find(|r| r.id == entry.recipe_id).unwrap();
+                if recipe.tags.contains(&"breakfast".to_string()) {
+                    assert_eq!(entry.meal, MealSlot::Breakfast);
+                }
+            }
+            // no recipe twice within 3 days
+            for earlier in &days[day.saturating_sub(2)..day] {
+                for entry in &planned.entries {
+                    assert!(earlier
+                        .entries
+                        .iter()
+                        .all(|e| e.recipe_id != entry.recipe_id));
+                }
+            }
+        }
+
+        // deterministic
+        let again = generate(&recipes, &request).unwrap();
+        for (a, b) in days.iter().zip(&again) {
+            for (a, b) in a.entries.iter().zip(&b.entries) {
+                assert_eq!((a.recipe_id, a.servings), (b.recipe_id, b.servings));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tags_and_limits() {
+        let recipes = library();
+        let mut request = request();
+        request.required_tags = vec!["vegetarian".to_string()];
+        request.excluded_tags = vec!["gluten-free".to_string()];
+        request.meals = vec![MealSlot::Lunch];
+        request.variety_days = 2;
+        let days = generate(&recipes, &request).unwrap();
+        for entry in days.iter().flat_map(|day| &day.entries) {
+            assert!([6, 10].contains(&entry.recipe_id));
+        }
+
+        // two recipes can't go three days without repeating
+        request.variety_days = 3;
+        let err = generate(&recipes, &request).unwrap_err();
+        assert!(err.to_string().contains("lunch on 2023-03-08"));
+
+        request.variety_days = 1;
+        request.start_date = "2023-02-30".to_string();
+        assert!(generate(&recipes, &request).is_err());
+    }
+
+    #[test]
+    fn test_add_days() {
+        assert_eq!(add_days("2023-03-06", 0).unwrap(), "2023-03-06");
+        assert_eq!(add_days("2023-02-27", 2).unwrap(), "2023-03-01");
+        assert_eq!(add_days("2024-02-27", 2).unwrap(), "2024-02-29");
+        assert_eq!(add_days("2023-12-30", 3).unwrap(), "2024-01-02");
+        assert_eq!(add_days("2000-01-01", 366).unwrap(), "2001-01-01");
+        assert!(add_days("2023-13-01", 1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_from_library() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        // (name, tag, ingredient, amount in grams)
+        let dishes = [
+            ("toast", "vegan", "sourdough bread", 150.0),
+            ("roast chicken", "poultry", "chicken breast", 300.0),
+            ("steak", "beef", "filet mignon", 250.0),
+            ("grilled salmon", "seafood", "salmon", 300.0),
+        ];
+        for (name, tag, ingredient_name, grams) in dishes {
+            let ingredient = ingredient::read_by_name(&pool, ingredient_name)
+                .await
+                .unwrap()
+                .unwrap();
+            let input = RecipeInput {
+                name: name.to_string(),
+                tags: vec![Tag {
+                    id: None,
+                    name: tag.to_string(),
+                }],
+                ingredients: vec![RecipeIngredientInput {
+                    ingredient_id: ingredient.id.unwrap(),
+                    amount: grams,
+                    unit: Unit::Gram,
+                }],
+                notes: vec![],
+                instructions: vec![],
+                servings: 1.0,
+                recipe_yield: None,
+            };
+            recipe::create_full(&pool, &input).await.unwrap();
+        }
+
+        let mut request = request();
+        request.days = 2;
+        request.meals = vec![MealSlot::Lunch, MealSlot::Dinner];
+        request.excluded_tags = vec!["beef".to_string()];
+        request.variety_days = 1;
+        let generated = create(&pool, &request).await.unwrap();
+
+        assert_eq!(generated.meal_plan.name, "week");
+        assert_eq!(generated.meal_plan.entries.len(), 4);
+        assert!(generated
+            .meal_plan
+            .entries
+            .iter()
+            .all(|entry| entry.recipe_name.as_deref() != Some("steak")));
+        let planned: Vec<i64> = generated
+            .days
+            .iter()
+            .flat_map(|day| day.entries.iter().map(|entry| entry.recipe_id))
+            .collect();
+        let saved: Vec<i64> = generated
+            .meal_plan
+            .entries
+            .iter()
+            .map(|entry| entry.recipe_id)
+            .collect();
+        assert_eq!(planned, saved);
+    }
+}
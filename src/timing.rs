@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crud::recipe::RecipeStep;
+
+// Dinners on weeknights taking longer than this get a warning in meal plans
+pub const WEEKNIGHT_DINNER_MINUTES: f32 = 45.0;
+
+// Words that mean the cook can walk away while the clock runs
+const PASSIVE_WORDS: &[&str] = &[
+    "bake",
+    "bakes",
+    "baking",
+    "roast",
+    "roasting",
+    "simmer",
+    "simmering",
+    "braise",
+    "braising",
+    "rest",
+    "resting",
+    "marinate",
+    "marinating",
+    "chill",
+    "chilling",
+    "refrigerate",
+    "freeze",
+    "soak",
+    "soaking",
+    "rise",
+    "proof",
+    "prove",
+    "cool",
+    "cooling",
+    "steep",
+    "ferment",
+    "let",
+    "leave",
+    "stand",
+    "overnight",
+];
+
+// Words that mean hands on work, they override a passive clause before them
+const ACTIVE_WORDS: &[&str] = &[
+    "stir", "stirring", "whisk", "whisking", "knead", "kneading", "fry", "frying", "saute",
+    "sauté", "sear", "searing", "chop", "mix", "mixing", "beat", "fold", "brown", "grill", "cook",
+    "cooking", "boil", "blend", "toast",
+];
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct RecipeTime {
+    pub total_minutes: f32,
+    pub active_minutes: f32,
+    pub passive_minutes: f32,
+}
+
+impl RecipeTime {
+    // Steps are assumed to run one after another
+    pub fn from_steps(steps: &[RecipeStep]) -> Self {
+        let mut time = RecipeTime::default();
+        for step in steps {
+            let (active, passive) = step_minutes(step);
+            time.active_minutes += active;
+            time.passive_minutes += passive;
+        }
+        time.total_minutes = time.active_minutes + time.passive_minutes;
+        time
+    }
+}
+
+// Active and passive minutes of a step. Durations set on the step win over
+// the ones parsed from its text
+pub fn step_minutes(step: &RecipeStep) -> (f32, f32) {
+    if step.active_minutes.is_some() || step.passive_minutes.is_some() {
+        return (
+            step.active_minutes.unwrap_or(0.0),
+            step.passive_minutes.unwrap_or(0.0),
+        );
+    }
+    parse_minutes(&step.text)
+}
+
+// Find durations like "20 minutes", "1 1/2 hours", "10-15 mins" or
+// "an hour" in free text and split them into active and passive minutes.
+// Each clause is passive if it has a word like "simmer" or "bake", active if
+// it has one like "stir", and like the clause before it otherwise. Ranges
+// count as their upper bound and "every 5 minutes" is not a duration
+pub fn parse_minutes(text: &str) -> (f32, f32) {
+    let mut active = 0.0;
+    let mut passive = 0.0;
+    let mut is_passive = false;
+    for clause in clauses(text) {
+        let words = tokenize(clause);
+        if words.iter().any(|w| ACTIVE_WORDS.contains(&w.as_str())) {
+            is_passive = false;
+        } else if words.iter().any(|w| PASSIVE_WORDS.contains(&w.as_str())) {
+            is_passive = true;
+        }
+        let minutes = clause_minutes(&words);
+        if is_passive {
+            passive += minutes;
+        } else {
+            active += minutes;
+        }
+    }
+    (active, passive)
+}
+
+fn clauses(text: &str) -> Vec<&str> {
+    let mut clauses = Vec::new();
+    let mut start = 0;
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    for (i, &(offset, c)) in chars.iter().enumerate() {
+        // a '.' between digits is a decimal point
+        let decimal = c == '.'
+            && i > 0
+            && chars[i - 1].1.is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|(_, c)| c.is_ascii_digit());
+        if matches!(c, ',' | ';' | '.' | ':' | '(' | ')') && !decimal {
+            clauses.push(&text[start..offset]);
+            start = offset + c.len_utf8();
+        }
+    }
+    clauses.push(&text[start..]);
+    clauses
+        .into_iter()
+        .flat_map(|clause| clause.split(" then "))
+        .collect()
+}
+
+// Lowercase words, with '-' as a word of its own so "10-15" and "20-minute"
+// split up
+fn tokenize(clause: &str) -> Vec<String> {
+    clause
+        .to_lowercase()
+        .replace('-', " - ")
+        .split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| c == '"' || c == '\'')
+                .to_string()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn clause_minutes(words: &[String]) -> f32 {
+    let mut minutes = 0.0;
+    let mut i = 0;
+    while i < words.len() {
+        let every = i > 0 && words[i - 1] == "every";
+        match duration_at(words, i) {
+            Some((value, next)) => {
+                if !every {
+                    minutes += value;
+                }
+                i = next;
+            }
+            None => i += 1,
+        }
+    }
+    minutes
+}
+
+// A duration starting at `words[i]`, in minutes, and the index after it
+fn duration_at(words: &[String], i: usize) -> Option<(f32, usize)> {
+    if words[i] == "overnight" {
+        return Some((8.0 * 60.0, i + 1));
+    }
+    let (mut value, mut next) = number_at(words, i)?;
+    // "10-15" or "10 to 15"
+    if matches!(words.get(next).map(String::as_str), Some("-" | "to")) {
+        if let Some((upper, after)) = number_at(words, next + 1) {
+            value = upper;
+            next = after;
+        }
+    }
+    // "20-minute", "half an hour"
+    if matches!(words.get(next).map(String::as_str), Some("-" | "a" | "an")) {
+        next += 1;
+    }
+    let unit = unit_minutes(words.get(next)?)?;
+    Some((value * unit, next + 1))
+}
+
+// "2", "1.5", "1/2", "1 1/2", "½", "a", "an" or "half", and the index after it
+fn number_at(words: &[String], i: usize) -> Option<(f32, usize)> {
+    let value = match words.get(i)?.as_str() {
+        "a" | "an" | "one" => 1.0,
+        "half" => 0.5,
+        "two" => 2.0,
+        "three" => 3.0,
+        "few" => 3.0,
+        word => parse_number(word)?,
+    };
+    // mixed number, "1 1/2"
+    if let Some(fraction) = words.get(i + 1).filter(|word| word.contains('/')) {
+        if let Some(fraction) = parse_number(fraction) {
+            return Some((value + fraction, i + 2));
+        }
+    }
+    Some((value, i + 1))
+}
+
+fn parse_number(word: &str) -> Option<f32> {
+    let fraction = match word {
+        "¼" => Some(0.25),
+        "½" => Some(0.5),
+        "¾" => Some(0.75),
+        "⅓" => Some(1.0 / 3.0),
+        "⅔" => Some(2.0 / 3.0),
+        _ => None,
+    };
+    if fraction.is_some() {
+        return fraction;
+    }
+    match word.split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator: f32 = denominator.parse().ok()?;
+            (denominator != 0.0).then_some(numerator.parse::<f32>().ok()? / denominator)
+        }
+        None => word.parse().ok().filter(|value: &f32| value.is_finite()),
+    }
+}
+
+fn unit_minutes(word: &str) -> Option<f32> {
+    match word {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1.0 / 60.0),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(1.0),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(60.0),
+        "day" | "days" => Some(24.0 * 60.0),
+        _ => None,
+    }
+}
+
+// (year, month, day) for a valid YYYY-MM-DD date
+pub fn parse_date(date: &str) -> Option<(i64, i64, i64)> {
+    let mut parts = date.split('-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    if ![year, month, day]
+        .iter()
+        .all(|part| part.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return None;
+    }
+    let (year, month, day): (i64, i64, i64) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    (1..=month_days)
+        .contains(&day)
+        .then_some((year, month, day))
+}
+
+// Day of the week for a YYYY-MM-DD date, 0 is Sunday
+pub fn weekday(date: &str) -> Option<u32> {
+    let (year, month, day) = parse_date(date)?;
+    // Sakamoto's method, January and February of year 0 fall in year -1
+    const OFFSETS: [i64; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let year = if month < 3 { year - 1 } else { year };
+    let days = year + year.div_euclid(4) - year.div_euclid(100)
+        + year.div_euclid(400)
+        + OFFSETS[month as usize - 1]
+        + day;
+    Some(days.rem_euclid(7) as u32)
+}
+
+// Monday to Thursday, Friday to Sunday nights count as the weekend
+pub fn is_weeknight(date: &str) -> bool {
+    matches!(weekday(date), Some(1..=4))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timing::*;
+
+    #[test]
+    fn test_parse_minutes() {
+        // (text, active, passive)
+        let cases = [
+            ("Chop the onions", 0.0, 0.0),
+            ("Simmer for 20 minutes", 0.0, 20.0),
+            ("Fry the onions for 5-7 mins", 7.0, 0.0),
+            ("Bake at 180C for 1 1/2 hours", 0.0, 90.0),
+            ("Knead for 10 minutes, then let rise for 1 hour", 10.0, 60.0),
+            ("Simmer, covered, for 2.5 hours", 0.0, 150.0),
+            ("Cook for 10 minutes, stirring every 2 minutes", 10.0, 0.0),
+            ("Marinate overnight", 0.0, 480.0),
+            ("Let it rest for half an hour", 0.0, 30.0),
+            ("Serve after a 5-minute rest", 0.0, 5.0),
+            ("Whisk for 30 seconds", 0.5, 0.0),
+            ("Add 2 cups of stock", 0.0, 0.0),
+            ("Roast for ½ hour", 0.0, 30.0),
+        ];
+        for (text, active, passive) in cases {
+            assert_eq!(parse_minutes(text), (active, passive), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_recipe_time() {
+        let steps = vec![
+            RecipeStep::from("Brown the beef for 10 minutes"),
+            RecipeStep::from("Simmer for 1 hour"),
+            // explicit durations win over the text
+            RecipeStep {
+                active_minutes: Some(5.0),
+                ..RecipeStep::from("Bake for 20 minutes")
+            },
+        ];
+        assert_eq!(
+            RecipeTime::from_steps(&steps),
+            RecipeTime {
+                total_minutes: 75.0,
+                active_minutes: 15.0,
+                passive_minutes: 60.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_weekday() {
+        assert_eq!(weekday("2023-03-06"), Some(1));
+        assert_eq!(weekday("2024-02-29"), Some(4));
+        assert_eq!(weekday("2000-01-01"), Some(6));
+        assert_eq!(weekday("2023-13-01"), None);
+        assert_eq!(weekday("2023-02-30"), None);
+        // 0000-01-01 was a Saturday in the proleptic Gregorian calendar
+        assert_eq!(weekday("0000-01-01"), Some(6));
+        assert_eq!(weekday("0000-01-05"), Some(3));
+        assert_eq!(weekday("0000-03-01"), Some(3));
+        assert!(is_weeknight("2023-03-09"));
+        assert!(!is_weeknight("2023-03-10"));
+    }
+}
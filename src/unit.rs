@@ -0,0 +1,473 @@
+use serde::{Deserialize, Serialize};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Sqlite, Type};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnitKind {
+    Volume,
+    Mass,
+    Count,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Unit {
+    // volume
+    Milliliter,
+    Teaspoon,
+    Tablespoon,
+    FluidOunce,
+    Cup,
+    Pint,
+    Quart,
+    Liter,
+    // mass
+    Gram,
+    Kilogram,
+    Ounce,
+    Pound,
+    // count, e.g. 1 banana, 1 leaf, etc.
+    Piece,
+    Leaf,
+    Clove,
+    Tortilla,
+    Slice,
+    Can,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 18] = [
+        Unit::Milliliter,
+        Unit::Teaspoon,
+        Unit::Tablespoon,
+        Unit::FluidOunce,
+        Unit::Cup,
+        Unit::Pint,
+        Unit::Quart,
+        Unit::Liter,
+        Unit::Gram,
+        Unit::Kilogram,
+        Unit::Ounce,
+        Unit::Pound,
+        Unit::Piece,
+        Unit::Leaf,
+        Unit::Clove,
+        Unit::Tortilla,
+        Unit::Slice,
+        Unit::Can,
+    ];
+
+    // Canonical label, this is what gets stored in the db
+    pub fn label(&self) -> &'static str {
+        match self {
+            Unit::Milliliter => "ml",
+            Unit::Teaspoon => "tsp",
+            Unit::Tablespoon => "Tbsp",
+            Unit::FluidOunce => "fl oz",
+            Unit::Cup => "cup",
+            Unit::Pint => "pt",
+            Unit::Quart => "qt",
+            Unit::Liter => "l",
+            Unit::Gram => "g",
+            Unit::Kilogram => "kg",
+            Unit::Ounce => "oz",
+            Unit::Pound => "lb",
+            Unit::Piece => "piece",
+            Unit::Leaf => "leaf",
+            Unit::Clove => "clove",
+            Unit::Tortilla => "tortilla",
+            Unit::Slice => "slice",
+            Unit::Can => "can",
+        }
+    }
+
+    pub fn kind(&self) -> UnitKind {
+        match self {
+            Unit::Milliliter
+            | Unit::Teaspoon
+            | Unit::Tablespoon
+            | Unit::FluidOunce
+            | Unit::Cup
+            | Unit::Pint
+            | Unit::Quart
+            | Unit::Liter => UnitKind::Volume,
+            Unit::Gram | Unit::Kilogram | Unit::Ounce | Unit::Pound => UnitKind::Mass,
+            Unit::Piece | Unit::Leaf | Unit::Clove | Unit::Tortilla | Unit::Slice | Unit::Can => {
+                UnitKind::Count
+            }
+        }
+    }
+
+    // Amount of this unit in its base unit (ml for volume, g for mass, 1 for counts)
+    pub fn base_amount(&self) -> f32 {
+        match self {
+            Unit::Milliliter => 1.0,
+            Unit::Teaspoon => 4.93,
+            Unit::Tablespoon => 14.79,
+            Unit::FluidOunce => 29.57,
+            Unit::Cup => 236.59,
+            Unit::Pint => 473.18,
+            Unit::Quart => 946.35,
+            Unit::Liter => 1000.0,
+            Unit::Gram => 1.0,
+            Unit::Kilogram => 1000.0,
+            Unit::Ounce => 28.35,
+            Unit::Pound => 453.59,
+            _ => 1.0,
+        }
+    }
+
+    // Convert an amount of this unit into another unit of the same kind.
+    // Count units only convert to themselves (a clove is not a tortilla).
+    pub fn convert(&self, amount: f32, to: Unit) -> Option<f32> {
+        if *self == to {
+            return Some(amount);
+        }
+        match (self.kind(), to.kind()) {
+            (UnitKind::Volume, UnitKind::Volume) | (UnitKind::Mass, UnitKind::Mass) => {
+                Some(amount * self.base_amount() / to.base_amount())
+            }
+            _ => None,
+        }
+    }
+
+    // Same amount in the unit a cook would use, rounded to something that can
+    // be measured. US volumes move between tsp, Tbsp and cup (6 tsp is 2 Tbsp,
+    // 16 Tbsp is 1 cup), ounces become pounds, metric amounts switch to l or
+    // kg at 1000 and counts round to halves or quarters
+    pub fn friendly(&self, amount: f32) -> (f32, Unit) {
+        if amount <= 0.0 || !amount.is_finite() {
+            return (amount, *self);
+        }
+        match self {
+            Unit::Teaspoon
+            | Unit::Tablespoon
+            | Unit::FluidOunce
+            | Unit::Cup
+            | Unit::Pint
+            | Unit::Quart => {
+                let teaspoons = amount * self.base_amount() / Unit::Teaspoon.base_amount();
+                friendly_us_volume(teaspoons)
+            }
+            Unit::Milliliter | Unit::Liter | Unit::Gram | Unit::Kilogram => {
+                let (small, large) = match self.kind() {
+                    UnitKind::Volume => (Unit::Milliliter, Unit::Liter),
+                    _ => (Unit::Gram, Unit::Kilogram),
+                };
+                let base = amount * self.base_amount();
+                if base >= 1000.0 {
+                    (round_to(base / 1000.0, 0.05), large)
+                } else if base >= 100.0 {
+                    (round_to(base, 5.0), small)
+                } else if base >= 10.0 {
+                    (round_to(base, 1.0), small)
+                } else {
+                    (round_to(base, 0.5).max(0.5), small)
+                }
+            }
+            Unit::Ounce | Unit::Pound => {
+                let ounces = amount * self.base_amount() / Unit::Ounce.base_amount();
+                if ounces >= 16.0 {
+                    (round_to(ounces / 16.0, 0.25), Unit::Pound)
+                } else {
+                    (round_to(ounces, 0.25).max(0.25), Unit::Ounce)
+                }
+            }
+            _ => {
+                let amount = if amount < 1.0 {
+                    round_to(amount, 0.25).max(0.25)
+                } else if amount < 3.0 {
+                    round_to(amount, 0.5)
+                } else {
+                    amount.round()
+                };
+                (amount, *self)
+            }
+        }
+    }
+
+    // Metric amounts as decimals, everything else as fractions
+    pub fn format_amount(&self, amount: f32) -> String {
+        match self {
+            Unit::Milliliter | Unit::Liter | Unit::Gram | Unit::Kilogram => format_decimal(amount),
+            _ => format_fraction(amount),
+        }
+    }
+}
+
+// Largest of cup, Tbsp and tsp that measures the amount within 5%
+fn friendly_us_volume(teaspoons: f32) -> (f32, Unit) {
+    let candidates = [
+        (Unit::Cup, 0.25, 48.0),
+        (Unit::Tablespoon, 1.0, 3.0),
+        (Unit::Teaspoon, 0.0, 1.0),
+    ];
+    for (unit, minimum, size) in candidates {
+        let amount = teaspoons / size;
+        // the conversion factors are rounded, 2 fl oz is 0.2499 cup
+        if amount < minimum * 0.99 {
+            continue;
+        }
+        let rounded = round_kitchen_fraction(amount, unit);
+        if rounded > 0.0 && (rounded - amount).abs() <= amount * 0.05 {
+            return (rounded, unit);
+        }
+    }
+    // a pinch or an odd amount, as close as an eighth of a tsp gets
+    if teaspoons >= 12.0 {
+        (
+            round_kitchen_fraction(teaspoons / 48.0, Unit::Cup),
+            Unit::Cup,
+        )
+    } else {
+        (round_to(teaspoons, 0.125).max(0.125), Unit::Teaspoon)
+    }
+}
+
+// What measuring spoons and cups come in: quarter and third cups, half
+// tablespoons and eighth teaspoons
+fn round_kitchen_fraction(amount: f32, unit: Unit) -> f32 {
+    match unit {
+        Unit::Cup => {
+            let quarters = round_to(amount, 0.25);
+            let thirds = (amount * 3.0).round() / 3.0;
+            if (thirds - amount).abs() < (quarters - amount).abs() {
+                thirds
+            } else {
+                quarters
+            }
+        }
+        Unit::Tablespoon => round_to(amount, 0.5),
+        _ => round_to(amount, 0.125),
+    }
+}
+
+fn round_to(amount: f32, step: f32) -> f32 {
+    (amount / step).round() * step
+}
+
+// At most two decimals, without trailing zeros
+pub fn format_decimal(amount: f32) -> String {
+    let amount = format!("{:.2}", amount);
+    amount
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+// "1 1/2", "1/3", "2" or "0.15" when there is no simple fraction
+pub fn format_fraction(amount: f32) -> String {
+    let whole = amount.trunc();
+    let rest = amount - whole;
+    let fraction = [
+        (1.0 / 8.0, "1/8"),
+        (1.0 / 4.0, "1/4"),
+        (1.0 / 3.0, "1/3"),
+        (3.0 / 8.0, "3/8"),
+        (1.0 / 2.0, "1/2"),
+        (5.0 / 8.0, "5/8"),
+        (2.0 / 3.0, "2/3"),
+        (3.0 / 4.0, "3/4"),
+        (7.0 / 8.0, "7/8"),
+    ]
+    .iter()
+    .find(|(value, _)| (rest - value).abs() < 0.01)
+    .map(|(_, label)| *label);
+
+    match fraction {
+        _ if rest < 0.01 => format!("{}", whole),
+        _ if rest > 0.99 => format!("{}", whole + 1.0),
+        Some(fraction) if whole == 0.0 => fraction.to_string(),
+        Some(fraction) => format!("{} {}", whole, fraction),
+        None => format_decimal(amount),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownUnitError(pub String);
+
+impl fmt::Display for UnknownUnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown unit: '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownUnitError {}
+
+impl FromStr for Unit {
+    type Err = UnknownUnitError;
+
+    // Return Unit based on name, accepts common aliases and plurals
+    fn from_str(label: &str) -> Result<Self, Self::Err> {
+        let trimmed = label.trim().trim_end_matches('.');
+        // "T" and "t" are the usual shorthand for tablespoon and teaspoon,
+        // so these have to be checked before lowercasing
+        match trimmed {
+            "T" | "Tb" | "Tbs" => return Ok(Unit::Tablespoon),
+            "t" => return Ok(Unit::Teaspoon),
+            _ => {}
+        }
+
+        let unit = match trimmed.to_lowercase().as_str() {
+            "ml" | "milliliter" | "milliliters" | "millilitre" | "millilitres" => Unit::Milliliter,
+            "tsp" | "tsps" | "teaspoon" | "teaspoons" => Unit::Teaspoon,
+            "tbsp" | "tbsps" | "tablespoon" | "tablespoons" => Unit::Tablespoon,
+            "fl oz" | "fl. oz" | "floz" | "fluid ounce" | "fluid ounces" => Unit::FluidOunce,
+            "c" | "cup" | "cups" => Unit::Cup,
+            "pt" | "pint" | "pints" => Unit::Pint,
+            "qt" | "quart" | "quarts" => Unit::Quart,
+            "l" | "liter" | "liters" | "litre" | "litres" => Unit::Liter,
+            "g" | "gram" | "grams" | "gr" => Unit::Gram,
+            "kg" | "kilogram" | "kilograms" => Unit::Kilogram,
+            "oz" | "ounce" | "ounces" => Unit::Ounce,
+            "lb" | "lbs" | "pound" | "pounds" => Unit::Pound,
+            "piece" | "pieces" | "pc" | "pcs" | "each" | "whole" => Unit::Piece,
+            "leaf" | "leaves" => Unit::Leaf,
+            "clove" | "cloves" => Unit::Clove,
+            "tortilla" | "tortillas" => Unit::Tortilla,
+            "slice" | "slices" => Unit::Slice,
+            "can" | "cans" => Unit::Can,
+            _ => return Err(UnknownUnitError(label.to_string())),
+        };
+
+        Ok(unit)
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+impl TryFrom<String> for Unit {
+    type Error = UnknownUnitError;
+
+    fn try_from(label: String) -> Result<Self, Self::Error> {
+        label.parse()
+    }
+}
+
+impl From<Unit> for String {
+    fn from(unit: Unit) -> Self {
+        unit.label().to_string()
+    }
+}
+
+// Units are stored as their canonical label in TEXT columns
+impl Type<Sqlite> for Unit {
+    fn type_info() -> SqliteTypeInfo {
+        <&str as Type<Sqlite>>::type_info()
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for Unit {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        <&str as Encode<Sqlite>>::encode(self.label(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Unit {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let label = <&str as Decode<Sqlite>>::decode(value)?;
+        Ok(label.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::unit::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.01, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        assert_eq!("tsp".parse::<Unit>().unwrap(), Unit::Teaspoon);
+        assert_eq!("teaspoon".parse::<Unit>().unwrap(), Unit::Teaspoon);
+        assert_eq!("Teaspoons".parse::<Unit>().unwrap(), Unit::Teaspoon);
+        assert_eq!("Tbsp".parse::<Unit>().unwrap(), Unit::Tablespoon);
+        assert_eq!("tablespoon".parse::<Unit>().unwrap(), Unit::Tablespoon);
+        assert_eq!("T".parse::<Unit>().unwrap(), Unit::Tablespoon);
+        assert_eq!("t".parse::<Unit>().unwrap(), Unit::Teaspoon);
+        assert_eq!("fl. oz.".parse::<Unit>().unwrap(), Unit::FluidOunce);
+        assert_eq!(" cups ".parse::<Unit>().unwrap(), Unit::Cup);
+        assert_eq!("lbs".parse::<Unit>().unwrap(), Unit::Pound);
+        assert_eq!("cloves".parse::<Unit>().unwrap(), Unit::Clove);
+        assert!("handful".parse::<Unit>().is_err());
+    }
+
+    #[test]
+    fn test_label_round_trip() {
+        for unit in Unit::ALL {
+            assert_eq!(unit.label().parse::<Unit>().unwrap(), unit);
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        // same checks as the old python unit module
+        assert_close(Unit::Teaspoon.convert(1.0, Unit::Milliliter).unwrap(), 4.93);
+        assert_close(Unit::Teaspoon.convert(1.0, Unit::Tablespoon).unwrap(), 0.33);
+        assert_close(Unit::Teaspoon.convert(1.0, Unit::FluidOunce).unwrap(), 0.17);
+        assert_close(Unit::Teaspoon.convert(1.0, Unit::Cup).unwrap(), 0.02);
+
+        assert_close(Unit::Pound.convert(1.0, Unit::Ounce).unwrap(), 16.0);
+        assert_close(Unit::Kilogram.convert(2.0, Unit::Gram).unwrap(), 2000.0);
+        assert_eq!(Unit::Clove.convert(3.0, Unit::Clove), Some(3.0));
+
+        assert!(Unit::Cup.convert(1.0, Unit::Gram).is_none());
+        assert!(Unit::Clove.convert(1.0, Unit::Tortilla).is_none());
+    }
+
+    #[test]
+    fn test_friendly() {
+        let friendly = |amount: f32, unit: Unit| {
+            let (amount, unit) = unit.friendly(amount);
+            format!("{} {}", unit.format_amount(amount), unit)
+        };
+        assert_eq!(friendly(6.0, Unit::Teaspoon), "2 Tbsp");
+        assert_eq!(friendly(16.0, Unit::Tablespoon), "1 cup");
+        // 1/4 cup scaled from 4 to 7 servings
+        assert_eq!(friendly(0.4375, Unit::Cup), "7 Tbsp");
+        assert_eq!(friendly(1.75, Unit::Cup), "1 3/4 cup");
+        assert_eq!(friendly(0.66, Unit::Cup), "2/3 cup");
+        assert_eq!(friendly(0.3, Unit::Teaspoon), "1/4 tsp");
+        assert_eq!(friendly(4.0, Unit::Teaspoon), "4 tsp");
+        assert_eq!(friendly(2.0, Unit::FluidOunce), "1/4 cup");
+        assert_eq!(friendly(1.5, Unit::Pint), "3 cup");
+        assert_eq!(friendly(26.0, Unit::Ounce), "1 3/4 lb");
+        assert_eq!(friendly(0.3, Unit::Pound), "4 3/4 oz");
+        assert_eq!(friendly(1240.0, Unit::Gram), "1.25 kg");
+        assert_eq!(friendly(437.5, Unit::Milliliter), "440 ml");
+        assert_eq!(friendly(0.5, Unit::Liter), "500 ml");
+        assert_eq!(friendly(3.5, Unit::Clove), "4 clove");
+        assert_eq!(friendly(1.75, Unit::Tortilla), "2 tortilla");
+        assert_eq!(friendly(1.2, Unit::Tortilla), "1 tortilla");
+        assert_eq!(friendly(0.6, Unit::Piece), "1/2 piece");
+        assert_eq!(friendly(0.1, Unit::Can), "1/4 can");
+    }
+
+    #[test]
+    fn test_format_fraction() {
+        assert_eq!(format_fraction(2.0), "2");
+        assert_eq!(format_fraction(0.5), "1/2");
+        assert_eq!(format_fraction(1.0 / 3.0), "1/3");
+        assert_eq!(format_fraction(2.75), "2 3/4");
+        assert_eq!(format_fraction(1.999), "2");
+        assert_eq!(format_fraction(0.15), "0.15");
+    }
+
+    #[test]
+    fn test_serde() {
+        let unit: Unit = serde_json::from_str(r#""tablespoon""#).unwrap();
+        assert_eq!(unit, Unit::Tablespoon);
+        assert_eq!(serde_json::to_string(&unit).unwrap(), r#""Tbsp""#);
+        assert!(serde_json::from_str::<Unit>(r#""handful""#).is_err());
+    }
+}
@@ -0,0 +1,723 @@
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::crud::ingredient::{self, Ingredient};
+use crate::crud::seed::Provenance;
+use crate::crud::unit_conversion::{self, UnitConversion};
+use crate::error::{Error, Result};
+use crate::nutrition::NutritionFacts;
+use crate::unit::{Unit, UnitKind};
+
+// `Ingredient.source` of imported foods
+pub const SOURCE: &str = "usda_fdc";
+
+// FoodData Central nutrient numbers and units mapped onto `NutritionFacts`
+// fields. When a food has several numbers for one field the earlier row wins,
+// Foundation foods often only have the Atwater energy values
+const NUTRIENT_NUMBERS: &[(&str, &str, &str)] = &[
+    ("208", "KCAL", "calories_kcal"),
+    ("957", "KCAL", "calories_kcal"),
+    ("958", "KCAL", "calories_kcal"),
+    ("203", "G", "protein_g"),
+    ("205", "G", "carbohydrates_g"),
+    ("269", "G", "sugar_g"),
+    ("269.3", "G", "sugar_g"),
+    ("204", "G", "fat_g"),
+    ("606", "G", "saturated_fat_g"),
+    ("291", "G", "fiber_g"),
+    ("306", "MG", "potassium_mg"),
+    ("307", "MG", "sodium_mg"),
+    ("601", "MG", "cholesterol_mg"),
+    ("539", "G", "added_sugar_g"),
+    ("605", "G", "trans_fat_g"),
+    ("303", "MG", "iron_mg"),
+    ("301", "MG", "calcium_mg"),
+    ("304", "MG", "magnesium_mg"),
+    ("401", "MG", "vitamin_c_mg"),
+    ("328", "UG", "vitamin_d_mcg"),
+];
+
+// A food from the bundle, nutrients are per 100 g
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FdcFood {
+    pub fdc_id: i64,
+    pub data_type: String,
+    pub description: String,
+    pub nutrients: NutritionFacts,
+    pub portions: Vec<FdcPortion>,
+}
+
+// `amount` `unit` of the food weighs `gram_weight` g
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FdcPortion {
+    pub amount: f32,
+    pub unit: Unit,
+    pub gram_weight: f32,
+}
+
+impl FdcFood {
+    pub fn to_ingredient(&self, name: &str) -> Ingredient {
+        Ingredient {
+            id: None,
+            name: name.to_string(),
+            unit: Unit::Gram,
+            amount: 100.0,
+            nutrients: self.nutrients.clone(),
+            grams_per_unit: None,
+            source: Some(SOURCE.to_string()),
+            fdc_id: Some(self.fdc_id),
+        }
+    }
+
+    // Grams per unit, one per unit in the order of the portions. Mass units
+    // are left out, they convert to grams anyway
+    pub fn grams_per_unit(&self) -> Vec<(Unit, f32)> {
+        let mut conversions: Vec<(Unit, f32)> = Vec::new();
+        for portion in &self.portions {
+            if portion.unit.kind() == UnitKind::Mass
+                || portion.amount <= 0.0
+                || portion.gram_weight <= 0.0
+                || conversions.iter().any(|(unit, _)| *unit == portion.unit)
+            {
+                continue;
+            }
+            conversions.push((portion.unit, portion.gram_weight / portion.amount));
+        }
+        conversions
+    }
+}
+
+// Lowercased description, "Butter, salted" becomes "butter, salted"
+pub fn ingredient_name(description: &str) -> String {
+    description.trim().to_lowercase()
+}
+
+// Read food.csv, nutrient.csv, food_nutrient.csv and food_portion.csv from a
+// FoodData Central CSV download, plus measure_unit.csv if it is there. Only
+// the foods in `fdc_ids` are kept, all of them if it is empty
+pub fn read_bundle(dir: &Path, fdc_ids: &[i64]) -> Result<Vec<FdcFood>> {
+    let wanted = |fdc_id: i64| fdc_ids.is_empty() || fdc_ids.contains(&fdc_id);
+
+    let mut foods = Vec::new();
+    let mut index = HashMap::new();
+    let food_csv = Csv::read(dir, "food.csv")?;
+    for record in food_csv.records() {
+        let fdc_id = record.parse("fdc_id")?;
+        if !wanted(fdc_id) {
+            continue;
+        }
+        index.insert(fdc_id, foods.len());
+        foods.push(FdcFood {
+            fdc_id,
+            data_type: record.get("data_type")?.to_string(),
+            description: record.get("description")?.to_string(),
+            nutrients: NutritionFacts::default(),
+            portions: Vec::new(),
+        });
+    }
+    if let Some(missing) = fdc_ids.iter().find(|id| !index.contains_key(id)) {
+        return Err(Error::NotFound(format!(
+            "Food {} is not in {}",
+            missing,
+            dir.join("food.csv").display()
+        )));
+    }
+
+    // nutrient id -> (field, priority)
+    let mut fields = HashMap::new();
+    for record in Csv::read(dir, "nutrient.csv")?.records() {
+        let number = record.get("nutrient_nbr")?;
+        let unit_name = record.get("unit_name")?;
+        let mapped = NUTRIENT_NUMBERS.iter().position(|(nbr, unit, _)| {
+            number_matches(nbr, number) && unit.eq_ignore_ascii_case(unit_name)
+        });
+        if let Some(priority) = mapped {
+            let id: i64 = record.parse("id")?;
+            fields.insert(id, (NUTRIENT_NUMBERS[priority].2, priority));
+        }
+    }
+
+    // (food, field) -> priority of the value that was used
+    let mut used = HashMap::new();
+    for record in Csv::read(dir, "food_nutrient.csv")?.records() {
+        let food = match index.get(&record.parse::<i64>("fdc_id")?) {
+            Some(&food) => food,
+            None => continue,
+        };
+        let (field, priority) = match fields.get(&record.parse::<i64>("nutrient_id")?) {
+            Some(&mapped) => mapped,
+            None => continue,
+        };
+        let amount: f32 = record.parse("amount")?;
+        if used
+            .get(&(food, field))
+            .is_some_and(|&used| used < priority)
+        {
+            continue;
+        }
+        used.insert((food, field), priority);
+        foods[food].nutrients.set(field, Some(amount));
+    }
+
+    let mut measure_units = HashMap::new();
+    if dir.join("measure_unit.csv").exists() {
+        for record in Csv::read(dir, "measure_unit.csv")?.records() {
+            let id: i64 = record.parse("id")?;
+            measure_units.insert(id, record.get("name")?.to_string());
+        }
+    }
+
+    for record in Csv::read(dir, "food_portion.csv")?.records() {
+        let food = match index.get(&record.parse::<i64>("fdc_id")?) {
+            Some(&food) => food,
+            None => continue,
+        };
+        let measure_unit = record
+            .parse_optional::<i64>("measure_unit_id")?
+            .and_then(|id| measure_units.get(&id))
+            .map(String::as_str)
+            .unwrap_or_default();
+        // SR Legacy puts the unit in the modifier, Foundation foods use
+        // measure units, survey foods only have a description like "1 cup"
+        let unit = [
+            measure_unit,
+            record.get("modifier")?,
+            record.get("portion_description")?,
+        ]
+        .into_iter()
+        .find_map(portion_unit);
+        if let Some(unit) = unit {
+            foods[food].portions.push(FdcPortion {
+                amount: record.parse_optional("amount")?.unwrap_or(1.0),
+                unit,
+                gram_weight: record.parse("gram_weight")?,
+            });
+        }
+    }
+
+    Ok(foods)
+}
+
+// "208" matches "208" and "208.0"
+fn number_matches(expected: &str, number: &str) -> bool {
+    match (expected.parse::<f64>(), number.trim().parse::<f64>()) {
+        (Ok(expected), Ok(number)) => expected == number,
+        _ => false,
+    }
+}
+
+// The unit in "cup", "tbsp", "cup, chopped" or "2 tablespoons, dry", None
+// for things like "stick" or "large"
+fn portion_unit(text: &str) -> Option<Unit> {
+    let text = text.split([',', '(']).next().unwrap_or_default();
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .skip_while(|word| {
+            word.chars()
+                .all(|c| c.is_ascii_digit() || c == '/' || c == '.')
+        })
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    let unit = words.join(" ").parse().or_else(|_| words[0].parse()).ok()?;
+    (unit != Unit::Gram).then_some(unit)
+}
+
+// What importing a food would do
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ImportAction {
+    Add,
+    Update {
+        ingredient_id: i64,
+    },
+    Skip {
+        ingredient_id: Option<i64>,
+        reason: String,
+    },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SimilarIngredient {
+    pub id: i64,
+    pub name: String,
+    pub score: f32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportPreview {
+    pub fdc_id: i64,
+    pub description: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub action: ImportAction,
+    // existing ingredients with similar names, best first, to spot foods
+    // that are already in the catalog under another name
+    pub similar: Vec<SimilarIngredient>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ImportOptions {
+    // ingredient names to use instead of the lowercased description
+    pub names: HashMap<i64, String>,
+    // overwrite ingredients with the same name that were not imported
+    pub replace: bool,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub added: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub conversions: usize,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} skipped, {} unit conversions",
+            self.added, self.updated, self.skipped, self.conversions
+        )
+    }
+}
+
+// Match the foods against the catalog without changing anything. A food
+// updates the ingredient imported with the same fdc_id, is added if its name
+// is free, and is skipped if the name belongs to another ingredient (unless
+// `options.replace`) or the imported row has since been edited by the user
+pub async fn preview(
+    pool: &Pool<Sqlite>,
+    foods: &[FdcFood],
+    options: &ImportOptions,
+) -> Result<Vec<ImportPreview>> {
+    let existing: Vec<(i64, String, Option<i64>, Provenance)> =
+        sqlx::query_as("SELECT id, name, fdc_id, provenance FROM ingredients")
+            .fetch_all(pool)
+            .await?;
+
+    let mut claimed: HashMap<String, i64> = HashMap::new();
+    let mut previews = Vec::new();
+    for food in foods {
+        let name = options
+            .names
+            .get(&food.fdc_id)
+            .cloned()
+            .unwrap_or_else(|| ingredient_name(&food.description));
+        let by_fdc_id = existing.iter().find(|row| row.2 == Some(food.fdc_id));
+        let by_name = existing.iter().find(|row| row.1 == name);
+
+        let skip = |ingredient_id, reason: String| ImportAction::Skip {
+            ingredient_id,
+            reason,
+        };
+        let action = if let Some(other) = claimed.get(&name) {
+            skip(
+                None,
+                format!("name '{}' is also used by food {}", name, other),
+            )
+        } else {
+            match (by_fdc_id, by_name) {
+                (Some((id, ..)), _) if by_fdc_id.map(|row| &row.3) == Some(&Provenance::User) => {
+                    skip(
+                        Some(*id),
+                        "edited by user since it was imported".to_string(),
+                    )
+                }
+                (Some((id, ..)), Some((other, ..))) if id != other => skip(
+                    Some(*id),
+                    format!("name '{}' is taken by ingredient {}", name, other),
+                ),
+                (Some((id, ..)), _) => ImportAction::Update { ingredient_id: *id },
+                (None, Some((id, ..))) if options.replace => {
+                    ImportAction::Update { ingredient_id: *id }
+                }
+                (None, Some((id, ..))) => skip(
+                    Some(*id),
+                    format!("ingredient {} is already called '{}'", id, name),
+                ),
+                (None, None) => ImportAction::Add,
+            }
+        };
+        if !matches!(action, ImportAction::Skip { .. }) {
+            claimed.insert(name.clone(), food.fdc_id);
+        }
+
+        let target = match &action {
+            ImportAction::Add => None,
+            ImportAction::Update { ingredient_id } => Some(*ingredient_id),
+            ImportAction::Skip { ingredient_id, .. } => *ingredient_id,
+        };
+        let mut similar: Vec<SimilarIngredient> = existing
+            .iter()
+            .filter(|(id, ..)| Some(*id) != target)
+            .map(|(id, existing_name, ..)| SimilarIngredient {
+                id: *id,
+                name: existing_name.clone(),
+                score: similarity(&name, existing_name),
+            })
+            .filter(|candidate| candidate.score >= 0.5)
+            .collect();
+        similar.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.id.cmp(&b.id)));
+        similar.truncate(3);
+
+        previews.push(ImportPreview {
+            fdc_id: food.fdc_id,
+            description: food.description.clone(),
+            name,
+            action,
+            similar,
+        });
+    }
+
+    Ok(previews)
+}
+
+// Add or update the foods as ingredients, see `preview`, with their portions
+// as unit conversions to grams
+pub async fn import(
+    pool: &Pool<Sqlite>,
+    foods: &[FdcFood],
+    options: &ImportOptions,
+) -> Result<ImportReport> {
+    let mut report = ImportReport::default();
+    for (food, preview) in foods.iter().zip(preview(pool, foods, options).await?) {
+        let ingredient = food.to_ingredient(&preview.name);
+        let ingredient_id = match preview.action {
+            ImportAction::Add => {
+                report.added += 1;
+                ingredient::create_imported(pool, &ingredient).await?
+            }
+            ImportAction::Update { ingredient_id } => {
+                ingredient::update_imported(pool, ingredient_id, &ingredient).await?;
+                report.updated += 1;
+                ingredient_id
+            }
+            ImportAction::Skip { .. } => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        for (unit, grams) in food.grams_per_unit() {
+            unit_conversion::create(
+                pool,
+                &UnitConversion {
+                    id: None,
+                    ingredient_id,
+                    from_unit: unit,
+                    to_unit: Unit::Gram,
+                    conversion_factor: grams,
+                },
+            )
+            .await?;
+            report.conversions += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+// Dice coefficient of the words in both names, 1.0 for the same words
+fn similarity(a: &str, b: &str) -> f32 {
+    let words = |text: &str| -> HashSet<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let (a, b) = (words(a), words(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f32 / (a.len() + b.len()) as f32
+}
+
+// A CSV file with a header row. FoodData Central quotes every field and
+// doubles quotes inside them
+struct Csv {
+    file_name: String,
+    columns: HashMap<String, usize>,
+    // (line number, fields)
+    rows: Vec<(usize, Vec<String>)>,
+}
+
+struct Record<'a> {
+    csv: &'a Csv,
+    line: usize,
+    fields: &'a [String],
+}
+
+impl Csv {
+    fn read(dir: &Path, file_name: &str) -> Result<Csv> {
+        let path = dir.join(file_name);
+        let content = fs::read_to_string(&path)
+            .map_err(|err| Error::Validation(format!("Can't read {}: {}", path.display(), err)))?;
+        let mut rows = parse_csv(&content).into_iter();
+        let header = match rows.next() {
+            Some((_, header)) => header,
+            None => return Err(Error::Validation(format!("{} is empty", file_name))),
+        };
+        let columns = header
+            .into_iter()
+            .enumerate()
+            // a byte order mark would stick to the first column name
+            .map(|(i, name)| (name.trim_start_matches('\u{feff}').to_string(), i))
+            .collect();
+
+        Ok(Csv {
+            file_name: file_name.to_string(),
+            columns,
+            rows: rows.collect(),
+        })
+    }
+
+    fn records(&self) -> impl Iterator<Item = Record<'_>> {
+        self.rows.iter().map(move |(line, fields)| Record {
+            csv: self,
+            line: *line,
+            fields,
+        })
+    }
+}
+
+impl Record<'_> {
+    fn get(&self, column: &str) -> Result<&str> {
+        let index = self.csv.columns.get(column).ok_or_else(|| {
+            Error::Validation(format!(
+                "{}: missing column '{}'",
+                self.csv.file_name, column
+            ))
+        })?;
+        Ok(self
+            .fields
+            .get(*index)
+            .map(|field| field.trim())
+            .unwrap_or_default())
+    }
+
+    fn parse<T: FromStr>(&self, column: &str) -> Result<T> {
+        self.parse_optional(column)?.ok_or_else(|| {
+            Error::Validation(format!(
+                "{}:{}: {} is empty",
+                self.csv.file_name, self.line, column
+            ))
+        })
+    }
+
+    // None for an empty field
+    fn parse_optional<T: FromStr>(&self, column: &str) -> Result<Option<T>> {
+        let value = self.get(column)?;
+        if value.is_empty() {
+            return Ok(None);
+        }
+        value.parse().map(Some).map_err(|_| {
+            Error::Validation(format!(
+                "{}:{}: invalid {} '{}'",
+                self.csv.file_name, self.line, column, value
+            ))
+        })
+    }
+}
+
+// Records with the line they start on. Quoted fields may contain commas,
+// newlines and "" for a quote
+fn parse_csv(content: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                fields.push(std::mem::take(&mut field));
+                if fields.iter().any(|field| !field.is_empty()) {
+                    records.push((start, std::mem::take(&mut fields)));
+                }
+                fields.clear();
+                line += 1;
+                start = line;
+            }
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+    fields.push(field);
+    if fields.iter().any(|field| !field.is_empty()) {
+        records.push((start, fields));
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crud::{self, get_connection_pool, seed, unit_conversion};
+    use crate::usda::*;
+    use tempfile::NamedTempFile;
+
+    const FIXTURES: &str = "tests/fixtures/usda";
+
+    #[test]
+    fn test_parse_csv() {
+        let records = parse_csv("\"a\",\"b\"\r\n\"1, 2\",\"say \"\"hi\"\"\"\n\n3,\"x\ny\"\n4,");
+        assert_eq!(
+            records,
+            vec![
+                (1, vec!["a".to_string(), "b".to_string()]),
+                (2, vec!["1, 2".to_string(), "say \"hi\"".to_string()]),
+                (4, vec!["3".to_string(), "x\ny".to_string()]),
+                (6, vec!["4".to_string(), "".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_portion_unit() {
+        assert_eq!(portion_unit("cup"), Some(Unit::Cup));
+        assert_eq!(portion_unit("cup, chopped"), Some(Unit::Cup));
+        assert_eq!(portion_unit("2 tablespoons, dry"), Some(Unit::Tablespoon));
+        assert_eq!(portion_unit("1 fl oz"), Some(Unit::FluidOunce));
+        assert_eq!(portion_unit("pat (1\" sq, 1/3\" high)"), None);
+        assert_eq!(portion_unit("large"), None);
+        assert_eq!(portion_unit(""), None);
+    }
+
+    #[test]
+    fn test_read_bundle() {
+        let foods = read_bundle(Path::new(FIXTURES), &[]).unwrap();
+        assert_eq!(foods.len(), 3);
+
+        let butter = &foods[0];
+        assert_eq!(butter.fdc_id, 173430);
+        assert_eq!(butter.description, "Butter, salted");
+        assert_eq!(butter.nutrients.calories_kcal, Some(717.0));
+        assert_eq!(butter.nutrients.sodium_mg, Some(643.0));
+        assert_eq!(butter.nutrients.trans_fat_g, Some(3.278));
+        // not in the bundle, so unknown rather than 0
+        assert_eq!(butter.nutrients.added_sugar_g, None);
+        // the pat and the stick have no unit
+        assert_eq!(
+            butter.grams_per_unit(),
+            vec![(Unit::Tablespoon, 14.2), (Unit::Cup, 227.0)]
+        );
+
+        // cup from measure_unit.csv, leaf from the modifier, oz is a mass unit
+        let spinach = &foods[1];
+        assert_eq!(
+            spinach.grams_per_unit(),
+            vec![(Unit::Cup, 30.0), (Unit::Leaf, 10.0)]
+        );
+        assert_eq!(spinach.nutrients.vitamin_c_mg, Some(28.1));
+
+        // 2 tablespoons weigh 23 g
+        let rice = &foods[2];
+        assert_eq!(
+            rice.grams_per_unit(),
+            vec![(Unit::Cup, 185.0), (Unit::Tablespoon, 11.5)]
+        );
+        assert_eq!(rice.nutrients.vitamin_d_mcg, None);
+
+        let foods = read_bundle(Path::new(FIXTURES), &[168462]).unwrap();
+        assert_eq!(foods.len(), 1);
+        assert!(read_bundle(Path::new(FIXTURES), &[1]).is_err());
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("butter", "butter"), 1.0);
+        assert!(similarity("butter, salted", "butter") > 0.6);
+        assert!(similarity("butter, salted", "peanut butter") < 0.6);
+        assert_eq!(similarity("spinach, raw", "rice"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_import() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_filename = temp_file.path();
+        let pool = get_connection_pool(temp_filename.to_str().unwrap())
+            .await
+            .unwrap();
+
+        crud::create_tables(&pool).await.unwrap();
+        crud::populate_tables(&pool).await.unwrap();
+
+        let foods = read_bundle(Path::new(FIXTURES), &[]).unwrap();
+        let butter = ingredient::read_by_name(&pool, "butter")
+            .await
+            .unwrap()
+            .unwrap();
+        let mut options = ImportOptions::default();
+        // spinach goes in as "spinach", which is in the seed data
+        options.names.insert(168462, "spinach".to_string());
+
+        let previews = preview(&pool, &foods, &options).await.unwrap();
+        assert_eq!(previews[0].name, "butter, salted");
+        assert_eq!(previews[0].action, ImportAction::Add);
+        assert_eq!(previews[0].similar[0].name, "butter");
+        assert_eq!(previews[0].similar[0].id, butter.id.unwrap());
+        let spinach_id = match &previews[1].action {
+            ImportAction::Skip {
+                ingredient_id: Some(id),
+                ..
+            } => *id,
+            action => panic!("expected a skip, got {:?}", action),
+        };
+
+        let report = import(&pool, &foods, &options).await.unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                added: 2,
+                updated: 0,
+                skipped: 1,
+                conversions: 4,
+            }
+        );
+        let imported = ingredient::read_by_name(&pool, "butter, salted")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(imported.source.as_deref(), Some(SOURCE));
+        assert_eq!(imported.fdc_id, Some(173430));
+        assert_eq!(imported.unit, Unit::Gram);
+        assert_eq!(imported.nutrients.fat_g, Some(81.11));
+        let converter = unit_conversion::read_converter(&pool, &imported)
+            .await
+            .unwrap();
+        let grams = converter.convert(1.0, Unit::Cup, Unit::Gram).unwrap();
+        assert!((grams - 227.0).abs() < 1.0, "{}", grams);
+
+        // importing again updates by fdc_id, --replace takes over spinach
+        options.replace = true;
+        let report = import(&pool, &foods, &options).await.unwrap();
+        assert_eq!((report.added, report.updated, report.skipped), (0, 3, 0));
+        let spinach = ingredient::read_one(&pool, spinach_id).await.unwrap();
+        assert_eq!(spinach.fdc_id, Some(168462));
+        assert_eq!(spinach.nutrients.iron_mg, Some(2.71));
+
+        // and seeding leaves imported rows alone
+        let report = seed::run(&pool, Path::new("data")).await.unwrap();
+        assert_eq!(report.updated, 0);
+        let spinach = ingredient::read_one(&pool, spinach_id).await.unwrap();
+        assert_eq!(spinach.fdc_id, Some(168462));
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::crud::ingredient::Ingredient;
+use crate::crud::tag::Tag;
+use crate::error::Result;
+use crate::unit::Unit;
+
+// A problem in a seed data file, displayed as `path:line:column: message`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+// Check every file under `<data_dir>/ingredients` and `<data_dir>/tags.json`.
+// Only unreadable files are errors, everything else is a diagnostic
+pub fn check_data_dir(data_dir: &Path) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let mut seen = HashMap::new();
+
+    let mut file_paths = crate::crud::get_json_files(data_dir.join("ingredients"))?;
+    file_paths.sort();
+    for file_path in file_paths {
+        let content = fs::read_to_string(&file_path)?;
+        diagnostics.extend(check_ingredients(&file_path, &content, &mut seen));
+    }
+
+    let file_path = data_dir.join("tags.json");
+    let content = fs::read_to_string(&file_path)?;
+    diagnostics.extend(check_tags(&file_path, &content));
+
+    Ok(diagnostics)
+}
+
+// `seen` maps ingredient names to where they were first defined, so
+// duplicates are found across files
+pub fn check_ingredients(
+    path: &Path,
+    content: &str,
+    seen: &mut HashMap<String, Diagnostic>,
+) -> Vec<Diagnostic> {
+    let file = SourceFile { path, content };
+    let elements = match file.elements() {
+        Ok(elements) => elements,
+        Err(diagnostic) => return vec![diagnostic],
+    };
+
+    let mut diagnostics = Vec::new();
+    for element in elements {
+        let ingredient: Ingredient = match serde_json::from_str(element.source(content)) {
+            Ok(ingredient) => ingredient,
+            Err(err) => {
+                diagnostics.push(file.json_error(element.start, &err));
+                continue;
+            }
+        };
+        let at = |key: &str, message: String| {
+            file.diagnostic(
+                element.keys.get(key).copied().unwrap_or(element.start),
+                message,
+            )
+        };
+
+        // unknown nutrient values are allowed, only the known ones are checked
+        let nutrients = &ingredient.nutrients;
+        let mut values = vec![("amount", Some(ingredient.amount))];
+        values.extend(nutrients.values());
+        values.push(("grams_per_unit", ingredient.grams_per_unit));
+        for (key, value) in values {
+            match value {
+                Some(value) if !value.is_finite() || value < 0.0 => diagnostics.push(at(
+                    key,
+                    format!("{} must not be negative, got {}", key, value),
+                )),
+                _ => {}
+            }
+        }
+        if ingredient.amount == 0.0 {
+            diagnostics.push(at("amount", "amount must be greater than 0".to_string()));
+        }
+        if ingredient.grams_per_unit == Some(0.0) {
+            diagnostics.push(at(
+                "grams_per_unit",
+                "grams_per_unit must be greater than 0".to_string(),
+            ));
+        }
+
+        let parts = [
+            (
+                "sugar_g",
+                nutrients.sugar_g,
+                "carbohydrates_g",
+                nutrients.carbohydrates_g,
+            ),
+            (
+                "fiber_g",
+                nutrients.fiber_g,
+                "carbohydrates_g",
+                nutrients.carbohydrates_g,
+            ),
+            (
+                "added_sugar_g",
+                nutrients.added_sugar_g,
+                "sugar_g",
+                nutrients.sugar_g,
+            ),
+            (
+                "saturated_fat_g",
+                nutrients.saturated_fat_g,
+                "fat_g",
+                nutrients.fat_g,
+            ),
+            (
+                "trans_fat_g",
+                nutrients.trans_fat_g,
+                "fat_g",
+                nutrients.fat_g,
+            ),
+        ];
+        for (part, part_value, total, total_value) in parts {
+            let (Some(part_value), Some(total_value)) = (part_value, total_value) else {
+                continue;
+            };
+            if part_value > total_value {
+                diagnostics.push(at(
+                    part,
+                    format!(
+                        "{} ({}) is greater than {} ({})",
+                        part, part_value, total, total_value
+                    ),
+                ));
+            }
+        }
+
+        // protein, carbohydrates and fat can't weigh more than the food itself
+        if let Some(weight_g) = ingredient.unit.convert(ingredient.amount, Unit::Gram) {
+            let macros_g = [
+                nutrients.protein_g,
+                nutrients.carbohydrates_g,
+                nutrients.fat_g,
+            ]
+            .iter()
+            .flatten()
+            .sum::<f32>();
+            if macros_g > weight_g * 1.05 {
+                diagnostics.push(at(
+                    "amount",
+                    format!(
+                        "protein, carbohydrates and fat add up to {:.1} g but {} {} weighs {:.1} g",
+                        macros_g, ingredient.amount, ingredient.unit, weight_g
+                    ),
+                ));
+            }
+        }
+
+        let name_at = at("name", String::new());
+        match seen.get(&ingredient.name) {
+            Some(first) => diagnostics.push(Diagnostic {
+                message: format!(
+                    "Duplicate ingredient '{}', first defined at {}:{}:{}",
+                    ingredient.name,
+                    first.path.display(),
+                    first.line,
+                    first.column
+                ),
+                ..name_at
+            }),
+            None => {
+                seen.insert(ingredient.name.clone(), name_at);
+            }
+        }
+    }
+
+    diagnostics
+}
+
+pub fn check_tags(path: &Path, content: &str) -> Vec<Diagnostic> {
+    let file = SourceFile { path, content };
+    let elements = match file.elements() {
+        Ok(elements) => elements,
+        Err(diagnostic) => return vec![diagnostic],
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut seen: HashMap<String, Diagnostic> = HashMap::new();
+    for element in elements {
+        let tag: Tag = match serde_json::from_str(element.source(content)) {
+            Ok(tag) => tag,
+            Err(err) => {
+                diagnostics.push(file.json_error(element.start, &err));
+                continue;
+            }
+        };
+        let name_at = file.diagnostic(
+            element.keys.get("name").copied().unwrap_or(element.start),
+            String::new(),
+        );
+        if tag.name.trim().is_empty() {
+            diagnostics.push(Diagnostic {
+                message: "Tag name must not be empty".to_string(),
+                ..name_at
+            });
+        } else if let Some(first) = seen.get(&tag.name) {
+            diagnostics.push(Diagnostic {
+                message: format!(
+                    "Duplicate tag '{}', first defined at line {}",
+                    tag.name, first.line
+                ),
+                ..name_at
+            });
+        } else {
+            seen.insert(tag.name, name_at);
+        }
+    }
+
+    diagnostics
+}
+
+// serde_json appends its own position, which is replaced by ours
+fn json_message(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+struct SourceFile<'a> {
+    path: &'a Path,
+    content: &'a str,
+}
+
+// One value of the top-level array, with byte offsets of its keys
+struct Element {
+    start: usize,
+    end: usize,
+    keys: HashMap<String, usize>,
+}
+
+impl Element {
+    fn source<'a>(&self, content: &'a str) -> &'a str {
+        &content[self.start..self.end]
+    }
+}
+
+impl<'a> SourceFile<'a> {
+    fn diagnostic(&self, offset: usize, message: String) -> Diagnostic {
+        let before = &self.content[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Diagnostic {
+            path: self.path.to_path_buf(),
+            line,
+            column: before[line_start..].chars().count() + 1,
+            message,
+        }
+    }
+
+    // serde_json positions are relative to the element that was parsed
+    fn json_error(&self, offset: usize, err: &serde_json::Error) -> Diagnostic {
+        let element = self.diagnostic(offset, String::new());
+        let (line, column) = if err.line() <= 1 {
+            (
+                element.line,
+                element.column + err.column().saturating_sub(1),
+            )
+        } else {
+            (element.line + err.line() - 1, err.column())
+        };
+        Diagnostic {
+            line,
+            column,
+            message: json_message(err),
+            ..element
+        }
+    }
+
+    // serde_json doesn't keep positions for parsed values, so find the
+    // elements of the top-level array and their keys by scanning the text
+    fn elements(&self) -> std::result::Result<Vec<Element>, Diagnostic> {
+        let value: serde_json::Value =
+            serde_json::from_str(self.content).map_err(|err| Diagnostic {
+                path: self.path.to_path_buf(),
+                line: err.line(),
+                column: err.column(),
+                message: json_message(&err),
+            })?;
+        if !value.is_array() {
+            let start = self.content.len() - self.content.trim_start().len();
+            return Err(self.diagnostic(start, "Expected a JSON array".to_string()));
+        }
+
+        let mut elements = Vec::new();
+        let mut current: Option<Element> = None;
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut string_start = 0;
+        // the last string directly inside an element, a key if `:` follows
+        let mut last_string = None;
+        for (i, byte) in self.content.bytes().enumerate() {
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if byte == b'\\' {
+                    escaped = true;
+                } else if byte == b'"' {
+                    in_string = false;
+                    if depth == 2 {
+                        last_string = Some((string_start, i + 1));
+                    }
+                }
+                continue;
+            }
+            if byte.is_ascii_whitespace() {
+                continue;
+            }
+            if depth == 1 && current.is_none() && byte != b',' && byte != b']' {
+                current = Some(Element {
+                    start: i,
+                    end: i,
+                    keys: HashMap::new(),
+                });
+            }
+            match byte {
+                b'"' => {
+                    in_string = true;
+                    string_start = i;
+                }
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth -= 1,
+                b':' if depth == 2 => {
+                    if let (Some((start, end)), Some(element)) = (last_string.take(), &mut current)
+                    {
+                        if let Ok(key) = serde_json::from_str::<String>(&self.content[start..end]) {
+                            element.keys.insert(key, start);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if (byte == b',' && depth == 1) || (byte == b']' && depth == 0) {
+                if let Some(mut element) = current.take() {
+                    element.end = i;
+                    elements.push(element);
+                }
+            }
+        }
+
+        Ok(elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::*;
+
+    const OATS: &str = r#"{"name": "oats", "amount": 1, "unit": "cup", "grams_per_unit": 80,
+        "calories_kcal": 300, "protein_g": 10, "carbohydrates_g": 54, "sugar_g": 1,
+        "fat_g": 5, "saturated_fat_g": 1, "fiber_g": 8, "potassium_mg": 300,
+        "sodium_mg": 5, "cholesterol_mg": 0}"#;
+
+    fn check(content: &str) -> Vec<String> {
+        check_ingredients(Path::new("grains.json"), content, &mut HashMap::new())
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_repo_data_is_valid() {
+        let diagnostics = check_data_dir(Path::new("data")).unwrap();
+        assert!(diagnostics.is_empty(), "{:#?}", diagnostics);
+    }
+
+    #[test]
+    fn test_valid_file() {
+        assert!(check(&format!("[\n{}\n]", OATS)).is_empty());
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        assert_eq!(
+            check("[\n  {\"name\": \"oats\",}\n]"),
+            vec!["grains.json:2:19: trailing comma"]
+        );
+        assert_eq!(check("{}"), vec!["grains.json:1:1: Expected a JSON array"]);
+    }
+
+    #[test]
+    fn test_unknown_unit() {
+        let content = format!("[\n{}\n]", OATS.replace("\"cup\"", "\"bushel\""));
+        let diagnostics = check(&content);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].starts_with("grains.json:2:"));
+        assert!(diagnostics[0].contains("Unknown unit: 'bushel'"));
+    }
+
+    #[test]
+    fn test_implausible_values() {
+        let content = format!(
+            "[\n{}\n]",
+            OATS.replace("\"sugar_g\": 1", "\"sugar_g\": 60")
+                .replace("\"saturated_fat_g\": 1", "\"saturated_fat_g\": 6")
+                .replace("\"sodium_mg\": 5", "\"sodium_mg\": -5")
+        );
+        assert_eq!(
+            check(&content),
+            vec![
+                "grains.json:5:9: sodium_mg must not be negative, got -5",
+                "grains.json:3:71: sugar_g (60) is greater than carbohydrates_g (54)",
+                "grains.json:4:21: saturated_fat_g (6) is greater than fat_g (5)",
+            ]
+        );
+
+        let content = format!(
+            "[\n{}\n]",
+            OATS.replace(
+                "\"amount\": 1, \"unit\": \"cup\"",
+                "\"amount\": 50, \"unit\": \"g\"",
+            )
+        );
+        assert_eq!(
+            check(&content),
+            vec!["grains.json:2:18: protein, carbohydrates and fat add up to 69.0 g but 50 g weighs 50.0 g"]
+        );
+    }
+
+    #[test]
+    fn test_optional_nutrients() {
+        // unknown values may be left out, micronutrients are checked like the rest
+        let content = format!(
+            "[\n{}\n]",
+            OATS.replace("\"sodium_mg\": 5, ", "").replace(
+                "\"cholesterol_mg\": 0",
+                "\"iron_mg\": -1, \"added_sugar_g\": 2",
+            )
+        );
+        assert_eq!(
+            check(&content),
+            vec![
+                "grains.json:5:9: iron_mg must not be negative, got -1",
+                "grains.json:5:24: added_sugar_g (2) is greater than sugar_g (1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicates_across_files() {
+        let mut seen = HashMap::new();
+        let first = check_ingredients(Path::new("a.json"), &format!("[{}]", OATS), &mut seen);
+        assert!(first.is_empty());
+        let second =
+            check_ingredients(Path::new("b.json"), &format!("[\n  {}\n]", OATS), &mut seen);
+        assert_eq!(
+            second.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+            vec!["b.json:2:4: Duplicate ingredient 'oats', first defined at a.json:1:3"]
+        );
+
+        let tags = check_tags(
+            Path::new("tags.json"),
+            "[\n  {\"name\": \"vegan\"},\n  {\"name\": \"vegan\"},\n  {\"name\": \"\"}\n]",
+        );
+        assert_eq!(
+            tags.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+            vec![
+                "tags.json:3:4: Duplicate tag 'vegan', first defined at line 2",
+                "tags.json:4:4: Tag name must not be empty",
+            ]
+        );
+    }
+}